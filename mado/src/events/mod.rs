@@ -1,21 +1,85 @@
 use schemars::JsonSchema;
 use serde::Serialize;
+use serde_json::Value;
 
-use crate::services::music_player::MusicPlayerState;
+use crate::{
+    operations::{OperationFinished, OperationProgress},
+    services::{
+        disks::DiskChange, messaging::Message, music_player::MusicPlayerState,
+        music_sources::MusicSourceChange, network::NetworkStatus, power::PowerChange,
+        sensors::SensorAlert, system_metrics::SystemMetrics, variable_watch::VariableChanged,
+    },
+};
 
-#[derive(Serialize, JsonSchema)]
+/// Events are pushed from the host to the page through `window.ipcEvent(event)`.
+#[derive(Clone, Serialize, JsonSchema)]
 #[serde(tag = "kind", content = "value")]
 pub enum Event {
+    /// Raised by `MusicPlayerService` whenever the player state changes
+    /// (track, position, volume, status...), while the page is subscribed with
+    /// `mado/subscribe_events`. Calling `get_data` also raises it if the state
+    /// changed since the last poll.
     MusicUpdate(MusicPlayerState),
+    /// Raised by any service when something goes wrong outside of a command call.
     ERROR(ErrorData),
+    /// Raised by a long-running command while it works, with the operation ID
+    /// returned when the command was called.
+    OperationProgress(OperationProgress),
+    /// Raised once when a long-running command completes, fails or is cancelled
+    /// through `mado/cancel_operation`.
+    OperationFinished(OperationFinished),
+    /// Message sent to the page by the host application, like a Rainmeter skin
+    /// running `[!CommandMeasure Shigure "Emit hover left"]`.
+    Custom(CustomEvent),
+    /// Raised when a variable watched with `host/watch_variable` changes,
+    /// checked every time the host updates.
+    VariableChanged(VariableChanged),
+    /// Message posted with `messages/post` on a channel the page subscribed to
+    /// with `messages/subscribe`, possibly by another page.
+    Message(Message),
+    /// Raised by `SystemMetricsService` at a regular interval, while the page
+    /// is subscribed with `mado/subscribe_events`.
+    SystemMetricsUpdate(SystemMetrics),
+    /// Raised by `DiskService` when a filesystem is mounted or unmounted, or
+    /// when its usage crosses the threshold set with `set_usage_threshold`,
+    /// while the page is subscribed with `mado/subscribe_events`.
+    DiskChanged(DiskChange),
+    /// Raised by `NetworkService` at a regular interval with the interfaces
+    /// and their rates, while the page is subscribed with `mado/subscribe_events`.
+    NetworkUpdate(NetworkStatus),
+    /// Raised by `SensorsService` when a sensor reaches its critical threshold
+    /// and when it goes back under, while the page is subscribed with
+    /// `mado/subscribe_events`.
+    SensorAlert(SensorAlert),
+    /// Raised by `PowerService` when the AC adapter is plugged or unplugged,
+    /// or when a discharging battery reaches the threshold set with
+    /// `set_low_battery_threshold`, while the page is subscribed with
+    /// `mado/subscribe_events`.
+    PowerChanged(PowerChange),
+    /// Raised by `MusicSources` when the music source it serves changes, as
+    /// its selection policy picks another one or the selected one goes away,
+    /// while the page is subscribed with `mado/subscribe_events`.
+    MusicSourceChanged(MusicSourceChange),
     // Add more variants here
 }
-#[derive(Serialize, JsonSchema)]
+#[derive(Clone, Serialize, JsonSchema)]
 pub struct ErrorData {
+    /// Human readable description of the error
     pub message: String,
+    /// Error code, specific to the service raising the error
     pub code: u32,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct CustomEvent {
+    /// Name chosen by the sender
+    #[schemars(example = &"hover")]
+    pub name: String,
+    /// JSON payload, `null` when the sender gave none
+    #[schemars(example = serde_json::json!({ "id": 3 }))]
+    pub data: Value,
+}
+
 pub trait EventRaiser {
     fn raise_event(&self, event: Event);
 }
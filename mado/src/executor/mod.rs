@@ -0,0 +1,236 @@
+//! Executor abstraction for async services.
+//!
+//! Mado does not pick an async runtime: hosts hand an [`Executor`] to the
+//! command registry, which uses it to run batches and the commands Mado
+//! serves itself off the WebView thread. [`ThreadExecutor`] works without any runtime,
+//! [`PoolExecutor`] bounds the threads of tasks that never wait on each other,
+//! and [`TestExecutor`] runs tasks step by step for unit tests.
+
+use std::{
+    collections::VecDeque,
+    future::Future,
+    panic::{AssertUnwindSafe, catch_unwind},
+    pin::{Pin, pin},
+    sync::{Arc, Mutex, mpsc},
+    task::{Context, Poll, Wake, Waker},
+    thread::{self, Thread},
+};
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Runs futures to completion in the background.
+pub trait Executor: Send + Sync {
+    fn spawn(&self, future: BoxFuture<'static, ()>);
+}
+
+/// Runs every task on its own thread with [`block_on`].
+/// Fine for the handful of concurrent commands a page makes; hosts with a
+/// runtime should provide an executor backed by it instead.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ThreadExecutor;
+
+impl Executor for ThreadExecutor {
+    fn spawn(&self, future: BoxFuture<'static, ()>) {
+        thread::spawn(move || block_on(future));
+    }
+}
+
+/// Runs tasks on a fixed number of threads, one task at a time per thread.
+/// Suits tasks that never wait on each other, like downloads: a task waiting
+/// on one queued behind it would wait forever once every thread does, so
+/// command registries, whose batches wait on their calls, need another one.
+pub struct PoolExecutor {
+    tasks: mpsc::Sender<BoxFuture<'static, ()>>,
+}
+
+impl PoolExecutor {
+    pub fn new(threads: usize) -> Self {
+        let (tasks, queue) = mpsc::channel::<BoxFuture<'static, ()>>();
+        let queue = Arc::new(Mutex::new(queue));
+        for _ in 0..threads.max(1) {
+            let queue = queue.clone();
+            thread::spawn(move || {
+                loop {
+                    let Ok(task) = queue.lock().unwrap().recv() else {
+                        return;
+                    };
+                    // A panicking task doesn't take its thread along
+                    let _ = catch_unwind(AssertUnwindSafe(|| block_on(task)));
+                }
+            });
+        }
+        Self { tasks }
+    }
+}
+
+impl Executor for PoolExecutor {
+    fn spawn(&self, future: BoxFuture<'static, ()>) {
+        let _ = self.tasks.send(future);
+    }
+}
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Blocks the current thread until `future` completes.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        thread::park();
+    }
+}
+
+type TaskQueue = Arc<Mutex<VecDeque<Arc<Task>>>>;
+
+struct Task {
+    future: Mutex<Option<BoxFuture<'static, ()>>>,
+    queue: TaskQueue,
+}
+
+impl Wake for Task {
+    fn wake(self: Arc<Self>) {
+        let queue = self.queue.clone();
+        queue.lock().unwrap().push_back(self);
+    }
+}
+
+/// Single threaded executor that only makes progress when asked to,
+/// so tests can observe async services between steps.
+#[derive(Default, Clone)]
+pub struct TestExecutor {
+    queue: TaskQueue,
+}
+
+impl TestExecutor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Polls tasks until none of them can make progress.
+    /// Returns the number of polls made.
+    pub fn run_until_stalled(&self) -> usize {
+        let mut polls = 0;
+        loop {
+            let Some(task) = self.queue.lock().unwrap().pop_front() else {
+                return polls;
+            };
+            let mut slot = task.future.lock().unwrap();
+            if let Some(mut future) = slot.take() {
+                polls += 1;
+                let waker = Waker::from(task.clone());
+                let mut cx = Context::from_waker(&waker);
+                if future.as_mut().poll(&mut cx).is_pending() {
+                    *slot = Some(future);
+                }
+            }
+        }
+    }
+}
+
+impl Executor for TestExecutor {
+    fn spawn(&self, future: BoxFuture<'static, ()>) {
+        let task = Arc::new(Task {
+            future: Mutex::new(Some(future)),
+            queue: self.queue.clone(),
+        });
+        self.queue.lock().unwrap().push_back(task);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    /// Future pending until `open` is called, like a socket waiting for data.
+    #[derive(Clone, Default)]
+    struct Gate(Arc<Mutex<(bool, Option<Waker>)>>);
+
+    impl Gate {
+        fn open(&self) {
+            let mut state = self.0.lock().unwrap();
+            state.0 = true;
+            if let Some(waker) = state.1.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    impl Future for Gate {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            let mut state = self.0.lock().unwrap();
+            if state.0 {
+                return Poll::Ready(());
+            }
+            state.1 = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+
+    #[test]
+    fn test_executor_runs_until_stalled() {
+        let executor = TestExecutor::new();
+        let gate = Gate::default();
+        let done = Arc::new(AtomicBool::new(false));
+        let (g, d) = (gate.clone(), done.clone());
+        executor.spawn(Box::pin(async move {
+            g.await;
+            d.store(true, Ordering::SeqCst);
+        }));
+
+        assert_eq!(executor.run_until_stalled(), 1);
+        assert!(!done.load(Ordering::SeqCst));
+        gate.open();
+        assert_eq!(executor.run_until_stalled(), 1);
+        assert!(done.load(Ordering::SeqCst));
+        assert_eq!(executor.run_until_stalled(), 0);
+    }
+
+    #[test]
+    fn pool_executor_bounds_its_threads() {
+        let executor = PoolExecutor::new(2);
+        let running = Arc::new(AtomicUsize::new(0));
+        let most = Arc::new(AtomicUsize::new(0));
+        let (tx, rx) = mpsc::channel();
+        executor.spawn(Box::pin(async { panic!("task failing") }));
+        for _ in 0..6 {
+            let (running, most, tx) = (running.clone(), most.clone(), tx.clone());
+            executor.spawn(Box::pin(async move {
+                most.fetch_max(running.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(20));
+                running.fetch_sub(1, Ordering::SeqCst);
+                tx.send(thread::current().id()).unwrap();
+            }));
+        }
+        let mut threads: Vec<_> = (0..6)
+            .map(|_| rx.recv_timeout(Duration::from_secs(5)).unwrap())
+            .collect();
+        threads.sort_by_key(|id| format!("{id:?}"));
+        threads.dedup();
+        assert_eq!(threads.len(), 2);
+        assert_eq!(most.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn block_on_waits_for_other_threads() {
+        let gate = Gate::default();
+        let g = gate.clone();
+        thread::spawn(move || g.open());
+        block_on(gate);
+    }
+}
@@ -1,4 +1,8 @@
 pub mod events;
+pub mod executor;
+pub mod operations;
+pub mod protocol;
+pub mod scheduler;
 pub mod services;
 
 pub trait System {}
@@ -0,0 +1,354 @@
+//! Long-running commands.
+//!
+//! A long-running command answers right away with an [`OperationStarted`] holding
+//! the operation ID, then reports through events tied to that ID:
+//! [`Event::OperationProgress`] while it works and a single
+//! [`Event::OperationFinished`] with the final result. The page can cancel it
+//! with `mado/cancel_operation`.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    thread,
+};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    events::{Event, EventRaiser},
+    protocol::CommandSpec,
+};
+
+pub type OperationId = u64;
+
+/// Returned by a long-running command instead of its result.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub struct OperationStarted {
+    /// ID the progress and result events will refer to
+    pub operation: OperationId,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq, JsonSchema)]
+pub struct OperationProgress {
+    pub operation: OperationId,
+    /// Progress Percentage (0.0 to 1.0), if the operation can tell
+    pub progress: Option<f64>,
+    /// What the operation is currently doing
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq, JsonSchema)]
+pub enum OperationStatus {
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq, JsonSchema)]
+pub struct OperationFinished {
+    pub operation: OperationId,
+    pub status: OperationStatus,
+    /// Result of the command, when it completed
+    pub result: Option<Value>,
+    /// Why the command failed, when it failed
+    pub error: Option<String>,
+}
+
+/// Implemented by services to expose a long-running command.
+///
+/// `start` must return quickly: the work belongs on a thread or task that
+/// reports through the context and ends with [`OperationContext::finish`].
+pub trait OperationHandler: Send + Sync {
+    fn start(&self, args: Value, ctx: OperationContext);
+}
+
+/// Runs a blocking closure on its own thread, finishing with its return value.
+pub struct Blocking<F>(Arc<F>);
+
+impl<F> Blocking<F>
+where
+    F: Fn(Value, &OperationContext) -> Result<Value, String> + Send + Sync + 'static,
+{
+    pub fn new(f: F) -> Self {
+        Self(Arc::new(f))
+    }
+}
+
+impl<F> OperationHandler for Blocking<F>
+where
+    F: Fn(Value, &OperationContext) -> Result<Value, String> + Send + Sync + 'static,
+{
+    fn start(&self, args: Value, ctx: OperationContext) {
+        let f = self.0.clone();
+        thread::spawn(move || {
+            let result = f(args, &ctx);
+            ctx.finish(result);
+        });
+    }
+}
+
+struct Shared {
+    raiser: Arc<dyn EventRaiser + Send + Sync>,
+    next_id: AtomicU64,
+    running: Mutex<HashMap<OperationId, Arc<AtomicBool>>>,
+}
+
+/// Handle given to a running operation to report progress and its result.
+#[derive(Clone)]
+pub struct OperationContext {
+    id: OperationId,
+    cancelled: Arc<AtomicBool>,
+    shared: Arc<Shared>,
+}
+
+impl OperationContext {
+    pub fn id(&self) -> OperationId {
+        self.id
+    }
+
+    /// Whether the page asked to cancel the operation.
+    /// Operations should check it regularly and stop early.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Raises an `OperationProgress` event, unless the operation already finished.
+    pub fn progress(&self, progress: Option<f64>, message: Option<&str>) {
+        if !self.shared.running.lock().unwrap().contains_key(&self.id) {
+            return;
+        }
+        self.shared
+            .raiser
+            .raise_event(Event::OperationProgress(OperationProgress {
+                operation: self.id,
+                progress,
+                message: message.map(str::to_string),
+            }));
+    }
+
+    /// Raises the `OperationFinished` event. Only the first call has an effect.
+    /// A cancelled operation is reported as cancelled, whatever its result.
+    pub fn finish(&self, result: Result<Value, String>) {
+        if self
+            .shared
+            .running
+            .lock()
+            .unwrap()
+            .remove(&self.id)
+            .is_none()
+        {
+            return;
+        }
+        let finished = match result {
+            _ if self.is_cancelled() => OperationFinished {
+                operation: self.id,
+                status: OperationStatus::Cancelled,
+                result: None,
+                error: None,
+            },
+            Ok(value) => OperationFinished {
+                operation: self.id,
+                status: OperationStatus::Completed,
+                result: Some(value),
+                error: None,
+            },
+            Err(error) => OperationFinished {
+                operation: self.id,
+                status: OperationStatus::Failed,
+                result: None,
+                error: Some(error),
+            },
+        };
+        self.shared
+            .raiser
+            .raise_event(Event::OperationFinished(finished));
+    }
+}
+
+/// Keeps track of running operations.
+#[derive(Clone)]
+pub struct OperationManager {
+    shared: Arc<Shared>,
+}
+
+impl OperationManager {
+    /// `raiser` receives the progress and result events of every operation.
+    pub fn new(raiser: Arc<dyn EventRaiser + Send + Sync>) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                raiser,
+                next_id: AtomicU64::new(1),
+                running: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// Starts `handler` as a new operation.
+    pub fn start(&self, handler: &dyn OperationHandler, args: Value) -> OperationStarted {
+        let id = self.shared.next_id.fetch_add(1, Ordering::SeqCst);
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.shared
+            .running
+            .lock()
+            .unwrap()
+            .insert(id, cancelled.clone());
+        handler.start(
+            args,
+            OperationContext {
+                id,
+                cancelled,
+                shared: self.shared.clone(),
+            },
+        );
+        OperationStarted { operation: id }
+    }
+
+    /// Requests cancellation. Returns `false` if the operation is not running.
+    pub fn cancel(&self, id: OperationId) -> bool {
+        match self.shared.running.lock().unwrap().get(&id) {
+            Some(cancelled) => {
+                cancelled.store(true, Ordering::SeqCst);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_running(&self, id: OperationId) -> bool {
+        self.shared.running.lock().unwrap().contains_key(&id)
+    }
+
+    /// Turns `spec` into a long-running command served by Mado: calling it
+    /// starts `handler` and answers with an [`OperationStarted`].
+    /// `spec` should be declared with `OperationStarted` as its return type.
+    pub fn operation_spec(
+        &self,
+        spec: CommandSpec,
+        handler: impl OperationHandler + 'static,
+    ) -> CommandSpec {
+        let manager = self.clone();
+        spec.with_handler(move |args: Value| manager.start(&handler, args))
+            .direct()
+    }
+
+    /// Spec of `mado/cancel_operation`.
+    pub fn command_specs(&self) -> Vec<CommandSpec> {
+        let manager = self.clone();
+        vec![
+            CommandSpec::new::<OperationId, bool>("mado", "cancel_operation")
+                .with_handler(move |id| manager.cancel(id))
+                .direct(),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::{Receiver, Sender, channel};
+
+    struct ChannelRaiser(Mutex<Sender<Event>>);
+
+    impl EventRaiser for ChannelRaiser {
+        fn raise_event(&self, event: Event) {
+            let _ = self.0.lock().unwrap().send(event);
+        }
+    }
+
+    fn manager() -> (OperationManager, Receiver<Event>) {
+        let (tx, rx) = channel();
+        (
+            OperationManager::new(Arc::new(ChannelRaiser(Mutex::new(tx)))),
+            rx,
+        )
+    }
+
+    /// Finishes as soon as it is started, without a thread.
+    struct Immediate(Result<Value, String>);
+
+    impl OperationHandler for Immediate {
+        fn start(&self, _args: Value, ctx: OperationContext) {
+            ctx.progress(Some(0.5), Some("halfway"));
+            ctx.finish(self.0.clone());
+            ctx.progress(Some(1.0), None);
+        }
+    }
+
+    #[test]
+    fn reports_progress_then_result() {
+        let (manager, events) = manager();
+        let started = manager.start(&Immediate(Ok(Value::from(42))), Value::Null);
+
+        let Ok(Event::OperationProgress(progress)) = events.try_recv() else {
+            panic!("expected a progress event");
+        };
+        assert_eq!(progress.operation, started.operation);
+        assert_eq!(progress.message.as_deref(), Some("halfway"));
+        let Ok(Event::OperationFinished(finished)) = events.try_recv() else {
+            panic!("expected a finished event");
+        };
+        assert_eq!(finished.status, OperationStatus::Completed);
+        assert_eq!(finished.result, Some(Value::from(42)));
+        // Nothing is reported after the operation finished
+        assert!(events.try_recv().is_err());
+        assert!(!manager.is_running(started.operation));
+    }
+
+    #[test]
+    fn reports_failures() {
+        let (manager, events) = manager();
+        manager.start(&Immediate(Err("disk full".into())), Value::Null);
+        let finished = events
+            .try_iter()
+            .find_map(|e| match e {
+                Event::OperationFinished(f) => Some(f),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(finished.status, OperationStatus::Failed);
+        assert_eq!(finished.error.as_deref(), Some("disk full"));
+    }
+
+    #[test]
+    fn cancels_running_operations() {
+        let (manager, events) = manager();
+        let handler = Blocking::new(|_, ctx: &OperationContext| {
+            while !ctx.is_cancelled() {
+                thread::yield_now();
+            }
+            Ok(Value::Null)
+        });
+        let started = manager.start(&handler, Value::Null);
+        assert!(manager.is_running(started.operation));
+        assert!(manager.cancel(started.operation));
+
+        let Ok(Event::OperationFinished(finished)) = events.recv() else {
+            panic!("expected a finished event");
+        };
+        assert_eq!(finished.status, OperationStatus::Cancelled);
+        assert!(!manager.cancel(started.operation));
+    }
+
+    #[test]
+    fn serves_operations_as_commands() {
+        let (manager, _events) = manager();
+        let spec = manager.operation_spec(
+            CommandSpec::new::<String, OperationStarted>("test", "scan"),
+            Immediate(Ok(Value::Null)),
+        );
+        let mut registry = crate::protocol::CommandRegistry::new();
+        registry.register(spec);
+        registry.extend(manager.command_specs());
+
+        let started = registry.invoke("test/scan", Value::from("C:/")).unwrap();
+        assert_eq!(started, serde_json::json!({ "operation": 1 }));
+        assert!(registry.invoke("test/scan", Value::from(3)).is_err());
+        let cancelled = registry.invoke("mado/cancel_operation", Value::from(1));
+        assert_eq!(cancelled.unwrap(), Value::Bool(false));
+    }
+}
@@ -0,0 +1,211 @@
+use std::sync::mpsc;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::validation::{CommandError, CommandRegistry, CommandSpec};
+
+/// Service and command name of the batch endpoint, i.e. `mado://mado/batch`.
+pub const BATCH_SERVICE: &str = "mado";
+pub const BATCH_COMMAND: &str = "batch";
+
+/// A single call inside a batch.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct BatchCall {
+    /// Command to call, like `host/read_string`
+    pub command: String,
+    /// Argument of the command, omitted for commands without arguments
+    #[serde(default)]
+    pub args: Value,
+}
+
+/// Outcome of a single call inside a batch, in the same position as the call.
+#[derive(Debug, Clone, Serialize, PartialEq, JsonSchema)]
+pub struct BatchResult {
+    pub command: String,
+    /// Return value of the command, if it succeeded
+    pub result: Option<Value>,
+    /// Why the command failed, if it did
+    pub error: Option<CommandError>,
+}
+
+/// Spec of the batch endpoint itself, so the calls array gets validated.
+pub fn batch_spec() -> CommandSpec {
+    CommandSpec::new::<Vec<BatchCall>, Vec<BatchResult>>(BATCH_SERVICE, BATCH_COMMAND)
+}
+
+/// Parses and validates the body of a `mado/batch` call.
+#[allow(clippy::result_large_err)]
+pub fn parse_calls(body: &[u8]) -> Result<Vec<BatchCall>, CommandError> {
+    let spec = batch_spec();
+    let args = spec.validate_body(body)?;
+    serde_json::from_value(args).map_err(|e| spec.failure(format!("{}: {e}", spec.path())))
+}
+
+impl CommandRegistry {
+    /// Runs every call in order and collects their results.
+    /// Consecutive calls to parallel safe commands run concurrently on the
+    /// registry's executor, which must make progress while the batch waits.
+    pub fn execute_batch(&self, calls: Vec<BatchCall>) -> Vec<BatchResult> {
+        let mut results = Vec::with_capacity(calls.len());
+        let mut calls = calls.into_iter().peekable();
+        while let Some(call) = calls.next() {
+            let mut group = vec![call];
+            if self.is_parallel_safe(&group[0].command) {
+                while let Some(next) = calls.next_if(|c| self.is_parallel_safe(&c.command)) {
+                    group.push(next);
+                }
+            }
+            if group.len() == 1 {
+                results.extend(group.into_iter().map(|call| {
+                    let result = self.invoke(&call.command, call.args);
+                    batch_result(call.command, result)
+                }));
+                continue;
+            }
+            let pending: Vec<_> = group
+                .into_iter()
+                .map(|call| {
+                    let (tx, rx) = mpsc::channel();
+                    let future = self.invoke_async(&call.command, call.args);
+                    self.executor().spawn(Box::pin(async move {
+                        let _ = tx.send(future.await);
+                    }));
+                    (call.command, rx)
+                })
+                .collect();
+            for (command, rx) in pending {
+                results.push(match rx.recv() {
+                    Ok(result) => batch_result(command, result),
+                    // The task dropped its sender without answering: it panicked
+                    Err(_) => BatchResult {
+                        error: Some(CommandError {
+                            command: command.clone(),
+                            signature: String::new(),
+                            field: None,
+                            expected: None,
+                            found: None,
+                            message: format!("{command}: command panicked"),
+                        }),
+                        command,
+                        result: None,
+                    },
+                });
+            }
+        }
+        results
+    }
+
+    fn is_parallel_safe(&self, command: &str) -> bool {
+        command
+            .split_once('/')
+            .and_then(|(service, name)| self.get(service, name))
+            .is_some_and(|spec| spec.parallel && spec.handler.is_some())
+    }
+}
+
+fn batch_result(command: String, result: Result<Value, CommandError>) -> BatchResult {
+    match result {
+        Ok(result) => BatchResult {
+            command,
+            result: Some(result),
+            error: None,
+        },
+        Err(error) => BatchResult {
+            command,
+            result: None,
+            error: Some(error),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::{
+        Arc, Barrier,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    fn call(command: &str, args: Value) -> BatchCall {
+        BatchCall {
+            command: command.into(),
+            args,
+        }
+    }
+
+    #[test]
+    fn runs_calls_in_order() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let mut registry = CommandRegistry::new();
+        let c = counter.clone();
+        registry.register(
+            CommandSpec::without_args::<usize>("test", "next")
+                .with_handler(move |()| c.fetch_add(1, Ordering::SeqCst)),
+        );
+        registry.register(
+            CommandSpec::new::<String, String>("host", "get_variable")
+                .with_handler(|var: String| var.to_uppercase()),
+        );
+
+        let results = registry.execute_batch(vec![
+            call("test/next", Value::Null),
+            call("host/get_variable", json!("#a#")),
+            call("test/next", Value::Null),
+        ]);
+        let values: Vec<_> = results.iter().map(|r| r.result.clone()).collect();
+        assert_eq!(
+            values,
+            vec![Some(json!(0)), Some(json!("#A#")), Some(json!(1))]
+        );
+    }
+
+    #[test]
+    fn reports_errors_per_call() {
+        let mut registry = CommandRegistry::new();
+        registry.register(
+            CommandSpec::new::<f64, ()>("MusicPlayerService", "set_volume")
+                .with_range(0.0, 1.0)
+                .with_handler(|_: f64| ()),
+        );
+        registry.register(CommandSpec::new::<String, ()>("host", "no_handler"));
+
+        let results = registry.execute_batch(vec![
+            call("MusicPlayerService/set_volume", json!(2.0)),
+            call("MusicPlayerService/set_volume", json!(0.5)),
+            call("host/no_handler", json!("x")),
+            call("nope", Value::Null),
+        ]);
+        assert_eq!(results.len(), 4);
+        let error = results[0].error.as_ref().unwrap();
+        assert_eq!(error.found.as_deref(), Some("number 2.0"));
+        assert_eq!(results[1].result, Some(Value::Null));
+        assert!(results[2].error.is_some());
+        assert!(results[3].error.is_some());
+    }
+
+    #[test]
+    fn runs_parallel_safe_calls_concurrently() {
+        // Both calls must be running at the same time for the barrier to open
+        let barrier = Arc::new(Barrier::new(2));
+        let mut registry = CommandRegistry::new();
+        let b = barrier.clone();
+        registry.register(
+            CommandSpec::new::<u32, u32>("test", "wait")
+                .with_handler(move |n: u32| {
+                    b.wait();
+                    n
+                })
+                .parallel_safe(),
+        );
+
+        let results = registry.execute_batch(vec![
+            call("test/wait", json!(1)),
+            call("test/wait", json!(2)),
+        ]);
+        let values: Vec<_> = results.iter().map(|r| r.result.clone()).collect();
+        assert_eq!(values, vec![Some(json!(1)), Some(json!(2))]);
+    }
+}
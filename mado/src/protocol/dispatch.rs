@@ -0,0 +1,121 @@
+use serde_json::Value;
+
+use super::validation::{CommandError, CommandRegistry, CommandSpec, Handler};
+use crate::executor::{BoxFuture, block_on};
+
+impl CommandRegistry {
+    /// Invokes `service/command` with `args`, validating them first.
+    /// Async handlers are waited for on the current thread.
+    #[allow(clippy::result_large_err)]
+    pub fn invoke(&self, command: &str, args: Value) -> Result<Value, CommandError> {
+        let (spec, handler) = self.resolve(command)?;
+        spec.validate(&args)?;
+        let result = match handler {
+            Handler::Sync(handler) => handler(args),
+            Handler::Async(handler) => block_on(handler(args)),
+        };
+        result.map_err(|e| spec.failure(format!("{}: {e}", spec.path())))
+    }
+
+    /// Invokes `service/command` with `args`, validating them first.
+    /// The handler only runs when the future is polled, so spawning it on an
+    /// executor keeps even sync handlers off the current thread.
+    pub fn invoke_async(
+        &self,
+        command: &str,
+        args: Value,
+    ) -> BoxFuture<'static, Result<Value, CommandError>> {
+        let (spec, handler) = match self.resolve(command) {
+            Ok(resolved) => resolved,
+            Err(error) => return Box::pin(async move { Err(error) }),
+        };
+        if let Err(error) = spec.validate(&args) {
+            return Box::pin(async move { Err(error) });
+        }
+        let failure = spec.failure(String::new());
+        let fail = move |e: String| CommandError {
+            message: format!("{}: {e}", failure.command),
+            ..failure
+        };
+        match handler {
+            Handler::Sync(handler) => Box::pin(async move { handler(args).map_err(fail) }),
+            Handler::Async(handler) => {
+                let future = handler(args);
+                Box::pin(async move { future.await.map_err(fail) })
+            }
+        }
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn resolve(&self, command: &str) -> Result<(&CommandSpec, Handler), CommandError> {
+        let unknown = || CommandError {
+            command: command.to_string(),
+            signature: String::new(),
+            field: None,
+            expected: None,
+            found: None,
+            message: format!("{command}: unknown command or not available in batches"),
+        };
+        let (service, name) = command.split_once('/').ok_or_else(unknown)?;
+        let spec = self.get(service, name).ok_or_else(unknown)?;
+        let handler = spec.handler.clone().ok_or_else(unknown)?;
+        Ok((spec, handler))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::TestExecutor;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn runs_async_handlers_on_the_executor() {
+        let executor = TestExecutor::new();
+        let mut registry = CommandRegistry::with_executor(Arc::new(executor.clone()));
+        registry.register(
+            CommandSpec::new::<u32, u32>("test", "double")
+                .with_async_handler(|n: u32| async move { n * 2 }),
+        );
+        assert!(registry.get("test", "double").unwrap().is_async());
+
+        let result = Arc::new(Mutex::new(None));
+        let future = registry.invoke_async("test/double", Value::from(21));
+        let r = result.clone();
+        registry.executor().spawn(Box::pin(async move {
+            *r.lock().unwrap() = Some(future.await);
+        }));
+        assert!(result.lock().unwrap().is_none());
+        executor.run_until_stalled();
+        assert_eq!(*result.lock().unwrap(), Some(Ok(Value::from(42))));
+
+        // Batches wait for async handlers in place
+        assert_eq!(
+            registry.invoke("test/double", Value::from(2)),
+            Ok(Value::from(4))
+        );
+        assert!(registry.invoke("test/double", Value::from("2")).is_err());
+    }
+
+    #[test]
+    fn runs_sync_handlers_when_polled() {
+        let executor = TestExecutor::new();
+        let mut registry = CommandRegistry::with_executor(Arc::new(executor.clone()));
+        let calls = Arc::new(Mutex::new(0));
+        let c = calls.clone();
+        registry.register(CommandSpec::new::<u32, u32>("test", "count").with_handler(
+            move |n: u32| {
+                *c.lock().unwrap() += 1;
+                n
+            },
+        ));
+
+        let future = registry.invoke_async("test/count", Value::from(1));
+        registry.executor().spawn(Box::pin(async move {
+            future.await.unwrap();
+        }));
+        assert_eq!(*calls.lock().unwrap(), 0);
+        executor.run_until_stalled();
+        assert_eq!(*calls.lock().unwrap(), 1);
+    }
+}
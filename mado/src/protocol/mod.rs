@@ -0,0 +1,132 @@
+//! Glue between the `mado://` custom protocol and the command services.
+//!
+//! Hosts wrap the `wry_cmd` protocol handler with [`wrap_protocol`], so that
+//! malformed calls are rejected with a [`CommandError`] before reaching a
+//! service, and Mado's own endpoints (like `mado/batch`) are served.
+
+pub mod batch;
+mod dispatch;
+pub mod validation;
+
+use std::sync::Arc;
+
+use wry::{
+    RequestAsyncResponder, WebViewId,
+    http::{Request, Response, StatusCode, Uri, header::CONTENT_TYPE},
+};
+
+pub use batch::{BatchCall, BatchResult};
+pub use validation::{CommandError, CommandRegistry, CommandSpec};
+
+/// Splits a command URI into its service and command name.
+/// Handles both `mado://host/read_string` and `https://mado.localhost/host/read_string`.
+pub fn command_path(uri: &Uri) -> Option<(String, String)> {
+    let segments: Vec<&str> = uri.path().split('/').filter(|s| !s.is_empty()).collect();
+    match segments.as_slice() {
+        [.., service, command] => Some((service.to_string(), command.to_string())),
+        [command] => Some((uri.host()?.to_string(), command.to_string())),
+        [] => None,
+    }
+}
+
+/// Builds the JSON response sent back for a rejected call.
+pub fn error_response(error: &CommandError) -> Response<Vec<u8>> {
+    let body = serde_json::to_vec(&serde_json::json!({ "error": error })).unwrap_or_default();
+    json_response(StatusCode::BAD_REQUEST, body)
+}
+
+fn json_response(status: StatusCode, body: Vec<u8>) -> Response<Vec<u8>> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(body)
+        .unwrap()
+}
+
+fn result_response(result: Result<serde_json::Value, CommandError>) -> Response<Vec<u8>> {
+    match result {
+        Ok(value) => json_response(
+            StatusCode::OK,
+            serde_json::to_vec(&value).unwrap_or_default(),
+        ),
+        Err(error) => error_response(&error),
+    }
+}
+
+/// Handles `mado/batch`: validates the calls array and runs it.
+fn handle_batch(registry: &CommandRegistry, body: &[u8]) -> Response<Vec<u8>> {
+    match batch::parse_calls(body) {
+        Ok(calls) => {
+            let results = registry.execute_batch(calls);
+            json_response(
+                StatusCode::OK,
+                serde_json::to_vec(&results).unwrap_or_default(),
+            )
+        }
+        Err(error) => error_response(&error),
+    }
+}
+
+/// Wraps a custom protocol handler so every call is validated against `registry`
+/// first, and Mado's own endpoints (batches, direct commands) are answered
+/// without reaching `handler`. Batches and direct commands run on the
+/// registry's executor, so they never block the thread serving the protocol.
+pub fn wrap_protocol<F>(
+    registry: Arc<CommandRegistry>,
+    handler: F,
+) -> impl Fn(WebViewId, Request<Vec<u8>>, RequestAsyncResponder) + 'static
+where
+    F: Fn(WebViewId, Request<Vec<u8>>, RequestAsyncResponder) + 'static,
+{
+    move |id, request, responder| {
+        if let Some((service, command)) = command_path(request.uri()) {
+            if service.eq_ignore_ascii_case(batch::BATCH_SERVICE) && command == batch::BATCH_COMMAND
+            {
+                let body = request.into_body();
+                let batch_registry = registry.clone();
+                registry.executor().spawn(Box::pin(async move {
+                    responder.respond(handle_batch(&batch_registry, &body));
+                }));
+                return;
+            }
+            if let Some(spec) = registry.get(&service, &command)
+                && spec.direct
+            {
+                let args = match spec.validate_body(request.body()) {
+                    Ok(args) => args,
+                    Err(error) => return responder.respond(error_response(&error)),
+                };
+                let future = registry.invoke_async(&spec.path(), args);
+                registry.executor().spawn(Box::pin(async move {
+                    responder.respond(result_response(future.await));
+                }));
+                return;
+            }
+            if let Err(error) = registry.validate(&service, &command, request.body()) {
+                responder.respond(error_response(&error));
+                return;
+            }
+        }
+        handler(id, request, responder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_command_paths() {
+        let parse = |uri: &str| command_path(&uri.parse().unwrap());
+        assert_eq!(
+            parse("mado://host/read_string"),
+            Some(("host".into(), "read_string".into()))
+        );
+        assert_eq!(
+            parse("https://mado.localhost/host/read_string"),
+            Some(("host".into(), "read_string".into()))
+        );
+        assert_eq!(parse("mado://host/"), None);
+    }
+}
@@ -0,0 +1,841 @@
+use std::{any::type_name, collections::HashMap, fmt, future::Future, sync::Arc};
+
+use schemars::{JsonSchema, Schema, SchemaGenerator};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::{Map, Value};
+
+use crate::executor::{BoxFuture, Executor, ThreadExecutor};
+
+/// Type-erased command implementation, taking and returning JSON.
+pub type CommandHandler = Arc<dyn Fn(Value) -> Result<Value, String> + Send + Sync>;
+/// Type-erased async command implementation, taking and returning JSON.
+pub type AsyncCommandHandler =
+    Arc<dyn Fn(Value) -> BoxFuture<'static, Result<Value, String>> + Send + Sync>;
+
+#[derive(Clone)]
+pub(crate) enum Handler {
+    Sync(CommandHandler),
+    Async(AsyncCommandHandler),
+}
+
+/// Structured error returned to the page when a command call is rejected.
+#[derive(Debug, Clone, Serialize, PartialEq, JsonSchema)]
+pub struct CommandError {
+    /// Command that failed, like `host/read_int`
+    pub command: String,
+    /// Signature of the command, like `fn read_int(RmReadParameters<i32>) -> i32`
+    pub signature: String,
+    /// Path to the offending field, like `default` or `bangs[2].measure`.
+    /// Empty when the argument itself is wrong.
+    pub field: Option<String>,
+    /// What the command expected at `field`
+    pub expected: Option<String>,
+    /// What was received instead
+    pub found: Option<String>,
+    /// Human readable description of the error
+    pub message: String,
+}
+
+/// Describes the argument a command accepts, so calls can be checked before
+/// they reach the service.
+/// Specs with a handler can also be invoked directly, e.g. from a batch.
+#[derive(Clone)]
+pub struct CommandSpec {
+    pub service: String,
+    pub command: String,
+    pub signature: String,
+    args: Option<Schema>,
+    pub(crate) handler: Option<Handler>,
+    pub(crate) parallel: bool,
+    pub(crate) direct: bool,
+}
+
+impl fmt::Debug for CommandSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CommandSpec")
+            .field("command", &self.path())
+            .field("signature", &self.signature)
+            .field("handler", &self.handler.is_some())
+            .field("parallel", &self.parallel)
+            .field("direct", &self.direct)
+            .finish()
+    }
+}
+
+impl CommandSpec {
+    /// Spec for a command taking a single `Args` argument and returning `Ret`.
+    pub fn new<Args: JsonSchema, Ret>(service: &str, command: &str) -> Self {
+        Self {
+            service: service.to_string(),
+            command: command.to_string(),
+            signature: format!(
+                "fn {command}({}) -> {}",
+                short_type_name::<Args>(),
+                short_type_name::<Ret>()
+            ),
+            args: Some(SchemaGenerator::default().into_root_schema_for::<Args>()),
+            handler: None,
+            parallel: false,
+            direct: false,
+        }
+    }
+
+    /// Spec for a command that takes no argument.
+    pub fn without_args<Ret>(service: &str, command: &str) -> Self {
+        Self {
+            service: service.to_string(),
+            command: command.to_string(),
+            signature: format!("fn {command}() -> {}", short_type_name::<Ret>()),
+            args: None,
+            handler: None,
+            parallel: false,
+            direct: false,
+        }
+    }
+
+    /// Restricts a numeric argument to `min..=max`.
+    pub fn with_range(mut self, min: f64, max: f64) -> Self {
+        if let Some(schema) = &mut self.args {
+            schema.insert("minimum".into(), min.into());
+            schema.insert("maximum".into(), max.into());
+        }
+        self
+    }
+
+    /// Lets the command be invoked without going through the WebView protocol.
+    pub fn with_handler<Args, Ret, F>(mut self, handler: F) -> Self
+    where
+        Args: DeserializeOwned,
+        Ret: Serialize,
+        F: Fn(Args) -> Ret + Send + Sync + 'static,
+    {
+        self.handler = Some(Handler::Sync(Arc::new(move |args| {
+            let args = serde_json::from_value(args).map_err(|e| e.to_string())?;
+            serde_json::to_value(handler(args)).map_err(|e| e.to_string())
+        })));
+        self
+    }
+
+    /// Like [`with_handler`](Self::with_handler), for handlers that can fail.
+    /// An `Err` is reported to the page as a [`CommandError`].
+    pub fn with_fallible_handler<Args, Ret, F>(mut self, handler: F) -> Self
+    where
+        Args: DeserializeOwned,
+        Ret: Serialize,
+        F: Fn(Args) -> Result<Ret, String> + Send + Sync + 'static,
+    {
+        self.handler = Some(Handler::Sync(Arc::new(move |args| {
+            let args = serde_json::from_value(args).map_err(|e| e.to_string())?;
+            serde_json::to_value(handler(args)?).map_err(|e| e.to_string())
+        })));
+        self
+    }
+
+    /// Like [`with_handler`](Self::with_handler), for handlers returning a future.
+    /// Async commands are always served by Mado, on the registry's executor.
+    pub fn with_async_handler<Args, Ret, F, Fut>(mut self, handler: F) -> Self
+    where
+        Args: DeserializeOwned,
+        Ret: Serialize,
+        F: Fn(Args) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Ret> + Send + 'static,
+    {
+        self.handler = Some(Handler::Async(Arc::new(
+            move |args| match serde_json::from_value::<Args>(args) {
+                Ok(args) => {
+                    let future = handler(args);
+                    Box::pin(async move {
+                        serde_json::to_value(future.await).map_err(|e| e.to_string())
+                    })
+                }
+                Err(e) => {
+                    let e = e.to_string();
+                    Box::pin(async move { Err(e) })
+                }
+            },
+        )));
+        self.direct = true;
+        self
+    }
+
+    pub fn is_async(&self) -> bool {
+        matches!(self.handler, Some(Handler::Async(_)))
+    }
+
+    /// Whether Mado serves the command itself, on the registry's executor.
+    pub fn is_direct(&self) -> bool {
+        self.direct
+    }
+
+    /// Marks the command as safe to run concurrently with other parallel safe commands.
+    pub fn parallel_safe(mut self) -> Self {
+        self.parallel = true;
+        self
+    }
+
+    /// Serves the command from its handler instead of forwarding it to the
+    /// wrapped protocol handler, for commands that only exist in Mado.
+    pub fn direct(mut self) -> Self {
+        self.direct = true;
+        self
+    }
+
+    /// `service/command`, as used in error messages.
+    pub fn path(&self) -> String {
+        format!("{}/{}", self.service, self.command)
+    }
+
+    /// Checks `args` against the argument schema of the command.
+    #[allow(clippy::result_large_err)]
+    pub fn validate(&self, args: &Value) -> Result<(), CommandError> {
+        let Some(schema) = &self.args else {
+            return Ok(());
+        };
+        let empty = Map::new();
+        let defs = schema
+            .get("$defs")
+            .and_then(Value::as_object)
+            .unwrap_or(&empty);
+        check(schema.as_value(), defs, args, "").map_err(|mismatch| {
+            let message = match &mismatch.field {
+                Some(field) if mismatch.found == MISSING => format!(
+                    "{}: missing field `{field}` (expected {}). Signature: {}",
+                    self.path(),
+                    mismatch.expected,
+                    self.signature
+                ),
+                Some(field) => format!(
+                    "{}: invalid value for `{field}`, expected {} but found {}. Signature: {}",
+                    self.path(),
+                    mismatch.expected,
+                    mismatch.found,
+                    self.signature
+                ),
+                None => format!(
+                    "{}: invalid argument, expected {} but found {}. Signature: {}",
+                    self.path(),
+                    mismatch.expected,
+                    mismatch.found,
+                    self.signature
+                ),
+            };
+            self.error(mismatch.field, mismatch.expected, mismatch.found, message)
+        })
+    }
+
+    /// Parses and validates a raw request body, returning the parsed argument.
+    #[allow(clippy::result_large_err)]
+    pub fn validate_body(&self, body: &[u8]) -> Result<Value, CommandError> {
+        let args = parse_body(body).map_err(|e| {
+            self.error(
+                None,
+                "a JSON value".into(),
+                "invalid JSON".into(),
+                format!("{}: request body is not valid JSON ({e})", self.path()),
+            )
+        })?;
+        self.validate(&args)?;
+        Ok(args)
+    }
+
+    /// Builds an error for a call that was valid but failed.
+    pub fn failure(&self, message: String) -> CommandError {
+        CommandError {
+            command: self.path(),
+            signature: self.signature.clone(),
+            field: None,
+            expected: None,
+            found: None,
+            message,
+        }
+    }
+
+    /// Builds an error for this command.
+    pub fn error(
+        &self,
+        field: Option<String>,
+        expected: String,
+        found: String,
+        message: String,
+    ) -> CommandError {
+        CommandError {
+            command: self.path(),
+            signature: self.signature.clone(),
+            field,
+            expected: Some(expected),
+            found: Some(found),
+            message,
+        }
+    }
+}
+
+/// Set of command specs known to the host, keyed by service and command name.
+/// Commands without a spec are not validated.
+pub struct CommandRegistry {
+    specs: HashMap<(String, String), CommandSpec>,
+    executor: Arc<dyn Executor>,
+}
+
+impl Default for CommandRegistry {
+    fn default() -> Self {
+        Self {
+            specs: HashMap::new(),
+            executor: Arc::new(ThreadExecutor),
+        }
+    }
+}
+
+impl fmt::Debug for CommandRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CommandRegistry")
+            .field("specs", &self.specs.values())
+            .finish_non_exhaustive()
+    }
+}
+
+impl CommandRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registry driving async commands with `executor` instead of a thread per call.
+    pub fn with_executor(executor: Arc<dyn Executor>) -> Self {
+        Self {
+            specs: HashMap::new(),
+            executor,
+        }
+    }
+
+    pub fn executor(&self) -> &Arc<dyn Executor> {
+        &self.executor
+    }
+
+    pub fn register(&mut self, spec: CommandSpec) {
+        let key = (spec.service.to_lowercase(), spec.command.to_lowercase());
+        self.specs.insert(key, spec);
+    }
+
+    pub fn extend(&mut self, specs: impl IntoIterator<Item = CommandSpec>) {
+        specs.into_iter().for_each(|spec| self.register(spec));
+    }
+
+    /// Service names are matched case-insensitively.
+    pub fn get(&self, service: &str, command: &str) -> Option<&CommandSpec> {
+        self.specs
+            .get(&(service.to_lowercase(), command.to_lowercase()))
+    }
+
+    /// Validates the raw request body of a call to `service/command`.
+    #[allow(clippy::result_large_err)]
+    pub fn validate(&self, service: &str, command: &str, body: &[u8]) -> Result<(), CommandError> {
+        match self.get(service, command) {
+            Some(spec) => spec.validate_body(body).map(|_| ()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Parses a request body, an empty body meaning `null`.
+pub fn parse_body(body: &[u8]) -> serde_json::Result<Value> {
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return Ok(Value::Null);
+    }
+    serde_json::from_slice(body)
+}
+
+const MISSING: &str = "nothing";
+
+struct Mismatch {
+    field: Option<String>,
+    expected: String,
+    found: String,
+}
+
+/// Checks `value` against the subset of JSON schema emitted by schemars.
+fn check(
+    schema: &Value,
+    defs: &Map<String, Value>,
+    value: &Value,
+    path: &str,
+) -> Result<(), Mismatch> {
+    let mismatch = || Mismatch {
+        field: (!path.is_empty()).then(|| path.to_string()),
+        expected: describe(schema, defs),
+        found: describe_value(value),
+    };
+
+    if let Some(name) = ref_name(schema) {
+        return match defs.get(name) {
+            Some(def) => check(def, defs, value, path),
+            None => Ok(()),
+        };
+    }
+    for key in ["anyOf", "oneOf"] {
+        if let Some(options) = schema.get(key).and_then(Value::as_array) {
+            if options.iter().any(|o| check(o, defs, value, path).is_ok()) {
+                return Ok(());
+            }
+            // In a tagged enum, only the variant named by the tag is relevant
+            let tagged: Vec<&Value> = options.iter().filter(|o| has_tag_of(o, value)).collect();
+            if let [only] = tagged.as_slice() {
+                return check(only, defs, value, path);
+            }
+            // A single non-null option gives a more precise error than the union
+            let non_null: Vec<&Value> = options.iter().filter(|o| o["type"] != "null").collect();
+            if let [only] = non_null.as_slice() {
+                return check(only, defs, value, path);
+            }
+            return Err(mismatch());
+        }
+    }
+    if let Some(expected) = schema.get("const")
+        && expected != value
+    {
+        return Err(mismatch());
+    }
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array)
+        && !allowed.contains(value)
+    {
+        return Err(mismatch());
+    }
+    if let Some(ty) = schema.get("type")
+        && !type_matches(ty, schema.get("format").and_then(Value::as_str), value)
+    {
+        return Err(mismatch());
+    }
+    if let Some(number) = value.as_f64() {
+        let below = schema["minimum"].as_f64().is_some_and(|min| number < min);
+        let above = schema["maximum"].as_f64().is_some_and(|max| number > max);
+        if below || above {
+            return Err(mismatch());
+        }
+    }
+    if let Value::Object(object) = value {
+        if let Some(required) = schema.get("required").and_then(Value::as_array) {
+            for name in required.iter().filter_map(Value::as_str) {
+                if !object.contains_key(name) {
+                    return Err(Mismatch {
+                        field: Some(join(path, name)),
+                        expected: describe(&schema["properties"][name], defs),
+                        found: MISSING.to_string(),
+                    });
+                }
+            }
+        }
+        if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+            for (name, field) in object {
+                if let Some(field_schema) = properties.get(name) {
+                    check(field_schema, defs, field, &join(path, name))?;
+                }
+            }
+        }
+    }
+    if let (Value::Array(items), Some(items_schema)) = (value, schema.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            check(items_schema, defs, item, &format!("{path}[{i}]"))?;
+        }
+    }
+    Ok(())
+}
+
+fn type_matches(ty: &Value, format: Option<&str>, value: &Value) -> bool {
+    match ty {
+        Value::Array(types) => types.iter().any(|t| type_matches(t, format, value)),
+        Value::String(ty) => match ty.as_str() {
+            "null" => value.is_null(),
+            "boolean" => value.is_boolean(),
+            "string" => value.is_string(),
+            "array" => value.is_array(),
+            "object" => value.is_object(),
+            "number" => value.is_number(),
+            "integer" => match value.as_f64() {
+                Some(n) if n.fract() == 0.0 => match format {
+                    Some("int8") => (i8::MIN as f64..=i8::MAX as f64).contains(&n),
+                    Some("int16") => (i16::MIN as f64..=i16::MAX as f64).contains(&n),
+                    Some("int32") => (i32::MIN as f64..=i32::MAX as f64).contains(&n),
+                    Some("uint8") => (0.0..=u8::MAX as f64).contains(&n),
+                    Some("uint16") => (0.0..=u16::MAX as f64).contains(&n),
+                    Some("uint32") => (0.0..=u32::MAX as f64).contains(&n),
+                    Some("uint64" | "uint") => {
+                        value.is_u64() || (0.0..u64::MAX as f64).contains(&n)
+                    }
+                    Some("int64" | "int") => {
+                        value.is_i64() || (i64::MIN as f64..i64::MAX as f64).contains(&n)
+                    }
+                    _ => true,
+                },
+                _ => false,
+            },
+            _ => true,
+        },
+        _ => true,
+    }
+}
+
+/// Describes what a schema accepts, in terms a page author understands.
+fn describe(schema: &Value, defs: &Map<String, Value>) -> String {
+    if let Some(name) = ref_name(schema) {
+        return match defs.get(name) {
+            Some(def) if def.get("enum").is_some() => describe(def, defs),
+            _ => format!("object `{name}`"),
+        };
+    }
+    if let Some(options) = schema
+        .get("anyOf")
+        .or_else(|| schema.get("oneOf"))
+        .and_then(Value::as_array)
+    {
+        let options: Vec<String> = options.iter().map(|o| describe(o, defs)).collect();
+        return options.join(" or ");
+    }
+    if let Some(expected) = schema.get("const") {
+        return expected.to_string();
+    }
+    if let Some((name, tag)) = tag_of(schema) {
+        return format!("object with \"{name}\": {tag}");
+    }
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        let allowed: Vec<String> = allowed.iter().map(Value::to_string).collect();
+        return format!("one of {}", allowed.join(", "));
+    }
+    let mut description = match schema.get("type") {
+        Some(Value::String(ty)) => ty.clone(),
+        Some(Value::Array(types)) => {
+            let types: Vec<&str> = types.iter().filter_map(Value::as_str).collect();
+            types.join(" or ")
+        }
+        _ => "any value".to_string(),
+    };
+    if let Some(format) = schema.get("format").and_then(Value::as_str) {
+        description = format!("{description} ({format})");
+    }
+    match (schema["minimum"].as_f64(), schema["maximum"].as_f64()) {
+        (Some(min), Some(max)) => format!("{description} between {min} and {max}"),
+        (Some(min), None) if min != 0.0 => format!("{description} >= {min}"),
+        (None, Some(max)) => format!("{description} <= {max}"),
+        _ => description,
+    }
+}
+
+fn describe_value(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Bool(b) => format!("boolean {b}"),
+        Value::Number(n) => format!("number {n}"),
+        Value::String(s) => format!("string {}", Value::String(s.clone())),
+        Value::Array(_) => "array".to_string(),
+        Value::Object(_) => "object".to_string(),
+    }
+}
+
+/// The constant property identifying a variant of a tagged enum, if any.
+fn tag_of(schema: &Value) -> Option<(&String, &Value)> {
+    schema
+        .get("properties")?
+        .as_object()?
+        .iter()
+        .find_map(|(name, property)| Some((name, property.get("const")?)))
+}
+
+fn has_tag_of(schema: &Value, value: &Value) -> bool {
+    tag_of(schema).is_some_and(|(name, tag)| value.get(name) == Some(tag))
+}
+
+fn ref_name(schema: &Value) -> Option<&str> {
+    schema.get("$ref")?.as_str()?.strip_prefix("#/$defs/")
+}
+
+fn join(path: &str, field: &str) -> String {
+    if path.is_empty() {
+        field.to_string()
+    } else {
+        format!("{path}.{field}")
+    }
+}
+
+/// `std::any::type_name` without module paths, e.g. `RmReadParameters<i32>`.
+fn short_type_name<T: ?Sized>() -> String {
+    shorten(type_name::<T>())
+}
+
+/// A type written without module paths.
+fn shorten(full: &str) -> String {
+    let mut short = String::with_capacity(full.len());
+    let mut segment = String::new();
+    for c in full.chars() {
+        if c.is_alphanumeric() || c == '_' || c == ':' {
+            segment.push(c);
+        } else {
+            short.push_str(segment.rsplit("::").next().unwrap_or_default());
+            segment.clear();
+            short.push(c);
+        }
+    }
+    short.push_str(segment.rsplit("::").next().unwrap_or_default());
+    short.replace(", ", ",").replace(',', ", ")
+}
+
+/// Signatures of the methods of the `#[commands]` impls in `source`, written
+/// like [`CommandSpec::signature`], so tests can check hand-written specs
+/// against the methods `wry_cmd` serves. A `Result<T, E>` return counts as
+/// `T`, like for specs with a fallible handler.
+#[cfg(any(test, feature = "test-support"))]
+pub fn commands_signatures(source: &str) -> Vec<String> {
+    let file = syn::parse_file(source).expect("commands source should be valid Rust");
+    file.items
+        .iter()
+        .filter_map(|item| match item {
+            syn::Item::Impl(item) if item.attrs.iter().any(|a| a.path().is_ident("commands")) => {
+                Some(item)
+            }
+            _ => None,
+        })
+        .flat_map(|item| &item.items)
+        .filter_map(|item| match item {
+            syn::ImplItem::Fn(method) => Some(method_signature(&method.sig)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Signature of a method, e.g. `fn read_int(RmReadParameters<i32>) -> i32`
+/// for `fn read_int(&self, args: RmReadParameters<i32>) -> i32`.
+#[cfg(any(test, feature = "test-support"))]
+fn method_signature(signature: &syn::Signature) -> String {
+    let arg = signature
+        .inputs
+        .iter()
+        .find_map(|input| match input {
+            syn::FnArg::Typed(arg) => Some(type_signature(&arg.ty)),
+            syn::FnArg::Receiver(_) => None,
+        })
+        .unwrap_or_default();
+    let ret = match &signature.output {
+        syn::ReturnType::Default => "()".to_string(),
+        syn::ReturnType::Type(_, ty) => type_signature(ok_type(ty)),
+    };
+    format!("fn {}({arg}) -> {ret}", signature.ident)
+}
+
+/// `T` for a `Result<T, E>`, else `ty` itself.
+#[cfg(any(test, feature = "test-support"))]
+fn ok_type(ty: &syn::Type) -> &syn::Type {
+    if let syn::Type::Path(path) = ty
+        && let Some(last) = path.path.segments.last()
+        && last.ident == "Result"
+        && let syn::PathArguments::AngleBracketed(args) = &last.arguments
+        && let Some(syn::GenericArgument::Type(ok)) = args.args.first()
+    {
+        return ok;
+    }
+    ty
+}
+
+/// A type written like [`short_type_name`] does.
+#[cfg(any(test, feature = "test-support"))]
+fn type_signature(ty: &syn::Type) -> String {
+    let tokens = quote::ToTokens::to_token_stream(ty).to_string();
+    shorten(&tokens.replace(' ', ""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[allow(dead_code)]
+    #[derive(Deserialize, JsonSchema)]
+    struct ReadParameters<T> {
+        key: String,
+        default: T,
+    }
+
+    fn registry() -> CommandRegistry {
+        let mut registry = CommandRegistry::new();
+        registry.register(CommandSpec::new::<ReadParameters<i32>, i32>(
+            "host", "read_int",
+        ));
+        registry.register(
+            CommandSpec::new::<f64, ()>("MusicPlayerService", "set_volume").with_range(0.0, 1.0),
+        );
+        registry
+    }
+
+    #[test]
+    fn accepts_valid_calls() {
+        let registry = registry();
+        assert!(
+            registry
+                .validate("host", "read_int", br#"{"key":"Size","default":3}"#)
+                .is_ok()
+        );
+        assert!(
+            registry
+                .validate("musicplayerservice", "set_volume", b"0.5")
+                .is_ok()
+        );
+        assert!(
+            registry
+                .validate("host", "unknown", b"\"anything\"")
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn reports_missing_field() {
+        let err = registry()
+            .validate("host", "read_int", br#"{"key":"Size"}"#)
+            .unwrap_err();
+        assert_eq!(err.command, "host/read_int");
+        assert_eq!(err.signature, "fn read_int(ReadParameters<i32>) -> i32");
+        assert_eq!(err.field.as_deref(), Some("default"));
+        assert_eq!(err.expected.as_deref(), Some("integer (int32)"));
+        assert_eq!(err.found.as_deref(), Some(MISSING));
+    }
+
+    #[test]
+    fn reports_wrong_type() {
+        let err = registry()
+            .validate("MusicPlayerService", "set_volume", br#""loud""#)
+            .unwrap_err();
+        assert_eq!(err.field, None);
+        assert_eq!(
+            err.expected.as_deref(),
+            Some("number (double) between 0 and 1")
+        );
+        assert_eq!(err.found.as_deref(), Some(r#"string "loud""#));
+        assert!(err.message.contains("fn set_volume(f64) -> ()"));
+    }
+
+    #[test]
+    fn reports_out_of_range() {
+        let registry = registry();
+        assert!(
+            registry
+                .validate("MusicPlayerService", "set_volume", b"1.5")
+                .is_err()
+        );
+        assert!(
+            registry
+                .validate("MusicPlayerService", "set_volume", b"-0.1")
+                .is_err()
+        );
+        let err = registry
+            .validate(
+                "host",
+                "read_int",
+                &serde_json::to_vec(&json!({"key": "a", "default": 1.5})).unwrap(),
+            )
+            .unwrap_err();
+        assert_eq!(err.field.as_deref(), Some("default"));
+    }
+
+    #[test]
+    fn reads_the_signatures_of_commands_impls() {
+        let source = r#"
+            struct Host;
+
+            impl Host {
+                fn skipped(&self) {}
+            }
+
+            #[commands(name = "host")]
+            impl Host {
+                /// Opens `mado://host/{x}` and https://example.com, see "}"
+                fn open(&self, url: std::string::String) -> Result<Vec<(u8, bool)>, String> {
+                    let _ = "mado://host/read_int // not a comment {";
+                    Ok(Vec::new())
+                }
+
+                fn read_int(&self, args: ReadParameters<i32>) -> i32 {
+                    args.default
+                }
+
+                fn play(&self) {}
+            }
+        "#;
+        assert_eq!(
+            commands_signatures(source),
+            vec![
+                "fn open(String) -> Vec<(u8, bool)>",
+                "fn read_int(ReadParameters<i32>) -> i32",
+                "fn play() -> ()",
+            ]
+        );
+        assert_eq!(
+            commands_signatures(source)[1],
+            CommandSpec::new::<ReadParameters<i32>, i32>("host", "read_int").signature
+        );
+    }
+
+    #[test]
+    fn checks_64_bit_and_pointer_sized_integers() {
+        let count = CommandSpec::new::<usize, ()>("test", "count");
+        assert!(count.validate(&json!(5)).is_ok());
+        assert!(count.validate(&json!(u64::MAX)).is_ok());
+        let err = count.validate(&json!(-5)).unwrap_err();
+        assert_eq!(err.expected.as_deref(), Some("integer (uint)"));
+        assert!(count.validate(&json!(1e20)).is_err());
+
+        let offset = CommandSpec::new::<i64, ()>("test", "offset");
+        assert!(offset.validate(&json!(-5)).is_ok());
+        assert!(offset.validate(&json!(i64::MIN)).is_ok());
+        assert!(offset.validate(&json!(u64::MAX)).is_err());
+    }
+
+    #[test]
+    fn reports_invalid_json() {
+        let err = registry()
+            .validate("host", "read_int", b"{key")
+            .unwrap_err();
+        assert_eq!(err.found.as_deref(), Some("invalid JSON"));
+    }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize, JsonSchema)]
+    #[serde(tag = "kind")]
+    enum Shape {
+        Circle { radius: f64 },
+        Square { side: f64 },
+    }
+
+    #[test]
+    fn reports_errors_inside_tagged_variants() {
+        let spec = CommandSpec::new::<Vec<Shape>, ()>("test", "draw");
+        assert!(
+            spec.validate(&json!([{ "kind": "Circle", "radius": 1 }]))
+                .is_ok()
+        );
+
+        let err = spec
+            .validate(&json!([{ "kind": "Square", "side": 1 }, { "kind": "Circle" }]))
+            .unwrap_err();
+        assert_eq!(err.field.as_deref(), Some("[1].radius"));
+        assert_eq!(err.found.as_deref(), Some(MISSING));
+
+        let err = spec.validate(&json!([{ "kind": "Star" }])).unwrap_err();
+        assert_eq!(err.field.as_deref(), Some("[0]"));
+        assert_eq!(
+            err.expected.as_deref(),
+            Some(r#"object with "kind": "Circle" or object with "kind": "Square""#)
+        );
+    }
+
+    #[test]
+    fn reports_handler_failures() {
+        let mut registry = CommandRegistry::new();
+        registry.register(
+            CommandSpec::new::<u32, u32>("test", "half").with_fallible_handler(|n: u32| {
+                if n.is_multiple_of(2) {
+                    Ok(n / 2)
+                } else {
+                    Err(format!("{n} is odd"))
+                }
+            }),
+        );
+        assert_eq!(registry.invoke("test/half", json!(4)), Ok(json!(2)));
+        let err = registry.invoke("test/half", json!(3)).unwrap_err();
+        assert_eq!(err.message, "test/half: 3 is odd");
+    }
+}
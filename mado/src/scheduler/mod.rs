@@ -0,0 +1,379 @@
+//! Shared polling of data providers.
+//!
+//! Providers (the music player, system metrics...) register a poll function
+//! with the interval they want, under the name of the event they raise. A
+//! provider is only polled while at least one page subscribed to its event
+//! with `mado/subscribe_events`, and once per interval however many pages
+//! did: the poll function gets the subscribers and raises the event for each.
+//!
+//! Hosts call [`Scheduler::tick`] often, e.g. on every update of every
+//! instance; it only polls the providers that are due. Time comes from a
+//! [`Clock`], so tests can use [`FakeClock`] to step through schedules.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    events::{ErrorData, Event},
+    protocol::CommandSpec,
+};
+
+/// Source of the current time.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Clock that only moves when told to.
+#[derive(Debug)]
+pub struct FakeClock {
+    now: Mutex<Instant>,
+}
+
+impl Default for FakeClock {
+    fn default() -> Self {
+        Self {
+            now: Mutex::new(Instant::now()),
+        }
+    }
+}
+
+impl FakeClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+}
+
+/// `ErrorData::code` of the `ERROR` events raised when a provider registered
+/// with [`Scheduler::register_polled`] can't read its source.
+pub const READ_FAILED: u32 = 1;
+
+/// Identifies a page subscribing to provider events, e.g. a host instance.
+pub type SubscriberId = u64;
+
+/// Polls a provider for the given subscribers.
+pub type PollFn = dyn Fn(&[SubscriberId]) + Send + Sync;
+
+struct Provider {
+    interval: Duration,
+    poll: Arc<PollFn>,
+    subscribers: BTreeSet<SubscriberId>,
+    next_poll: Option<Instant>,
+}
+
+pub struct Scheduler {
+    clock: Arc<dyn Clock>,
+    providers: Mutex<BTreeMap<String, Provider>>,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new(Arc::new(SystemClock))
+    }
+}
+
+impl Scheduler {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            clock,
+            providers: Mutex::default(),
+        }
+    }
+
+    /// Registers the provider of the `event` events, polled every `interval`.
+    /// Registering an event again replaces its provider, keeping the subscribers.
+    pub fn register(
+        &self,
+        event: &str,
+        interval: Duration,
+        poll: impl Fn(&[SubscriberId]) + Send + Sync + 'static,
+    ) {
+        let mut providers = self.providers.lock().unwrap();
+        let subscribers = providers
+            .remove(event)
+            .map(|p| p.subscribers)
+            .unwrap_or_default();
+        providers.insert(
+            event.to_string(),
+            Provider {
+                interval,
+                poll: Arc::new(poll),
+                next_poll: (!subscribers.is_empty()).then(|| self.clock.now()),
+                subscribers,
+            },
+        );
+    }
+
+    /// Registers the provider of `event` for a source read once per poll,
+    /// whatever the number of subscribers: `to_events` turns what `read`
+    /// returns into the events `raise` delivers to each of them. A failed read
+    /// is delivered as `ERROR` with [`READ_FAILED`], its message prefixed with
+    /// `service`.
+    pub fn register_polled<T>(
+        &self,
+        event: &str,
+        service: &str,
+        interval: Duration,
+        read: impl Fn() -> Result<T, String> + Send + Sync + 'static,
+        to_events: impl Fn(T) -> Vec<Event> + Send + Sync + 'static,
+        raise: impl Fn(SubscriberId, Event) + Send + Sync + 'static,
+    ) {
+        let service = service.to_string();
+        self.register(event, interval, move |subscribers| {
+            let events = match read() {
+                Ok(value) => to_events(value),
+                Err(message) => vec![Event::ERROR(ErrorData {
+                    message: format!("{service}: {message}"),
+                    code: READ_FAILED,
+                })],
+            };
+            for &subscriber in subscribers {
+                for event in &events {
+                    raise(subscriber, event.clone());
+                }
+            }
+        });
+    }
+
+    /// Subscribes to `event`. The first subscriber makes its provider due right away.
+    /// Returns `false` if `subscriber` already was subscribed.
+    pub fn subscribe(&self, event: &str, subscriber: SubscriberId) -> Result<bool, String> {
+        let mut providers = self.providers.lock().unwrap();
+        let provider = providers
+            .get_mut(event)
+            .ok_or_else(|| format!("no provider for {event} events"))?;
+        if provider.subscribers.is_empty() {
+            provider.next_poll = Some(self.clock.now());
+        }
+        Ok(provider.subscribers.insert(subscriber))
+    }
+
+    /// Returns `false` if `subscriber` wasn't subscribed to `event`.
+    pub fn unsubscribe(&self, event: &str, subscriber: SubscriberId) -> bool {
+        let mut providers = self.providers.lock().unwrap();
+        let Some(provider) = providers.get_mut(event) else {
+            return false;
+        };
+        let removed = provider.subscribers.remove(&subscriber);
+        if provider.subscribers.is_empty() {
+            provider.next_poll = None;
+        }
+        removed
+    }
+
+    /// Drops every subscription of `subscriber`, e.g. when its page goes away.
+    pub fn unsubscribe_all(&self, subscriber: SubscriberId) {
+        let events: Vec<String> = self.providers.lock().unwrap().keys().cloned().collect();
+        for event in events {
+            self.unsubscribe(&event, subscriber);
+        }
+    }
+
+    pub fn is_subscribed(&self, event: &str, subscriber: SubscriberId) -> bool {
+        let providers = self.providers.lock().unwrap();
+        providers
+            .get(event)
+            .is_some_and(|p| p.subscribers.contains(&subscriber))
+    }
+
+    /// Polls every provider that is due and returns how many were polled.
+    pub fn tick(&self) -> usize {
+        let now = self.clock.now();
+        let due: Vec<(Arc<PollFn>, Vec<SubscriberId>)> = {
+            let mut providers = self.providers.lock().unwrap();
+            providers
+                .values_mut()
+                .filter(|p| p.next_poll.is_some_and(|next| next <= now))
+                .map(|p| {
+                    p.next_poll = Some(now + p.interval);
+                    (p.poll.clone(), p.subscribers.iter().copied().collect())
+                })
+                .collect()
+        };
+        // Polled outside of the lock, so providers may subscribe or unsubscribe
+        for (poll, subscribers) in &due {
+            poll(subscribers);
+        }
+        due.len()
+    }
+
+    /// Time until the next provider is due, `None` if nothing is subscribed.
+    pub fn next_poll_in(&self) -> Option<Duration> {
+        let now = self.clock.now();
+        let providers = self.providers.lock().unwrap();
+        providers
+            .values()
+            .filter_map(|p| p.next_poll)
+            .min()
+            .map(|next| next.saturating_duration_since(now))
+    }
+
+    /// Specs of `mado/subscribe_events` and `mado/unsubscribe_events` for the
+    /// page `subscriber`, served by Mado. Both take an event name.
+    pub fn command_specs(self: &Arc<Self>, subscriber: SubscriberId) -> Vec<CommandSpec> {
+        let (subscribe, unsubscribe) = (self.clone(), self.clone());
+        vec![
+            CommandSpec::new::<String, bool>("mado", "subscribe_events")
+                .with_fallible_handler(move |event: String| subscribe.subscribe(&event, subscriber))
+                .direct(),
+            CommandSpec::new::<String, bool>("mado", "unsubscribe_events")
+                .with_handler(move |event: String| unsubscribe.unsubscribe(&event, subscriber))
+                .direct(),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::CommandRegistry;
+    use serde_json::json;
+
+    /// Subscribers of every poll made.
+    type Polls = Arc<Mutex<Vec<Vec<SubscriberId>>>>;
+
+    /// Scheduler with a `Tick` provider polled every second, recording its polls.
+    fn scheduler() -> (Arc<Scheduler>, Arc<FakeClock>, Polls) {
+        let clock = Arc::new(FakeClock::new());
+        let scheduler = Arc::new(Scheduler::new(clock.clone()));
+        let polls = Arc::new(Mutex::new(Vec::new()));
+        let record = polls.clone();
+        scheduler.register("Tick", Duration::from_secs(1), move |subscribers| {
+            record.lock().unwrap().push(subscribers.to_vec());
+        });
+        (scheduler, clock, polls)
+    }
+
+    #[test]
+    fn polls_only_while_subscribed() {
+        let (scheduler, clock, polls) = scheduler();
+        assert_eq!(scheduler.tick(), 0);
+        assert_eq!(scheduler.next_poll_in(), None);
+
+        assert_eq!(scheduler.subscribe("Tick", 1), Ok(true));
+        assert_eq!(scheduler.tick(), 1);
+        // Not due again before the interval elapsed
+        clock.advance(Duration::from_millis(400));
+        assert_eq!(scheduler.tick(), 0);
+        assert_eq!(scheduler.next_poll_in(), Some(Duration::from_millis(600)));
+        clock.advance(Duration::from_millis(600));
+        assert_eq!(scheduler.tick(), 1);
+
+        assert!(scheduler.unsubscribe("Tick", 1));
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(scheduler.tick(), 0);
+        assert_eq!(polls.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn shares_polls_between_subscribers() {
+        let (scheduler, clock, polls) = scheduler();
+        scheduler.subscribe("Tick", 1).unwrap();
+        scheduler.subscribe("Tick", 2).unwrap();
+        assert_eq!(scheduler.subscribe("Tick", 2), Ok(false));
+
+        // Every instance ticks on its own update, the provider is polled once
+        for _ in 0..3 {
+            scheduler.tick();
+        }
+        clock.advance(Duration::from_secs(1));
+        scheduler.unsubscribe_all(1);
+        scheduler.tick();
+        scheduler.tick();
+        assert_eq!(*polls.lock().unwrap(), vec![vec![1, 2], vec![2]]);
+        assert!(!scheduler.is_subscribed("Tick", 1));
+    }
+
+    #[test]
+    fn reads_polled_sources_once_for_every_subscriber() {
+        let scheduler = Scheduler::new(Arc::new(FakeClock::new()));
+        let reads = Arc::new(Mutex::new(vec![Err("gone".to_string()), Ok(2)]));
+        let raised = Arc::new(Mutex::new(Vec::new()));
+        let (r, record) = (reads.clone(), raised.clone());
+        scheduler.register_polled(
+            "Count",
+            "CountService",
+            Duration::ZERO,
+            move || r.lock().unwrap().pop().unwrap(),
+            |count: u32| {
+                (0..count)
+                    .map(|n| {
+                        Event::Custom(crate::events::CustomEvent {
+                            name: n.to_string(),
+                            data: serde_json::Value::Null,
+                        })
+                    })
+                    .collect()
+            },
+            move |subscriber, event| {
+                let json = serde_json::to_value(&event).unwrap();
+                record.lock().unwrap().push((subscriber, json));
+            },
+        );
+        scheduler.subscribe("Count", 1).unwrap();
+        scheduler.subscribe("Count", 2).unwrap();
+
+        scheduler.tick();
+        let raised_now = std::mem::take(&mut *raised.lock().unwrap());
+        let names: Vec<_> = raised_now
+            .iter()
+            .map(|(subscriber, json)| (*subscriber, json["value"]["name"].clone()))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                (1, json!("0")),
+                (1, json!("1")),
+                (2, json!("0")),
+                (2, json!("1"))
+            ]
+        );
+
+        scheduler.tick();
+        let raised_now = raised.lock().unwrap();
+        assert_eq!(raised_now.len(), 2);
+        assert_eq!(raised_now[0].1["kind"], "ERROR");
+        assert_eq!(raised_now[0].1["value"]["message"], "CountService: gone");
+        assert_eq!(raised_now[0].1["value"]["code"], READ_FAILED);
+    }
+
+    #[test]
+    fn subscribes_from_commands() {
+        let (scheduler, _clock, polls) = scheduler();
+        let mut registry = CommandRegistry::new();
+        registry.extend(scheduler.command_specs(7));
+
+        let subscribed = registry.invoke("mado/subscribe_events", json!("Tick"));
+        assert_eq!(subscribed, Ok(json!(true)));
+        scheduler.tick();
+        assert_eq!(*polls.lock().unwrap(), vec![vec![7]]);
+        assert!(
+            registry
+                .invoke("mado/subscribe_events", json!("Nothing"))
+                .is_err()
+        );
+        let unsubscribed = registry.invoke("mado/unsubscribe_events", json!("Tick"));
+        assert_eq!(unsubscribed, Ok(json!(true)));
+    }
+}
@@ -0,0 +1,871 @@
+//! Album covers served from `mado://covers/<key>`.
+//!
+//! Players give covers as remote URLs, `file://` URLs, local paths the WebView
+//! can't load or data URIs. [`CoverCache::proxy`] turns any of them into a
+//! `mado://covers/<key>` URL, the same for the same cover. Pages only get
+//! remote URLs and data URIs turned into one, with [`CoverCache::resolve`], so
+//! they can't read local files through it, nor reach the host's own network. The image is only read when the page
+//! loads it, then kept in a directory where files are named after the SHA-256
+//! of their content, so players giving the same cover differently share it.
+//! Files the page loaded least recently are removed when the directory outgrows
+//! its size limit, and the keys handed out longest ago are forgotten past a few
+//! hundred.
+//!
+//! Pages get a smaller image with `?width=` and `?height=`: it fits in both,
+//! keeping its aspect ratio, and images already fitting are left as they are.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{self, File},
+    io::{Cursor, Read},
+    net::{IpAddr, ToSocketAddrs},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use image::{ImageFormat, imageops::FilterType};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use wry::{
+    RequestAsyncResponder, WebViewId,
+    http::{
+        Request, Response, StatusCode, Uri,
+        header::{CACHE_CONTROL, CONTENT_TYPE},
+    },
+};
+
+use crate::{
+    executor::Executor,
+    operations::{Blocking, OperationContext, OperationManager, OperationStarted},
+    protocol::CommandSpec,
+};
+
+const SERVICE: &str = "CoverService";
+
+/// Host of the cover URLs, `mado://covers/<key>`.
+const HOST: &str = "covers";
+
+/// Size limit caches are usually given, in bytes.
+pub const DEFAULT_MAX_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Told the bytes of a cover read so far and the total when known, stops
+/// reading it by returning an error.
+pub type Progress<'a> = dyn FnMut(u64, Option<u64>) -> Result<(), String> + 'a;
+
+/// Largest cover downloaded, in bytes.
+const MAX_DOWNLOAD: u64 = 16 * 1024 * 1024;
+
+/// Keys remembered, the oldest being forgotten first.
+const MAX_KEYS: usize = 256;
+
+/// Length of the sources of the keys remembered, data URIs holding whole images.
+const MAX_SOURCES_SIZE: usize = 16 * 1024 * 1024;
+
+pub struct CoverCache {
+    dir: PathBuf,
+    max_size: u64,
+    keys: Mutex<Keys>,
+    agent: ureq::Agent,
+    /// Whether pages may resolve covers on the host's own network, only for
+    /// tests serving them locally.
+    local: bool,
+}
+
+impl CoverCache {
+    /// Cache keeping its files in `dir`, under `max_size` bytes.
+    pub fn new(dir: impl Into<PathBuf>, max_size: u64) -> Self {
+        let config = ureq::Agent::config_builder()
+            .timeout_global(Some(Duration::from_secs(10)))
+            .build();
+        Self {
+            dir: dir.into(),
+            max_size,
+            keys: Mutex::default(),
+            agent: ureq::Agent::new_with_config(config),
+            local: false,
+        }
+    }
+
+    /// URL serving the cover at `source`: an HTTP(S) URL, a `file://` URL, a
+    /// local path or a data URI. Empty and `mado://` sources are returned as
+    /// they are.
+    pub fn proxy(&self, source: &str) -> String {
+        if source.is_empty() || source.starts_with("mado://") {
+            return source.to_string();
+        }
+        let key = hex(&Sha256::digest(source.as_bytes())[..16]);
+        let url = format!("mado://{HOST}/{key}");
+        self.keys.lock().unwrap().insert(key, source);
+        url
+    }
+
+    /// [`proxy`](Self::proxy) for sources given by pages: HTTP(S) URLs and
+    /// data URIs, local files being the host's to share. URLs of loopback,
+    /// link-local and private addresses are refused, so pages can't make the
+    /// host read from its own network.
+    pub fn resolve(&self, source: &str) -> Result<String, String> {
+        let remote = ["http://", "https://", "data:", "mado://"]
+            .iter()
+            .any(|scheme| source.starts_with(scheme));
+        if !source.is_empty() && !remote {
+            return Err(format!(
+                "{source}: only HTTP(S) URLs and data URIs can be resolved"
+            ));
+        }
+        if source.starts_with("http") && !self.local && is_local_url(source) {
+            return Err(format!("{source}: not a remote address"));
+        }
+        Ok(self.proxy(source))
+    }
+
+    /// The cover of `key` with its MIME type, made to fit in `bounds` if given.
+    pub fn image(
+        &self,
+        key: &str,
+        bounds: Option<(u32, u32)>,
+    ) -> Result<(Vec<u8>, &'static str), String> {
+        let entry = self.keys.lock().unwrap().entries.get(key).cloned();
+        let entry = entry.ok_or_else(|| format!("Unknown cover {key}"))?;
+        let hash = self.original(key, entry, &mut |_, _| Ok(()))?;
+        let path = match bounds {
+            Some((width, height)) => self.resized(&hash, width, height)?,
+            None => self.dir.join(&hash),
+        };
+        let data = fs::read(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+        touch(&path);
+        let format = image::guess_format(&data).map_err(|e| e.to_string())?;
+        Ok((data, format.to_mime_type()))
+    }
+
+    /// Response to a request of a cover URL, `None` for other URLs.
+    pub fn respond(&self, uri: &Uri) -> Option<Response<Vec<u8>>> {
+        let key = cover_key(uri)?;
+        let query = uri.query().unwrap_or_default();
+        let param = |name: &str| {
+            query
+                .split('&')
+                .filter_map(|pair| pair.split_once('='))
+                .find(|(key, _)| *key == name)
+                .and_then(|(_, value)| value.parse::<u32>().ok())
+        };
+        let bounds = match (param("width"), param("height")) {
+            (None, None) => None,
+            (width, height) => Some((width.unwrap_or(u32::MAX), height.unwrap_or(u32::MAX))),
+        };
+        let response = Response::builder();
+        let response = if !self.keys.lock().unwrap().entries.contains_key(&key) {
+            response
+                .status(StatusCode::NOT_FOUND)
+                .body(format!("Unknown cover {key}").into_bytes())
+        } else {
+            match self.image(&key, bounds) {
+                Ok((data, mime)) => response
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, mime)
+                    .header(CACHE_CONTROL, "max-age=86400")
+                    .body(data),
+                Err(message) => response
+                    .status(StatusCode::BAD_GATEWAY)
+                    .body(message.into_bytes()),
+            }
+        };
+        Some(response.unwrap())
+    }
+
+    /// Reads the cover at `source`, given by a page like for
+    /// [`resolve`](Self::resolve), before the page loads it. `progress` is told
+    /// the bytes downloaded so far and the total when known, and stops the
+    /// download by returning an error. Answers with the `mado://` URL.
+    pub fn fetch(&self, source: &str, progress: &mut Progress<'_>) -> Result<String, String> {
+        let url = self.resolve(source)?;
+        let Some(key) = url.strip_prefix(&format!("mado://{HOST}/")) else {
+            return Ok(url);
+        };
+        let entry = self.keys.lock().unwrap().entries.get(key).cloned();
+        let entry = entry.ok_or_else(|| format!("Unknown cover {key}"))?;
+        self.original(key, entry, progress)?;
+        Ok(url)
+    }
+
+    /// Specs of the `CoverService` commands, served by Mado: `resolve` gives
+    /// pages with a cover from the web its `mado://` URL, and `fetch` does too
+    /// once the cover is read, as an operation of `operations` reporting the
+    /// download.
+    pub fn command_specs(self: &Arc<Self>, operations: &OperationManager) -> Vec<CommandSpec> {
+        let cache = self.clone();
+        let resolve = CommandSpec::new::<String, String>(SERVICE, "resolve")
+            .with_fallible_handler(move |source: String| cache.resolve(&source))
+            .direct();
+        let cache = self.clone();
+        let fetch = Blocking::new(move |source: Value, ctx: &OperationContext| {
+            let source: String = serde_json::from_value(source).map_err(|e| e.to_string())?;
+            let url = cache.fetch(&source, &mut |read, total| {
+                if ctx.is_cancelled() {
+                    return Err("Cancelled".to_string());
+                }
+                let progress = total.map(|total| read as f64 / total as f64);
+                ctx.progress(progress, Some(&format!("{read} bytes read")));
+                Ok(())
+            })?;
+            Ok(Value::from(url))
+        });
+        vec![
+            resolve,
+            operations.operation_spec(
+                CommandSpec::new::<String, OperationStarted>(SERVICE, "fetch"),
+                fetch,
+            ),
+        ]
+    }
+
+    /// Content hash of the cover of `key`, reading it from its source unless
+    /// it is still in the directory.
+    fn original(
+        &self,
+        key: &str,
+        entry: Entry,
+        progress: &mut Progress<'_>,
+    ) -> Result<String, String> {
+        let Entry { source, hash } = entry;
+        if let Some(hash) = hash.filter(|hash| self.dir.join(hash).exists()) {
+            return Ok(hash);
+        }
+        let data = self.read_source(&source, progress)?;
+        image::guess_format(&data).map_err(|_| format!("{source}: not an image"))?;
+        let hash = hex(&Sha256::digest(&data));
+        let path = self.dir.join(&hash);
+        if !path.exists() {
+            self.write(&path, &data)?;
+        }
+        if let Some(entry) = self.keys.lock().unwrap().entries.get_mut(key) {
+            entry.hash = Some(hash.clone());
+        }
+        Ok(hash)
+    }
+
+    /// Path of the original `hash` made to fit in `width` x `height`, as PNG.
+    fn resized(&self, hash: &str, width: u32, height: u32) -> Result<PathBuf, String> {
+        let original = self.dir.join(hash);
+        let path = self.dir.join(format!("{hash}-{width}x{height}"));
+        if path.exists() {
+            return Ok(path);
+        }
+        let data = fs::read(&original).map_err(|e| format!("{}: {e}", original.display()))?;
+        let image = image::load_from_memory(&data).map_err(|e| e.to_string())?;
+        if image.width() <= width && image.height() <= height {
+            return Ok(original);
+        }
+        let mut png = Vec::new();
+        image
+            .resize(width, height, FilterType::Triangle)
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .map_err(|e| e.to_string())?;
+        self.write(&path, &png)?;
+        Ok(path)
+    }
+
+    fn read_source(&self, source: &str, progress: &mut Progress<'_>) -> Result<Vec<u8>, String> {
+        if let Some(data) = source.strip_prefix("data:") {
+            return decode_data_uri(data).ok_or_else(|| "Invalid data URI".to_string());
+        }
+        if source.starts_with("http://") || source.starts_with("https://") {
+            let mut response = self
+                .agent
+                .get(source)
+                .call()
+                .map_err(|e| format!("{source}: {e}"))?;
+            let total = response.body().content_length();
+            let mut reader = response
+                .body_mut()
+                .with_config()
+                .limit(MAX_DOWNLOAD)
+                .reader();
+            let mut data = Vec::new();
+            let mut chunk = [0; 16 * 1024];
+            loop {
+                let read = reader
+                    .read(&mut chunk)
+                    .map_err(|e| format!("{source}: {e}"))?;
+                if read == 0 {
+                    return Ok(data);
+                }
+                data.extend_from_slice(&chunk[..read]);
+                progress(data.len() as u64, total)?;
+            }
+        }
+        let path = match source.strip_prefix("file://") {
+            Some(path) => file_url_path(path),
+            None => PathBuf::from(source),
+        };
+        fs::read(&path).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    /// Writes a file of the cache, then makes room for it.
+    fn write(&self, path: &Path, data: &[u8]) -> Result<(), String> {
+        fs::create_dir_all(&self.dir).map_err(|e| format!("{}: {e}", self.dir.display()))?;
+        // Written aside first, so a page loading it meanwhile never reads half of it
+        let partial = path.with_extension("partial");
+        fs::write(&partial, data)
+            .and_then(|()| fs::rename(&partial, path))
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+        self.evict(path);
+        Ok(())
+    }
+
+    /// Removes the least recently used files until the directory fits in
+    /// `max_size`, except `keep`.
+    fn evict(&self, keep: &Path) {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        let mut files: Vec<(SystemTime, u64, PathBuf)> = entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let metadata = entry.metadata().ok()?;
+                let modified = metadata.modified().ok()?;
+                metadata
+                    .is_file()
+                    .then(|| (modified, metadata.len(), entry.path()))
+            })
+            .collect();
+        let mut size: u64 = files.iter().map(|(_, len, _)| len).sum();
+        files.sort();
+        for (_, len, path) in files {
+            if size <= self.max_size {
+                break;
+            }
+            if path != keep && fs::remove_file(&path).is_ok() {
+                size -= len;
+            }
+        }
+    }
+}
+
+/// Keys handed out by `proxy`, forgotten from the oldest once there are more
+/// than `MAX_KEYS` or their sources outgrow `MAX_SOURCES_SIZE`.
+#[derive(Default)]
+struct Keys {
+    entries: HashMap<String, Entry>,
+    /// From the least to the most recently handed out
+    order: VecDeque<String>,
+    /// Length of all the sources
+    size: usize,
+}
+
+#[derive(Clone)]
+struct Entry {
+    source: String,
+    /// Content hash, once read
+    hash: Option<String>,
+}
+
+impl Keys {
+    fn insert(&mut self, key: String, source: &str) {
+        if self.entries.contains_key(&key) {
+            self.order.retain(|known| *known != key);
+        } else {
+            self.size += source.len();
+            let source = source.to_string();
+            self.entries
+                .insert(key.clone(), Entry { source, hash: None });
+        }
+        self.order.push_back(key);
+        // The key just handed out is kept, whatever its size
+        while self.order.len() > 1 && (self.order.len() > MAX_KEYS || self.size > MAX_SOURCES_SIZE)
+        {
+            let oldest = self.order.pop_front().unwrap();
+            if let Some(entry) = self.entries.remove(&oldest) {
+                self.size -= entry.source.len();
+            }
+        }
+    }
+}
+
+/// Wraps a custom protocol handler so the covers of `cache` are served on
+/// `executor`, as downloading one may take a while. A [`PoolExecutor`]
+/// bounds the covers read at once.
+///
+/// [`PoolExecutor`]: crate::executor::PoolExecutor
+pub fn wrap_covers<F>(
+    cache: Arc<CoverCache>,
+    executor: Arc<dyn Executor>,
+    handler: F,
+) -> impl Fn(WebViewId, Request<Vec<u8>>, RequestAsyncResponder) + 'static
+where
+    F: Fn(WebViewId, Request<Vec<u8>>, RequestAsyncResponder) + 'static,
+{
+    move |id, request, responder| {
+        if cover_key(request.uri()).is_none() {
+            return handler(id, request, responder);
+        }
+        let cache = cache.clone();
+        executor.spawn(Box::pin(async move {
+            if let Some(response) = cache.respond(request.uri()) {
+                responder.respond(response);
+            }
+        }));
+    }
+}
+
+/// Key of a cover URL, in its `mado://covers/<key>` or
+/// `https://mado.localhost/covers/<key>` form.
+fn cover_key(uri: &Uri) -> Option<String> {
+    let segments: Vec<&str> = uri.path().split('/').filter(|s| !s.is_empty()).collect();
+    let host = uri.host()?;
+    match segments.as_slice() {
+        [key] if host.eq_ignore_ascii_case(HOST) => Some(key.to_string()),
+        [prefix, key]
+            if host.eq_ignore_ascii_case("mado.localhost") && prefix.eq_ignore_ascii_case(HOST) =>
+        {
+            Some(key.to_string())
+        }
+        _ => None,
+    }
+}
+
+/// Whether the HTTP(S) URL `url` is on the host's own network: its host is
+/// `localhost` or has a loopback, link-local or private address. Names that
+/// can't be looked up are left to fail when read.
+fn is_local_url(url: &str) -> bool {
+    let Ok(uri) = url.parse::<Uri>() else {
+        return true;
+    };
+    let Some(host) = uri.host() else {
+        return true;
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    if host == "localhost" || host.ends_with(".localhost") {
+        return true;
+    }
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return is_local_address(ip);
+    }
+    let port = uri
+        .port_u16()
+        .unwrap_or(if url.starts_with("https") { 443 } else { 80 });
+    (host.as_str(), port)
+        .to_socket_addrs()
+        .is_ok_and(|mut addresses| addresses.any(|address| is_local_address(address.ip())))
+}
+
+fn is_local_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_local_address(IpAddr::V4(ip)),
+            None => {
+                ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local()
+            }
+        },
+    }
+}
+
+/// Marks a file as just used, for the eviction to keep it longer.
+fn touch(path: &Path) {
+    if let Ok(file) = File::options().write(true).open(path) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Decodes what follows `data:` in a data URI: `[<type>][;base64],<data>`.
+fn decode_data_uri(uri: &str) -> Option<Vec<u8>> {
+    let (meta, data) = uri.split_once(',')?;
+    if meta.ends_with(";base64") {
+        let data: String = data.chars().filter(|c| !c.is_ascii_whitespace()).collect();
+        STANDARD.decode(percent_decode(&data)).ok()
+    } else {
+        Some(percent_decode(data))
+    }
+}
+
+/// Path of a `file://` URL without its scheme, like `/C:/Music/cover.jpg`.
+fn file_url_path(path: &str) -> PathBuf {
+    let path = String::from_utf8_lossy(&percent_decode(path)).into_owned();
+    // Drive letters come after the slash starting the path
+    match path.as_bytes() {
+        [b'/', _, b':', ..] => PathBuf::from(&path[1..]),
+        _ => PathBuf::from(path),
+    }
+}
+
+fn percent_decode(text: &str) -> Vec<u8> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| text.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        events::{Event, EventRaiser},
+        operations::{OperationFinished, OperationStatus},
+        protocol::CommandRegistry,
+    };
+    use serde_json::json;
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            mpsc::{Receiver, Sender, channel},
+        },
+        thread,
+    };
+
+    struct ChannelRaiser(Mutex<Sender<Event>>);
+
+    impl EventRaiser for ChannelRaiser {
+        fn raise_event(&self, event: Event) {
+            let _ = self.0.lock().unwrap().send(event);
+        }
+    }
+
+    fn operations() -> (OperationManager, Receiver<Event>) {
+        let (tx, rx) = channel();
+        let raiser = Arc::new(ChannelRaiser(Mutex::new(tx)));
+        (OperationManager::new(raiser), rx)
+    }
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/covers")
+            .join(name)
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mado-covers-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    /// HTTP server answering `/cover.png` with the sample PNG, `/slow.png`
+    /// with it a few bytes at a time, `/endless.png` with bytes until the
+    /// client leaves and anything else with a 404, counting the requests.
+    fn serve_http() -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let count = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                count.fetch_add(1, Ordering::SeqCst);
+                thread::spawn(move || {
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    let mut request = String::new();
+                    reader.read_line(&mut request).unwrap();
+                    // Skip the headers
+                    let mut line = String::new();
+                    while reader.read_line(&mut line).unwrap() > 2 {
+                        line.clear();
+                    }
+                    let png = fs::read(fixture("cover.png")).unwrap();
+                    let (status, body, chunk) = match request.split(' ').nth(1) {
+                        Some("/cover.png") => ("200 OK", png, usize::MAX),
+                        Some("/slow.png") => ("200 OK", png, 64),
+                        Some("/endless.png") => ("200 OK", vec![0; 1 << 20], 64),
+                        _ => ("404 Not Found", b"nothing here".to_vec(), usize::MAX),
+                    };
+                    let head = format!(
+                        "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        body.len()
+                    );
+                    stream.write_all(head.as_bytes()).unwrap();
+                    for chunk in body.chunks(chunk) {
+                        // The client may leave first
+                        if stream.write_all(chunk).is_err() {
+                            return;
+                        }
+                        if chunk.len() < body.len() {
+                            thread::sleep(Duration::from_millis(20));
+                        }
+                    }
+                });
+            }
+        });
+        (base, requests)
+    }
+
+    fn key(url: &str) -> &str {
+        url.strip_prefix("mado://covers/").unwrap()
+    }
+
+    #[test]
+    fn serves_covers_from_every_kind_of_source() {
+        let (base, requests) = serve_http();
+        let dir = temp_dir("sources");
+        let cache = CoverCache::new(&dir, DEFAULT_MAX_SIZE);
+        let png = fs::read(fixture("cover.png")).unwrap();
+        let path = fixture("cover.png").display().to_string();
+        let sources = [
+            format!("{base}/cover.png"),
+            path.clone(),
+            format!("file://{}", path.replace(' ', "%20")),
+            format!("data:image/png;base64,{}", STANDARD.encode(&png)),
+        ];
+        for source in &sources {
+            let url = cache.proxy(source);
+            assert_eq!(url, cache.proxy(source));
+            assert_eq!(
+                cache.image(key(&url), None).unwrap(),
+                (png.clone(), "image/png")
+            );
+        }
+        // Same content, a single file
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        let url = cache.proxy(&sources[0]);
+        cache.image(key(&url), Some((16, 16))).unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        assert_eq!(cache.proxy(""), "");
+        assert_eq!(cache.proxy(&url), url);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn resolves_only_remote_covers_for_pages() {
+        let cache = CoverCache::new(temp_dir("resolve"), DEFAULT_MAX_SIZE);
+        let remote = "https://example.com/cover.png";
+        assert_eq!(cache.resolve(remote), Ok(cache.proxy(remote)));
+        assert!(cache.resolve("data:image/png;base64,AAAA").is_ok());
+        assert_eq!(cache.resolve(""), Ok(String::new()));
+
+        let path = fixture("cover.png").display().to_string();
+        for source in [
+            path.clone(),
+            format!("file://{path}"),
+            "ftp://host/a.png".into(),
+            "http://localhost:8080/a.png".into(),
+            "http://app.localhost/a.png".into(),
+            "http://127.0.0.1/a.png".into(),
+            "https://192.168.1.1/a.png".into(),
+            "http://10.0.0.2/a.png".into(),
+            "http://169.254.169.254/latest".into(),
+            "http://[::1]:8080/a.png".into(),
+            "http://[fe80::1]/a.png".into(),
+            "http://[::ffff:127.0.0.1]/a.png".into(),
+        ] {
+            assert!(cache.resolve(&source).is_err(), "{source}");
+        }
+        let (operations, _) = operations();
+        let mut registry = CommandRegistry::new();
+        registry.extend(Arc::new(cache).command_specs(&operations));
+        let error = registry
+            .invoke("CoverService/resolve", json!(path))
+            .unwrap_err();
+        assert!(
+            error.message.contains("only HTTP(S) URLs"),
+            "{}",
+            error.message
+        );
+        let url = registry.invoke("CoverService/resolve", json!(remote));
+        assert!(url.unwrap().as_str().unwrap().starts_with("mado://covers/"));
+    }
+
+    #[test]
+    fn fetches_covers_as_operations() {
+        let (base, _) = serve_http();
+        let (operations, events) = operations();
+        let cache = Arc::new(CoverCache {
+            local: true,
+            ..CoverCache::new(temp_dir("fetch"), DEFAULT_MAX_SIZE)
+        });
+        let mut registry = CommandRegistry::new();
+        registry.extend(cache.command_specs(&operations));
+        registry.extend(operations.command_specs());
+        let fetch = |url: String| registry.invoke("CoverService/fetch", json!(url)).unwrap();
+        let next_event = || events.recv_timeout(Duration::from_secs(5)).unwrap();
+        let wait_finished = |progress: &mut Vec<f64>| loop {
+            match next_event() {
+                Event::OperationProgress(event) => progress.extend(event.progress),
+                Event::OperationFinished(finished) => break finished,
+                _ => panic!("unexpected event"),
+            }
+        };
+
+        let started = fetch(format!("{base}/slow.png"));
+        assert_eq!(started, json!({ "operation": 1 }));
+        let mut progress = Vec::new();
+        let OperationFinished { status, result, .. } = wait_finished(&mut progress);
+        assert_eq!(status, OperationStatus::Completed);
+        assert!(progress.len() > 1, "{progress:?}");
+        assert!(progress.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(progress.last(), Some(&1.0));
+        let url = result.unwrap();
+        let (data, _) = cache.image(key(url.as_str().unwrap()), None).unwrap();
+        assert_eq!(data, fs::read(fixture("cover.png")).unwrap());
+
+        // Cancelled by the page halfway
+        let started = fetch(format!("{base}/endless.png"));
+        assert!(matches!(next_event(), Event::OperationProgress(_)));
+        let cancelled = registry.invoke("mado/cancel_operation", started["operation"].clone());
+        assert_eq!(cancelled.unwrap(), json!(true));
+        let finished = wait_finished(&mut Vec::new());
+        assert_eq!(finished.status, OperationStatus::Cancelled);
+
+        // Local files stay out of reach
+        fetch(fixture("cover.png").display().to_string());
+        let finished = wait_finished(&mut Vec::new());
+        assert_eq!(finished.status, OperationStatus::Failed);
+        assert!(finished.error.unwrap().contains("only HTTP(S) URLs"));
+        let _ = fs::remove_dir_all(temp_dir("fetch"));
+    }
+
+    #[test]
+    fn resizes_to_fit() {
+        let dir = temp_dir("resize");
+        let cache = CoverCache::new(&dir, DEFAULT_MAX_SIZE);
+        let url = cache.proxy(&fixture("cover.png").display().to_string());
+        let uri: Uri = format!("{url}?width=32").parse().unwrap();
+        let response = cache.respond(&uri).unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], "image/png");
+        let image = image::load_from_memory(response.body()).unwrap();
+        assert_eq!((image.width(), image.height()), (32, 16));
+
+        // Already fitting: the JPEG as it is
+        let jpeg = fixture("cover.jpg");
+        let url = cache.proxy(&jpeg.display().to_string());
+        let (data, mime) = cache.image(key(&url), Some((100, 100))).unwrap();
+        assert_eq!((data, mime), (fs::read(&jpeg).unwrap(), "image/jpeg"));
+        let (data, mime) = cache.image(key(&url), Some((10, 20))).unwrap();
+        let image = image::load_from_memory(&data).unwrap();
+        assert_eq!((image.width(), image.height(), mime), (10, 10, "image/png"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn evicts_least_recently_served() {
+        let dir = temp_dir("evict");
+        let (png, jpeg) = (fixture("cover.png"), fixture("cover.jpg"));
+        let size = |path: &Path| fs::metadata(path).unwrap().len();
+        let cache = CoverCache::new(&dir, size(&png) + size(&jpeg));
+        let png_key = key(&cache.proxy(&png.display().to_string())).to_string();
+        let jpeg_key = key(&cache.proxy(&jpeg.display().to_string())).to_string();
+        cache.image(&png_key, None).unwrap();
+        cache.image(&jpeg_key, None).unwrap();
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        // The PNG was served since the JPEG, which goes for the thumbnail
+        cache.image(&png_key, None).unwrap();
+        cache.image(&jpeg_key, Some((4, 4))).unwrap();
+        let mut files: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        let jpeg_hash = hex(&Sha256::digest(fs::read(&jpeg).unwrap()));
+        let png_hash = hex(&Sha256::digest(fs::read(&png).unwrap()));
+        let mut expected = vec![format!("{jpeg_hash}-4x4"), png_hash];
+        expected.sort();
+        assert_eq!(files, expected);
+
+        // Read again when needed
+        assert!(cache.image(&jpeg_key, None).is_ok());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn forgets_the_oldest_keys() {
+        let cache = CoverCache::new(temp_dir("keys"), DEFAULT_MAX_SIZE);
+        let first = cache.proxy("https://example.com/0.png");
+        let second = cache.proxy("https://example.com/1.png");
+        for i in 2..=MAX_KEYS {
+            cache.proxy(&format!("https://example.com/{i}.png"));
+        }
+        // Handed out again, so the second is now the oldest
+        cache.proxy("https://example.com/0.png");
+        let known = |url: &str| cache.keys.lock().unwrap().entries.contains_key(key(url));
+        assert!(known(&first));
+        assert!(!known(&second));
+        assert_eq!(cache.keys.lock().unwrap().entries.len(), MAX_KEYS);
+
+        // Large data URIs push the others out, the last one staying
+        let large = |fill: &str| {
+            let data = fill.repeat(MAX_SOURCES_SIZE / 2 + 1);
+            format!("data:image/png;base64,{data}")
+        };
+        let (a, b) = (large("A"), large("B"));
+        let a_url = cache.proxy(&a);
+        let b_url = cache.proxy(&b);
+        assert!(!known(&first));
+        assert!(!known(&a_url));
+        assert!(known(&b_url));
+        let keys = cache.keys.lock().unwrap();
+        assert_eq!((keys.order.len(), keys.size), (1, b.len()));
+    }
+
+    #[test]
+    fn reports_covers_that_cant_be_served() {
+        let (base, _) = serve_http();
+        let dir = temp_dir("errors");
+        let cache = CoverCache::new(&dir, DEFAULT_MAX_SIZE);
+        let status = |url: &str| cache.respond(&url.parse().unwrap()).unwrap().status();
+        assert_eq!(status("mado://covers/0123"), StatusCode::NOT_FOUND);
+        assert_eq!(
+            status(&cache.proxy(&format!("{base}/gone.png"))),
+            StatusCode::BAD_GATEWAY
+        );
+        let text = cache.proxy(&fixture("../../../Cargo.toml").display().to_string());
+        assert_eq!(status(&text), StatusCode::BAD_GATEWAY);
+        assert!(
+            cache
+                .respond(&"mado://host/read_string".parse().unwrap())
+                .is_none()
+        );
+
+        let key_of = |url: &str| cover_key(&url.parse().unwrap());
+        assert_eq!(key_of("mado://covers/abc").as_deref(), Some("abc"));
+        assert_eq!(
+            key_of("https://mado.localhost/covers/abc").as_deref(),
+            Some("abc")
+        );
+        for url in [
+            "mado://covers/a/abc",
+            "mado://host/covers/abc",
+            "https://mado.localhost/a/covers/abc",
+        ] {
+            assert_eq!(key_of(url), None, "{url}");
+        }
+        assert_eq!(decode_data_uri("text/plain,a%20b"), Some(b"a b".to_vec()));
+        assert_eq!(
+            file_url_path("/C:/My%20Music/a.jpg"),
+            PathBuf::from("C:/My Music/a.jpg")
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,304 @@
+//! Mounted filesystems and their free space.
+//!
+//! Hosts implement [`DiskService`] for their platform (Yomi reads
+//! `/proc/self/mountinfo` on Linux). A [`DiskMonitor`] compares successive
+//! lists to raise [`Event::DiskChanged`](crate::events::Event::DiskChanged)
+//! when a filesystem is mounted or unmounted, or when its usage crosses the
+//! configured threshold.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::{
+    events::Event,
+    protocol::CommandSpec,
+    scheduler::{Scheduler, SubscriberId},
+};
+
+pub trait DiskService {
+    /// Mounted filesystems backed by a storage device, in mount order.
+    fn list_mounts(&self) -> Result<Vec<Mount>, String>;
+}
+
+const SERVICE: &str = "DiskService";
+
+/// Spec of `DiskService/list_mounts`.
+pub fn command_specs(service: Arc<dyn DiskService + Send + Sync>) -> Vec<CommandSpec> {
+    vec![
+        CommandSpec::without_args::<Vec<Mount>>(SERVICE, "list_mounts")
+            .with_fallible_handler(move |()| service.list_mounts()),
+    ]
+}
+
+/// Sizes in bytes.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct Mount {
+    /// Device backing the filesystem
+    #[schemars(example = &"/dev/sdb1")]
+    pub device: String,
+    /// Where the filesystem is mounted, which identifies it
+    #[schemars(example = &"/media/usb")]
+    pub mount_point: String,
+    #[schemars(example = &"vfat")]
+    pub fs_type: String,
+    #[schemars(example = 32_000_000_000u64)]
+    pub total: u64,
+    #[schemars(example = 8_000_000_000u64)]
+    pub used: u64,
+    /// Space unprivileged programs can still use
+    #[schemars(example = 24_000_000_000u64)]
+    pub available: u64,
+    /// Whether the device is removable media, like a USB stick or an SD card
+    #[schemars(example = true)]
+    pub removable: bool,
+}
+
+impl Mount {
+    /// Share of the space in use (0.0 to 1.0).
+    pub fn usage(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        self.used as f64 / self.total as f64
+    }
+}
+
+/// Payload of [`Event::DiskChanged`](crate::events::Event::DiskChanged).
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+#[serde(tag = "change")]
+pub enum DiskChange {
+    Mounted {
+        mount: Mount,
+    },
+    Unmounted {
+        mount: Mount,
+    },
+    /// Usage reached the threshold
+    UsageAbove {
+        mount: Mount,
+        threshold: f64,
+    },
+    /// Usage went back under the threshold
+    UsageBelow {
+        mount: Mount,
+        threshold: f64,
+    },
+}
+
+/// Usage threshold monitors start with.
+pub const DEFAULT_THRESHOLD: f64 = 0.9;
+
+/// Remembers the last list of mounts to report what changed.
+pub struct DiskMonitor {
+    threshold: Mutex<f64>,
+    /// Mounts of the previous poll by mount point, `None` before the first one
+    mounts: Mutex<Option<BTreeMap<String, Mount>>>,
+    above: Mutex<BTreeSet<String>>,
+}
+
+impl Default for DiskMonitor {
+    fn default() -> Self {
+        Self::new(DEFAULT_THRESHOLD)
+    }
+}
+
+impl DiskMonitor {
+    pub fn new(threshold: f64) -> Self {
+        Self {
+            threshold: Mutex::new(threshold),
+            mounts: Mutex::default(),
+            above: Mutex::default(),
+        }
+    }
+
+    pub fn threshold(&self) -> f64 {
+        *self.threshold.lock().unwrap()
+    }
+
+    /// Mounts at or above `threshold` usage are reported from the next poll.
+    pub fn set_threshold(&self, threshold: f64) {
+        *self.threshold.lock().unwrap() = threshold;
+    }
+
+    /// Changes since the previous call. The first call only records `mounts`:
+    /// pages get the initial state from `list_mounts`.
+    pub fn changes(&self, mounts: Vec<Mount>) -> Vec<DiskChange> {
+        let threshold = self.threshold();
+        let current: BTreeMap<String, Mount> = mounts
+            .into_iter()
+            .map(|m| (m.mount_point.clone(), m))
+            .collect();
+        let mut above = self.above.lock().unwrap();
+        let previous = self.mounts.lock().unwrap().replace(current.clone());
+        let Some(previous) = previous else {
+            *above = current
+                .values()
+                .filter(|m| m.usage() >= threshold)
+                .map(|m| m.mount_point.clone())
+                .collect();
+            return Vec::new();
+        };
+
+        let mut changes: Vec<DiskChange> = previous
+            .iter()
+            .filter(|(point, _)| !current.contains_key(*point))
+            .map(|(point, mount)| {
+                above.remove(point);
+                DiskChange::Unmounted {
+                    mount: mount.clone(),
+                }
+            })
+            .collect();
+        for (point, mount) in &current {
+            if !previous.contains_key(point) {
+                changes.push(DiskChange::Mounted {
+                    mount: mount.clone(),
+                });
+            }
+            let is_above = mount.usage() >= threshold;
+            if is_above && above.insert(point.clone()) {
+                changes.push(DiskChange::UsageAbove {
+                    mount: mount.clone(),
+                    threshold,
+                });
+            } else if !is_above && above.remove(point) {
+                changes.push(DiskChange::UsageBelow {
+                    mount: mount.clone(),
+                    threshold,
+                });
+            }
+        }
+        changes
+    }
+
+    /// Spec of `DiskService/set_usage_threshold`, served by Mado.
+    pub fn command_specs(self: &Arc<Self>) -> Vec<CommandSpec> {
+        let monitor = self.clone();
+        vec![
+            CommandSpec::new::<f64, ()>(SERVICE, "set_usage_threshold")
+                .with_range(0.0, 1.0)
+                .with_handler(move |threshold| monitor.set_threshold(threshold))
+                .direct(),
+        ]
+    }
+}
+
+/// Event raised for each change `DiskMonitor` finds.
+pub const EVENT: &str = "DiskChanged";
+
+/// Lists the mounts of `service` every `interval` while pages are subscribed
+/// to `DiskChanged`, raising the changes `monitor` finds to every subscriber.
+pub fn register_provider(
+    scheduler: &Scheduler,
+    service: Arc<dyn DiskService + Send + Sync>,
+    monitor: Arc<DiskMonitor>,
+    interval: Duration,
+    raise: impl Fn(SubscriberId, Event) + Send + Sync + 'static,
+) {
+    scheduler.register_polled(
+        EVENT,
+        SERVICE,
+        interval,
+        move || service.list_mounts(),
+        move |mounts| {
+            let changes = monitor.changes(mounts);
+            changes.into_iter().map(Event::DiskChanged).collect()
+        },
+        raise,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mount(point: &str, used: u64) -> Mount {
+        Mount {
+            device: "/dev/sda1".into(),
+            mount_point: point.into(),
+            fs_type: "ext4".into(),
+            total: 100,
+            used,
+            available: 100 - used,
+            removable: false,
+        }
+    }
+
+    #[test]
+    fn reports_mounts_appearing_and_disappearing() {
+        let monitor = DiskMonitor::default();
+        assert!(
+            monitor
+                .changes(vec![mount("/", 10), mount("/home", 10)])
+                .is_empty()
+        );
+        assert!(
+            monitor
+                .changes(vec![mount("/", 20), mount("/home", 10)])
+                .is_empty()
+        );
+
+        let changes = monitor.changes(vec![mount("/", 20), mount("/media/usb", 5)]);
+        assert_eq!(
+            changes,
+            vec![
+                DiskChange::Unmounted {
+                    mount: mount("/home", 10)
+                },
+                DiskChange::Mounted {
+                    mount: mount("/media/usb", 5)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_threshold_crossings_once() {
+        let monitor = DiskMonitor::new(0.8);
+        // Already full on the first poll: nothing to report, the page lists it
+        monitor.changes(vec![mount("/", 85), mount("/home", 50)]);
+        assert!(
+            monitor
+                .changes(vec![mount("/", 90), mount("/home", 50)])
+                .is_empty()
+        );
+
+        let changes = monitor.changes(vec![mount("/", 70), mount("/home", 80)]);
+        assert_eq!(
+            changes,
+            vec![
+                DiskChange::UsageBelow {
+                    mount: mount("/", 70),
+                    threshold: 0.8
+                },
+                DiskChange::UsageAbove {
+                    mount: mount("/home", 80),
+                    threshold: 0.8
+                },
+            ]
+        );
+
+        monitor.set_threshold(0.6);
+        let changes = monitor.changes(vec![mount("/", 70), mount("/home", 80)]);
+        assert_eq!(changes.len(), 1);
+        assert!(
+            matches!(&changes[0], DiskChange::UsageAbove { mount, .. } if mount.mount_point == "/")
+        );
+    }
+
+    #[test]
+    fn serializes_changes_with_their_kind() {
+        let change = DiskChange::Unmounted {
+            mount: mount("/media/usb", 5),
+        };
+        let json = serde_json::to_value(&change).unwrap();
+        assert_eq!(json["change"], "Unmounted");
+        assert_eq!(json["mount"]["mount_point"], "/media/usb");
+    }
+}
@@ -0,0 +1,321 @@
+//! Messages between pages.
+//!
+//! Pages post JSON messages on named channels with `messages/post`; every page
+//! subscribed to the channel with `messages/subscribe` receives them as
+//! [`Event::Message`](crate::events::Event::Message), the sender included if it
+//! subscribed too. A message posted with `retain` is kept as the value of its
+//! channel and handed to pages subscribing later, so a page loading after the
+//! sender still learns the current state.
+//!
+//! The host owns one [`MessageBus`] for all its pages and connects each page
+//! with [`MessageBus::connect`].
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    events::{Event, EventRaiser},
+    protocol::CommandSpec,
+};
+
+/// Payload of [`Event::Message`](crate::events::Event::Message).
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct Message {
+    /// Channel the message was posted on
+    #[schemars(example = &"now-playing")]
+    pub channel: String,
+    /// JSON payload, `null` when the sender gave none
+    #[schemars(example = serde_json::json!({ "session": "spotify" }))]
+    pub data: Value,
+    /// `true` when this is the retained value of the channel, delivered on subscription
+    pub retained: bool,
+}
+
+/// Argument of `messages/post`.
+#[derive(Debug, Clone, PartialEq, Deserialize, JsonSchema)]
+pub struct PostMessage {
+    #[schemars(example = &"now-playing")]
+    pub channel: String,
+    #[serde(default)]
+    pub data: Value,
+    /// Keep the message for pages subscribing later. Retaining `null` forgets
+    /// the retained value of the channel.
+    #[serde(default)]
+    pub retain: bool,
+}
+
+pub type SubscriberId = u64;
+
+type Raiser = Arc<dyn EventRaiser + Send + Sync>;
+
+struct Subscriber {
+    raiser: Raiser,
+    channels: HashSet<String>,
+}
+
+#[derive(Default)]
+struct State {
+    next_id: SubscriberId,
+    subscribers: HashMap<SubscriberId, Subscriber>,
+    retained: HashMap<String, Value>,
+}
+
+/// Channels shared by every page of a host.
+#[derive(Default)]
+pub struct MessageBus {
+    state: Mutex<State>,
+}
+
+impl MessageBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Connects a page, whose messages are raised through `raiser`.
+    /// The page is disconnected when the client is dropped.
+    pub fn connect(self: &Arc<Self>, raiser: Raiser) -> MessageClient {
+        let mut state = self.state.lock().unwrap();
+        state.next_id += 1;
+        let id = state.next_id;
+        state.subscribers.insert(
+            id,
+            Subscriber {
+                raiser,
+                channels: HashSet::new(),
+            },
+        );
+        MessageClient {
+            id,
+            bus: self.clone(),
+        }
+    }
+
+    /// Delivers a message to the subscribers of its channel and returns how
+    /// many received it.
+    pub fn post(&self, message: PostMessage) -> usize {
+        let receivers: Vec<Raiser> = {
+            let mut state = self.state.lock().unwrap();
+            if message.retain {
+                if message.data.is_null() {
+                    state.retained.remove(&message.channel);
+                } else {
+                    state
+                        .retained
+                        .insert(message.channel.clone(), message.data.clone());
+                }
+            }
+            state
+                .subscribers
+                .values()
+                .filter(|s| s.channels.contains(&message.channel))
+                .map(|s| s.raiser.clone())
+                .collect()
+        };
+        // Raised outside of the lock, so pages may post from their event handlers
+        for raiser in &receivers {
+            raiser.raise_event(Event::Message(Message {
+                channel: message.channel.clone(),
+                data: message.data.clone(),
+                retained: false,
+            }));
+        }
+        receivers.len()
+    }
+
+    /// Retained value of `channel`, if any.
+    pub fn retained(&self, channel: &str) -> Option<Value> {
+        self.state.lock().unwrap().retained.get(channel).cloned()
+    }
+}
+
+/// Connection of one page to a [`MessageBus`].
+pub struct MessageClient {
+    id: SubscriberId,
+    bus: Arc<MessageBus>,
+}
+
+impl MessageClient {
+    pub fn id(&self) -> SubscriberId {
+        self.id
+    }
+
+    /// Subscribes to `channel`, raising its retained value right away.
+    /// Returns `false` if the page was already subscribed.
+    pub fn subscribe(&self, channel: &str) -> bool {
+        let (raiser, retained) = {
+            let mut state = self.bus.state.lock().unwrap();
+            let retained = state.retained.get(channel).cloned();
+            let Some(subscriber) = state.subscribers.get_mut(&self.id) else {
+                return false;
+            };
+            if !subscriber.channels.insert(channel.to_string()) {
+                return false;
+            }
+            (subscriber.raiser.clone(), retained)
+        };
+        if let Some(data) = retained {
+            raiser.raise_event(Event::Message(Message {
+                channel: channel.to_string(),
+                data,
+                retained: true,
+            }));
+        }
+        true
+    }
+
+    /// Returns `false` if the page wasn't subscribed to `channel`.
+    pub fn unsubscribe(&self, channel: &str) -> bool {
+        let mut state = self.bus.state.lock().unwrap();
+        state
+            .subscribers
+            .get_mut(&self.id)
+            .is_some_and(|s| s.channels.remove(channel))
+    }
+
+    pub fn post(&self, message: PostMessage) -> usize {
+        self.bus.post(message)
+    }
+
+    /// Specs of `messages/subscribe`, `messages/unsubscribe` and
+    /// `messages/post`, served by Mado.
+    pub fn command_specs(self: &Arc<Self>) -> Vec<CommandSpec> {
+        let (subscribe, unsubscribe, post) = (self.clone(), self.clone(), self.clone());
+        vec![
+            CommandSpec::new::<String, bool>("messages", "subscribe")
+                .with_handler(move |channel: String| subscribe.subscribe(&channel))
+                .direct(),
+            CommandSpec::new::<String, bool>("messages", "unsubscribe")
+                .with_handler(move |channel: String| unsubscribe.unsubscribe(&channel))
+                .direct(),
+            // Answers with the number of pages that received the message
+            CommandSpec::new::<PostMessage, usize>("messages", "post")
+                .with_handler(move |message| post.post(message))
+                .direct(),
+        ]
+    }
+}
+
+impl Drop for MessageClient {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.bus.state.lock() {
+            state.subscribers.remove(&self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::CommandRegistry;
+    use serde_json::json;
+    use std::sync::mpsc::{Receiver, Sender, channel};
+
+    struct ChannelRaiser(Mutex<Sender<Event>>);
+
+    impl EventRaiser for ChannelRaiser {
+        fn raise_event(&self, event: Event) {
+            let _ = self.0.lock().unwrap().send(event);
+        }
+    }
+
+    fn page(bus: &Arc<MessageBus>) -> (MessageClient, Receiver<Event>) {
+        let (tx, rx) = channel();
+        (bus.connect(Arc::new(ChannelRaiser(Mutex::new(tx)))), rx)
+    }
+
+    fn messages(events: &Receiver<Event>) -> Vec<Message> {
+        events
+            .try_iter()
+            .map(|event| match event {
+                Event::Message(message) => message,
+                _ => panic!("expected a message"),
+            })
+            .collect()
+    }
+
+    fn post(channel: &str, data: Value, retain: bool) -> PostMessage {
+        PostMessage {
+            channel: channel.to_string(),
+            data,
+            retain,
+        }
+    }
+
+    #[test]
+    fn delivers_to_subscribers_only() {
+        let bus = Arc::new(MessageBus::new());
+        let (music, music_events) = page(&bus);
+        let (visualizer, visualizer_events) = page(&bus);
+        let (_clock, clock_events) = page(&bus);
+
+        assert!(visualizer.subscribe("now-playing"));
+        assert!(!visualizer.subscribe("now-playing"));
+        assert_eq!(music.post(post("now-playing", json!("spotify"), false)), 1);
+
+        assert!(messages(&music_events).is_empty());
+        assert!(messages(&clock_events).is_empty());
+        assert_eq!(
+            messages(&visualizer_events),
+            vec![Message {
+                channel: "now-playing".into(),
+                data: json!("spotify"),
+                retained: false,
+            }]
+        );
+
+        assert!(visualizer.unsubscribe("now-playing"));
+        assert!(!visualizer.unsubscribe("now-playing"));
+        assert_eq!(music.post(post("now-playing", json!("mpd"), false)), 0);
+        // Disconnected pages receive nothing either
+        visualizer.subscribe("now-playing");
+        drop(visualizer);
+        assert_eq!(music.post(post("now-playing", json!("mpd"), false)), 0);
+    }
+
+    #[test]
+    fn hands_retained_values_to_late_subscribers() {
+        let bus = Arc::new(MessageBus::new());
+        let (music, _) = page(&bus);
+        music.post(post("now-playing", json!({ "session": "spotify" }), true));
+        music.post(post("now-playing", json!("not retained"), false));
+        assert_eq!(
+            bus.retained("now-playing"),
+            Some(json!({ "session": "spotify" }))
+        );
+
+        let (visualizer, events) = page(&bus);
+        visualizer.subscribe("now-playing");
+        let received = messages(&events);
+        assert_eq!(received.len(), 1);
+        assert!(received[0].retained);
+        assert_eq!(received[0].data, json!({ "session": "spotify" }));
+
+        music.post(post("now-playing", Value::Null, true));
+        assert_eq!(bus.retained("now-playing"), None);
+    }
+
+    #[test]
+    fn posts_from_commands() {
+        let bus = Arc::new(MessageBus::new());
+        let (sender, sender_events) = page(&bus);
+        let mut registry = CommandRegistry::new();
+        registry.extend(Arc::new(sender).command_specs());
+
+        let subscribed = registry.invoke("messages/subscribe", json!("echo"));
+        assert_eq!(subscribed, Ok(json!(true)));
+        let delivered = registry.invoke("messages/post", json!({ "channel": "echo" }));
+        assert_eq!(delivered, Ok(json!(1)));
+        assert_eq!(messages(&sender_events)[0].data, Value::Null);
+        assert!(
+            registry
+                .invoke("messages/post", json!({ "data": 1 }))
+                .is_err()
+        );
+    }
+}
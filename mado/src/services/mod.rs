@@ -1,5 +1,17 @@
+#[cfg(feature = "covers")]
+pub mod covers;
+pub mod disks;
 pub mod host;
 pub mod mado_version;
+pub mod messaging;
 pub mod music_player;
+pub mod music_sources;
+pub mod network;
+pub mod power;
+pub mod processes;
+pub mod published_values;
+pub mod sensors;
+pub mod system_metrics;
+pub mod variable_watch;
 
 pub mod shared_impls;
@@ -1,6 +1,10 @@
 use schemars::JsonSchema;
 use serde::Serialize;
 
+use std::sync::Arc;
+
+use crate::{executor::BoxFuture, protocol::CommandSpec};
+
 pub trait MusicPlayerService {
     fn play(&self);
     fn pause(&self);
@@ -15,6 +19,119 @@ pub trait MusicPlayerService {
     fn get_data(&self) -> MusicPlayerState;
 }
 
+/// Async counterpart of [`MusicPlayerService`], for players backed by sockets,
+/// D-Bus or files. Every `MusicPlayerService` is also an `AsyncMusicPlayerService`,
+/// doing its work when the future is polled.
+pub trait AsyncMusicPlayerService: Send + Sync {
+    fn play(&self) -> BoxFuture<'_, ()>;
+    fn pause(&self) -> BoxFuture<'_, ()>;
+    fn next(&self) -> BoxFuture<'_, ()>;
+    fn previous(&self) -> BoxFuture<'_, ()>;
+    /// Sets the volume to a percentage (0.0 to 1.0).
+    fn set_volume(&self, volume: f64) -> BoxFuture<'_, ()>;
+    /// Seeks to a position in the track, where position is a percentage (0.0 to 1.0).
+    fn seek_absolute(&self, position: f64) -> BoxFuture<'_, ()>;
+    fn get_data(&self) -> BoxFuture<'_, MusicPlayerState>;
+}
+
+impl<T: MusicPlayerService + Send + Sync> AsyncMusicPlayerService for T {
+    fn play(&self) -> BoxFuture<'_, ()> {
+        Box::pin(async move { MusicPlayerService::play(self) })
+    }
+    fn pause(&self) -> BoxFuture<'_, ()> {
+        Box::pin(async move { MusicPlayerService::pause(self) })
+    }
+    fn next(&self) -> BoxFuture<'_, ()> {
+        Box::pin(async move { MusicPlayerService::next(self) })
+    }
+    fn previous(&self) -> BoxFuture<'_, ()> {
+        Box::pin(async move { MusicPlayerService::previous(self) })
+    }
+    fn set_volume(&self, volume: f64) -> BoxFuture<'_, ()> {
+        Box::pin(async move { MusicPlayerService::set_volume(self, volume) })
+    }
+    fn seek_absolute(&self, position: f64) -> BoxFuture<'_, ()> {
+        Box::pin(async move { MusicPlayerService::seek_absolute(self, position) })
+    }
+    fn get_data(&self) -> BoxFuture<'_, MusicPlayerState> {
+        Box::pin(async move { MusicPlayerService::get_data(self) })
+    }
+}
+
+/// Specs of the `MusicPlayerService` commands, for validation and batching.
+pub fn command_specs(service: &'static (dyn MusicPlayerService + Sync)) -> Vec<CommandSpec> {
+    const SERVICE: &str = "MusicPlayerService";
+    vec![
+        CommandSpec::without_args::<()>(SERVICE, "play").with_handler(move |()| service.play()),
+        CommandSpec::without_args::<()>(SERVICE, "pause").with_handler(move |()| service.pause()),
+        CommandSpec::without_args::<()>(SERVICE, "next").with_handler(move |()| service.next()),
+        CommandSpec::without_args::<()>(SERVICE, "previous")
+            .with_handler(move |()| service.previous()),
+        CommandSpec::new::<f64, ()>(SERVICE, "set_volume")
+            .with_range(0.0, 1.0)
+            .with_handler(move |volume| service.set_volume(volume)),
+        CommandSpec::new::<f64, ()>(SERVICE, "seek_absolute")
+            .with_range(0.0, 1.0)
+            .with_handler(move |position| service.seek_absolute(position)),
+        CommandSpec::without_args::<MusicPlayerState>(SERVICE, "get_data")
+            .with_handler(move |()| service.get_data()),
+    ]
+}
+
+/// Specs serving the `MusicPlayerService` commands from an async service.
+/// The commands are answered by Mado on the registry's executor.
+pub fn async_command_specs(service: Arc<dyn AsyncMusicPlayerService>) -> Vec<CommandSpec> {
+    const SERVICE: &str = "MusicPlayerService";
+    let s = service.clone();
+    let play = move |()| {
+        let s = s.clone();
+        async move { s.play().await }
+    };
+    let s = service.clone();
+    let pause = move |()| {
+        let s = s.clone();
+        async move { s.pause().await }
+    };
+    let s = service.clone();
+    let next = move |()| {
+        let s = s.clone();
+        async move { s.next().await }
+    };
+    let s = service.clone();
+    let previous = move |()| {
+        let s = s.clone();
+        async move { s.previous().await }
+    };
+    let s = service.clone();
+    let set_volume = move |volume| {
+        let s = s.clone();
+        async move { s.set_volume(volume).await }
+    };
+    let s = service.clone();
+    let seek_absolute = move |position| {
+        let s = s.clone();
+        async move { s.seek_absolute(position).await }
+    };
+    let get_data = move |()| {
+        let s = service.clone();
+        async move { s.get_data().await }
+    };
+    vec![
+        CommandSpec::without_args::<()>(SERVICE, "play").with_async_handler(play),
+        CommandSpec::without_args::<()>(SERVICE, "pause").with_async_handler(pause),
+        CommandSpec::without_args::<()>(SERVICE, "next").with_async_handler(next),
+        CommandSpec::without_args::<()>(SERVICE, "previous").with_async_handler(previous),
+        CommandSpec::new::<f64, ()>(SERVICE, "set_volume")
+            .with_range(0.0, 1.0)
+            .with_async_handler(set_volume),
+        CommandSpec::new::<f64, ()>(SERVICE, "seek_absolute")
+            .with_range(0.0, 1.0)
+            .with_async_handler(seek_absolute),
+        CommandSpec::without_args::<MusicPlayerState>(SERVICE, "get_data")
+            .with_async_handler(get_data),
+    ]
+}
+
 #[derive(Debug, Clone, Serialize, PartialEq, JsonSchema)]
 pub enum MusicPlayerStatus {
     Stopped,
@@ -24,24 +141,56 @@ pub enum MusicPlayerStatus {
 
 #[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct MusicPlayerState {
+    /// Whether a player is connected to the service
+    #[schemars(example = true)]
     pub is_connected: bool,
+    /// Name of the player providing the data
+    #[schemars(example = &"Spotify")]
     pub player: String,
+    #[schemars(example = &"Song Title")]
     pub title: String,
+    #[schemars(example = &"Artist")]
     pub artist: String,
+    #[schemars(example = &"Album")]
     pub album: String,
     /// URL to the album cover image
+    #[schemars(example = &"https://example.com/cover.jpg")]
     pub cover: String,
     /// Duration in seconds
+    #[schemars(example = &"03:25")]
     pub duration: String,
     /// Position in seconds
+    #[schemars(example = &"01:10")]
     pub position: String,
     /// Prrogress Percentage (0.0 to 1.0)
+    #[schemars(example = 0.34)]
     pub progress: f64,
     /// Volume Percentage (0.0 to 1.0)
+    #[schemars(example = 0.8)]
     pub volume: f64,
+    #[schemars(example = MusicPlayerStatus::Playing)]
     pub status: MusicPlayerStatus,
 }
 
+impl MusicPlayerState {
+    /// State of pages while no player is running.
+    pub fn disconnected() -> Self {
+        Self {
+            is_connected: false,
+            player: "No Player".to_string(),
+            title: String::new(),
+            artist: String::new(),
+            album: String::new(),
+            cover: String::new(),
+            duration: "00:00".to_string(),
+            position: "00:00".to_string(),
+            progress: 0.0,
+            volume: 0.0,
+            status: MusicPlayerStatus::Stopped,
+        }
+    }
+}
+
 impl PartialEq for MusicPlayerState {
     fn eq(&self, other: &Self) -> bool {
         self.is_connected == other.is_connected &&
@@ -58,3 +207,66 @@ impl PartialEq for MusicPlayerState {
         self.status == other.status
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        executor::{Executor, TestExecutor},
+        protocol::CommandRegistry,
+    };
+    use serde_json::Value;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct FakePlayer {
+        volume: Mutex<f64>,
+    }
+
+    impl MusicPlayerService for FakePlayer {
+        fn play(&self) {}
+        fn pause(&self) {}
+        fn next(&self) {}
+        fn previous(&self) {}
+        fn set_volume(&self, volume: f64) {
+            *self.volume.lock().unwrap() = volume;
+        }
+        fn seek_absolute(&self, _position: f64) {}
+        fn get_data(&self) -> MusicPlayerState {
+            MusicPlayerState::disconnected()
+        }
+    }
+
+    #[test]
+    fn serves_sync_services_asynchronously() {
+        let player = Arc::new(FakePlayer::default());
+        let executor = TestExecutor::new();
+        let mut registry = CommandRegistry::with_executor(Arc::new(executor.clone()));
+        registry.extend(async_command_specs(player.clone()));
+
+        let future = registry.invoke_async("MusicPlayerService/set_volume", Value::from(0.25));
+        executor.spawn(Box::pin(async move {
+            future.await.unwrap();
+        }));
+        // Nothing happens until the executor polls the command
+        assert_eq!(*player.volume.lock().unwrap(), 0.0);
+        executor.run_until_stalled();
+        assert_eq!(*player.volume.lock().unwrap(), 0.25);
+
+        let state = Arc::new(Mutex::new(None));
+        let (future, s) = (
+            registry.invoke_async("MusicPlayerService/get_data", Value::Null),
+            state.clone(),
+        );
+        executor.spawn(Box::pin(async move {
+            *s.lock().unwrap() = Some(future.await.unwrap());
+        }));
+        executor.run_until_stalled();
+        let state = state.lock().unwrap().take().unwrap();
+        assert_eq!(
+            state,
+            serde_json::to_value(MusicPlayerState::disconnected()).unwrap()
+        );
+        assert_eq!(state["is_connected"], false);
+    }
+}
@@ -0,0 +1,470 @@
+//! Several music players behind one [`MusicPlayerService`].
+//!
+//! Users often have a few players running at once, like browsers through
+//! WebNowPlaying and native players. [`MusicSources`] follows them all and
+//! serves the one its [`SelectionPolicy`] selects, forwarding the commands of
+//! pages to it. Pages subscribed to `MusicSourceChanged` are told when the
+//! selected source changes.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    events::Event,
+    protocol::CommandSpec,
+    scheduler::{Scheduler, SubscriberId},
+    services::music_player::{MusicPlayerService, MusicPlayerState, MusicPlayerStatus},
+};
+
+/// How [`MusicSources`] selects the source it serves.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "policy")]
+pub enum SelectionPolicy {
+    /// The source that started playing last. While none plays, the selected
+    /// source stays selected as long as it is connected.
+    #[default]
+    MostRecentlyPlaying,
+    /// The first playing source in the order the sources were added, else
+    /// the first connected one.
+    Priority,
+    /// Always the source named `source`, even while it isn't connected.
+    Pinned {
+        #[schemars(example = &"MPD")]
+        source: String,
+    },
+}
+
+/// A source as listed by `MusicPlayerService/list_sources`.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct MusicSource {
+    #[schemars(example = &"Browser")]
+    pub name: String,
+    /// Whether commands go to this source
+    #[schemars(example = true)]
+    pub is_active: bool,
+    #[schemars(example = true)]
+    pub is_connected: bool,
+    pub status: MusicPlayerStatus,
+}
+
+/// Payload of [`Event::MusicSourceChanged`](crate::events::Event::MusicSourceChanged).
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct MusicSourceChange {
+    /// Source selected until now, `None` if there was none
+    #[schemars(example = Some("Browser"))]
+    pub previous: Option<String>,
+    /// Source selected from now on, `None` if no source can be selected
+    #[schemars(example = Some("MPD"))]
+    pub source: Option<String>,
+}
+
+struct Source {
+    name: String,
+    service: Arc<dyn MusicPlayerService + Send + Sync>,
+}
+
+#[derive(Default)]
+struct Selection {
+    /// Index of the selected source
+    active: Option<usize>,
+    /// For each source, the update it was first seen playing at, `None`
+    /// while it doesn't play
+    playing_since: Vec<Option<u64>>,
+    updates: u64,
+}
+
+/// A `MusicPlayerService` serving one of several sources.
+pub struct MusicSources {
+    sources: Vec<Source>,
+    policy: Mutex<SelectionPolicy>,
+    selection: Mutex<Selection>,
+}
+
+impl MusicSources {
+    pub fn new(policy: SelectionPolicy) -> Self {
+        Self {
+            sources: Vec::new(),
+            policy: Mutex::new(policy),
+            selection: Mutex::default(),
+        }
+    }
+
+    /// Adds a source named `name`. With [`SelectionPolicy::Priority`], the
+    /// sources added first come first.
+    pub fn with_source(
+        mut self,
+        name: impl Into<String>,
+        service: Arc<dyn MusicPlayerService + Send + Sync>,
+    ) -> Self {
+        self.sources.push(Source {
+            name: name.into(),
+            service,
+        });
+        self
+    }
+
+    pub fn policy(&self) -> SelectionPolicy {
+        self.policy.lock().unwrap().clone()
+    }
+
+    /// Selects sources with `policy` from the next update. Pinning a source
+    /// that wasn't added is an error.
+    pub fn set_policy(&self, policy: SelectionPolicy) -> Result<(), String> {
+        if let SelectionPolicy::Pinned { source } = &policy
+            && !self.sources.iter().any(|s| &s.name == source)
+        {
+            return Err(format!("No music source named {source}"));
+        }
+        *self.policy.lock().unwrap() = policy;
+        Ok(())
+    }
+
+    /// Name of the selected source, as of the last update.
+    pub fn active(&self) -> Option<String> {
+        let active = self.selection.lock().unwrap().active;
+        active.map(|index| self.sources[index].name.clone())
+    }
+
+    /// Reads every source and selects one with the policy, returning the
+    /// states of the sources.
+    pub fn update(&self) -> Vec<MusicPlayerState> {
+        let states: Vec<MusicPlayerState> = self
+            .sources
+            .iter()
+            .map(|source| source.service.get_data())
+            .collect();
+        let policy = self.policy();
+        let mut selection = self.selection.lock().unwrap();
+        selection.updates += 1;
+        let update = selection.updates;
+        selection.playing_since.resize(self.sources.len(), None);
+        for (since, state) in selection.playing_since.iter_mut().zip(&states) {
+            *since = match state.status {
+                MusicPlayerStatus::Playing if state.is_connected => since.or(Some(update)),
+                _ => None,
+            };
+        }
+
+        let playing = |index: &usize| selection.playing_since[*index].is_some();
+        let connected = |index: &usize| states[*index].is_connected;
+        let indices = 0..self.sources.len();
+        selection.active = match policy {
+            SelectionPolicy::MostRecentlyPlaying => indices
+                .clone()
+                .filter(playing)
+                // The last one started, the first added among those started together
+                .min_by_key(|&index| (std::cmp::Reverse(selection.playing_since[index]), index))
+                .or(selection.active.filter(connected))
+                .or_else(|| indices.clone().find(connected)),
+            SelectionPolicy::Priority => indices
+                .clone()
+                .find(playing)
+                .or_else(|| indices.clone().find(connected)),
+            SelectionPolicy::Pinned { source } => indices
+                .clone()
+                .find(|&index| self.sources[index].name == source),
+        };
+        states
+    }
+
+    /// Lists the sources, updating the selection.
+    pub fn list_sources(&self) -> Vec<MusicSource> {
+        let states = self.update();
+        let active = self.selection.lock().unwrap().active;
+        self.sources
+            .iter()
+            .zip(states)
+            .enumerate()
+            .map(|(index, (source, state))| MusicSource {
+                name: source.name.clone(),
+                is_active: active == Some(index),
+                is_connected: state.is_connected,
+                status: state.status,
+            })
+            .collect()
+    }
+
+    /// Runs `f` on the selected source, selecting one first if no update
+    /// happened yet.
+    fn forward(&self, f: impl FnOnce(&dyn MusicPlayerService)) {
+        let mut active = self.selection.lock().unwrap().active;
+        if active.is_none() {
+            self.update();
+            active = self.selection.lock().unwrap().active;
+        }
+        if let Some(index) = active {
+            f(&*self.sources[index].service);
+        }
+    }
+
+    /// Specs of `MusicPlayerService/list_sources` and
+    /// `MusicPlayerService/set_source_policy`, served by Mado.
+    pub fn command_specs(self: &Arc<Self>) -> Vec<CommandSpec> {
+        let sources = self.clone();
+        let s = self.clone();
+        vec![
+            CommandSpec::without_args::<Vec<MusicSource>>(SERVICE, "list_sources")
+                .with_handler(move |()| s.list_sources())
+                .direct(),
+            CommandSpec::new::<SelectionPolicy, ()>(SERVICE, "set_source_policy")
+                .with_fallible_handler(move |policy| sources.set_policy(policy))
+                .direct(),
+        ]
+    }
+}
+
+impl MusicPlayerService for MusicSources {
+    fn play(&self) {
+        self.forward(|source| source.play());
+    }
+
+    fn pause(&self) {
+        self.forward(|source| source.pause());
+    }
+
+    fn next(&self) {
+        self.forward(|source| source.next());
+    }
+
+    fn previous(&self) {
+        self.forward(|source| source.previous());
+    }
+
+    fn set_volume(&self, volume: f64) {
+        self.forward(|source| source.set_volume(volume));
+    }
+
+    fn seek_absolute(&self, position: f64) {
+        self.forward(|source| source.seek_absolute(position));
+    }
+
+    fn get_data(&self) -> MusicPlayerState {
+        let mut states = self.update();
+        match self.selection.lock().unwrap().active {
+            Some(index) => states.swap_remove(index),
+            None => MusicPlayerState::disconnected(),
+        }
+    }
+}
+
+const SERVICE: &str = "MusicPlayerService";
+
+/// Event raised when commands start going to another source.
+pub const EVENT: &str = "MusicSourceChanged";
+
+/// Updates `sources` every `interval` while pages are subscribed to
+/// `MusicSourceChanged`, raising it to every subscriber when the selected
+/// source differs from the previous poll. The first poll only takes note of
+/// it: pages ask for it with `list_sources`.
+pub fn register_provider(
+    scheduler: &Scheduler,
+    sources: Arc<MusicSources>,
+    interval: Duration,
+    raise: impl Fn(SubscriberId, Event) + Send + Sync + 'static,
+) {
+    let last: Mutex<Option<Option<String>>> = Mutex::default();
+    scheduler.register(EVENT, interval, move |subscribers| {
+        sources.update();
+        let source = sources.active();
+        let previous = last.lock().unwrap().replace(source.clone());
+        let Some(previous) = previous.filter(|previous| *previous != source) else {
+            return;
+        };
+        let change = MusicSourceChange { previous, source };
+        for &subscriber in subscribers {
+            raise(subscriber, Event::MusicSourceChanged(change.clone()));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scheduler::FakeClock;
+
+    struct FakeSource {
+        state: Mutex<MusicPlayerState>,
+        calls: Mutex<Vec<String>>,
+    }
+
+    impl FakeSource {
+        fn new() -> Arc<Self> {
+            Arc::new(Self {
+                state: Mutex::new(MusicPlayerState::disconnected()),
+                calls: Mutex::default(),
+            })
+        }
+
+        fn set(&self, status: Option<MusicPlayerStatus>) {
+            let mut state = self.state.lock().unwrap();
+            state.is_connected = status.is_some();
+            state.status = status.unwrap_or(MusicPlayerStatus::Stopped);
+        }
+
+        fn calls(&self) -> Vec<String> {
+            self.calls.lock().unwrap().clone()
+        }
+    }
+
+    impl MusicPlayerService for FakeSource {
+        fn play(&self) {
+            self.calls.lock().unwrap().push("play".into());
+        }
+        fn pause(&self) {}
+        fn next(&self) {}
+        fn previous(&self) {}
+        fn set_volume(&self, volume: f64) {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("set_volume {volume}"));
+        }
+        fn seek_absolute(&self, _position: f64) {}
+        fn get_data(&self) -> MusicPlayerState {
+            self.state.lock().unwrap().clone()
+        }
+    }
+
+    fn sources(policy: SelectionPolicy) -> (MusicSources, [Arc<FakeSource>; 3]) {
+        let fakes = [FakeSource::new(), FakeSource::new(), FakeSource::new()];
+        let sources = MusicSources::new(policy)
+            .with_source("Browser", fakes[0].clone())
+            .with_source("MPD", fakes[1].clone())
+            .with_source("Spotify", fakes[2].clone());
+        (sources, fakes)
+    }
+
+    fn active(sources: &MusicSources) -> Option<String> {
+        sources.update();
+        sources.active()
+    }
+
+    #[test]
+    fn selects_the_source_that_started_playing_last() {
+        use MusicPlayerStatus::*;
+        let (sources, [browser, mpd, spotify]) = sources(SelectionPolicy::default());
+        assert_eq!(active(&sources), None);
+        assert_eq!(sources.get_data(), MusicPlayerState::disconnected());
+
+        browser.set(Some(Paused));
+        mpd.set(Some(Stopped));
+        assert_eq!(active(&sources).as_deref(), Some("Browser"));
+        mpd.set(Some(Playing));
+        assert_eq!(active(&sources).as_deref(), Some("MPD"));
+        browser.set(Some(Playing));
+        assert_eq!(active(&sources).as_deref(), Some("Browser"));
+        // Still playing, Spotify starting later takes over
+        spotify.set(Some(Playing));
+        assert_eq!(active(&sources).as_deref(), Some("Spotify"));
+
+        // Pausing keeps the source selected until another one plays
+        spotify.set(Some(Paused));
+        browser.set(Some(Paused));
+        mpd.set(Some(Paused));
+        assert_eq!(active(&sources).as_deref(), Some("Spotify"));
+        spotify.set(None);
+        assert_eq!(active(&sources).as_deref(), Some("Browser"));
+    }
+
+    #[test]
+    fn selects_by_priority_or_pin() {
+        use MusicPlayerStatus::*;
+        let (sources, [browser, mpd, spotify]) = sources(SelectionPolicy::Priority);
+        spotify.set(Some(Playing));
+        mpd.set(Some(Playing));
+        browser.set(Some(Paused));
+        assert_eq!(active(&sources).as_deref(), Some("MPD"));
+        mpd.set(Some(Paused));
+        assert_eq!(active(&sources).as_deref(), Some("Spotify"));
+        spotify.set(None);
+        assert_eq!(active(&sources).as_deref(), Some("Browser"));
+
+        let pin = |source: &str| {
+            sources.set_policy(SelectionPolicy::Pinned {
+                source: source.into(),
+            })
+        };
+        pin("Spotify").unwrap();
+        assert_eq!(active(&sources).as_deref(), Some("Spotify"));
+        assert!(!sources.get_data().is_connected);
+        assert_eq!(pin("Radio"), Err("No music source named Radio".to_string()));
+        assert_eq!(
+            sources.policy(),
+            SelectionPolicy::Pinned {
+                source: "Spotify".into()
+            }
+        );
+
+        let json = serde_json::json!({ "policy": "Pinned", "source": "MPD" });
+        let policy: SelectionPolicy = serde_json::from_value(json).unwrap();
+        assert_eq!(
+            policy,
+            SelectionPolicy::Pinned {
+                source: "MPD".into()
+            }
+        );
+    }
+
+    #[test]
+    fn forwards_commands_to_the_selected_source() {
+        use MusicPlayerStatus::*;
+        let (sources, [browser, mpd, _]) = sources(SelectionPolicy::default());
+        browser.set(Some(Paused));
+        mpd.set(Some(Playing));
+        // Selects before the first command
+        sources.set_volume(0.5);
+        browser.set(Some(Playing));
+        sources.update();
+        sources.play();
+        assert_eq!(mpd.calls(), vec!["set_volume 0.5"]);
+        assert_eq!(browser.calls(), vec!["play"]);
+
+        let listed = sources.list_sources();
+        assert_eq!(
+            listed.iter().map(|s| s.is_active).collect::<Vec<_>>(),
+            vec![true, false, false]
+        );
+        assert!(!listed[2].is_connected);
+    }
+
+    #[test]
+    fn raises_source_changes() {
+        use MusicPlayerStatus::*;
+        let (sources, [browser, mpd, _]) = sources(SelectionPolicy::default());
+        let sources = Arc::new(sources);
+        let clock = Arc::new(FakeClock::new());
+        let scheduler = Scheduler::new(clock.clone());
+        let raised = Arc::new(Mutex::new(Vec::new()));
+        let record = raised.clone();
+        register_provider(
+            &scheduler,
+            sources.clone(),
+            Duration::from_secs(1),
+            move |_, event| match event {
+                Event::MusicSourceChanged(change) => record.lock().unwrap().push(change),
+                _ => unreachable!(),
+            },
+        );
+        scheduler.subscribe(EVENT, 1).unwrap();
+
+        browser.set(Some(Playing));
+        scheduler.tick();
+        for _ in 0..2 {
+            mpd.set(Some(Playing));
+            clock.advance(Duration::from_secs(1));
+            scheduler.tick();
+        }
+        assert_eq!(
+            *raised.lock().unwrap(),
+            vec![MusicSourceChange {
+                previous: Some("Browser".into()),
+                source: Some("MPD".into()),
+            }]
+        );
+    }
+}
@@ -0,0 +1,240 @@
+//! Network interfaces and their throughput.
+//!
+//! Hosts implement [`NetworkService`] for their platform (Yomi reads
+//! `/proc/net/dev` and `/sys/class/net` on Linux). Kernels only keep byte
+//! counters, so rates are computed from consecutive samples with a
+//! [`RateTracker`], which also copes with counters wrapping around.
+
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::{
+    events::Event,
+    protocol::CommandSpec,
+    scheduler::{Scheduler, SubscriberId},
+};
+
+pub trait NetworkService {
+    /// Reads the interfaces. Rates are measured since the previous call, so
+    /// the first call reports them as `0`.
+    fn get_network(&self) -> Result<NetworkStatus, String>;
+}
+
+const SERVICE: &str = "NetworkService";
+
+/// Spec of `NetworkService/get_network`.
+pub fn command_specs(service: Arc<dyn NetworkService + Send + Sync>) -> Vec<CommandSpec> {
+    vec![
+        CommandSpec::without_args::<NetworkStatus>(SERVICE, "get_network")
+            .with_fallible_handler(move |()| service.get_network()),
+    ]
+}
+
+/// Event carrying the interfaces and rates, raised on every poll.
+pub const EVENT: &str = "NetworkUpdate";
+
+/// Reads `service` every `interval` while pages are subscribed to
+/// `NetworkUpdate`, once for all of them.
+pub fn register_provider(
+    scheduler: &Scheduler,
+    service: Arc<dyn NetworkService + Send + Sync>,
+    interval: Duration,
+    raise: impl Fn(SubscriberId, Event) + Send + Sync + 'static,
+) {
+    scheduler.register_polled(
+        EVENT,
+        SERVICE,
+        interval,
+        move || service.get_network(),
+        |status| vec![Event::NetworkUpdate(status)],
+        raise,
+    );
+}
+
+/// Rates in bytes per second.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct NetworkStatus {
+    /// Download rate of all interfaces but loopback
+    #[schemars(example = 125000.0)]
+    pub download: f64,
+    /// Upload rate of all interfaces but loopback
+    #[schemars(example = 20000.0)]
+    pub upload: f64,
+    pub interfaces: Vec<NetworkInterface>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct NetworkInterface {
+    #[schemars(example = &"wlan0")]
+    pub name: String,
+    /// Whether the link is up and carrying traffic
+    #[schemars(example = true)]
+    pub is_up: bool,
+    #[schemars(example = false)]
+    pub is_loopback: bool,
+    /// Hardware address, if the interface has one
+    #[schemars(example = &"3c:22:fb:12:34:56")]
+    pub mac: Option<String>,
+    /// IPv4 and IPv6 addresses
+    #[schemars(example = vec!["192.168.1.20", "fe80::3e22:fbff:fe12:3456"])]
+    pub addresses: Vec<String>,
+    /// Bytes received since the interface came up
+    #[schemars(example = 1_500_000_000u64)]
+    pub rx_bytes: u64,
+    /// Bytes sent since the interface came up
+    #[schemars(example = 200_000_000u64)]
+    pub tx_bytes: u64,
+    #[schemars(example = 125000.0)]
+    pub rx_rate: f64,
+    #[schemars(example = 20000.0)]
+    pub tx_rate: f64,
+}
+
+impl NetworkStatus {
+    /// Status of `interfaces`, with the totals of the non-loopback ones.
+    pub fn new(interfaces: Vec<NetworkInterface>) -> Self {
+        let external = || interfaces.iter().filter(|i| !i.is_loopback);
+        Self {
+            download: external().map(|i| i.rx_rate).sum(),
+            upload: external().map(|i| i.tx_rate).sum(),
+            interfaces,
+        }
+    }
+}
+
+/// How close to its maximum a counter going backwards must have been to have
+/// wrapped around, rather than having been reset.
+const WRAP_MARGIN: u64 = 1 << 30;
+
+/// Bytes a counter moved from `previous` to `current`. A counter going
+/// backwards wrapped around when it was close to the maximum of 32 or 64 bit
+/// counters; otherwise it was reset, like when the interface goes down and
+/// up, and counted from 0.
+pub fn counter_delta(previous: u64, current: u64) -> u64 {
+    let max32 = u64::from(u32::MAX);
+    if current >= previous {
+        current - previous
+    } else if previous <= max32 && max32 - previous < WRAP_MARGIN {
+        max32 - previous + current + 1
+    } else if u64::MAX - previous < WRAP_MARGIN {
+        current.wrapping_sub(previous)
+    } else {
+        current
+    }
+}
+
+/// Turns the byte counters of interfaces into rates.
+#[derive(Debug, Default)]
+pub struct RateTracker {
+    /// Time and `(rx, tx)` counters of the previous sample of each interface
+    samples: HashMap<String, (Instant, u64, u64)>,
+}
+
+impl RateTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the counters of `interface` at `now` and returns the
+    /// `(rx, tx)` rates since its previous sample, `0` for the first one.
+    pub fn update(&mut self, interface: &str, now: Instant, rx: u64, tx: u64) -> (f64, f64) {
+        let previous = self.samples.insert(interface.to_string(), (now, rx, tx));
+        let Some((then, last_rx, last_tx)) = previous else {
+            return (0.0, 0.0);
+        };
+        let elapsed = now.saturating_duration_since(then).as_secs_f64();
+        if elapsed == 0.0 {
+            return (0.0, 0.0);
+        }
+        (
+            counter_delta(last_rx, rx) as f64 / elapsed,
+            counter_delta(last_tx, tx) as f64 / elapsed,
+        )
+    }
+
+    /// Forgets the interfaces not in `interfaces`, e.g. unplugged adapters.
+    pub fn retain(&mut self, interfaces: &[&str]) {
+        self.samples
+            .retain(|name, _| interfaces.contains(&name.as_str()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computes_rates_from_samples() {
+        let mut tracker = RateTracker::new();
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+
+        assert_eq!(tracker.update("eth0", at(0), 1000, 500), (0.0, 0.0));
+        assert_eq!(tracker.update("eth0", at(500), 2000, 500), (2000.0, 0.0));
+        assert_eq!(
+            tracker.update("eth0", at(1500), 5000, 1500),
+            (3000.0, 1000.0)
+        );
+        // Same instant twice: no time to measure a rate over
+        assert_eq!(tracker.update("eth0", at(1500), 6000, 1500), (0.0, 0.0));
+
+        tracker.retain(&["wlan0"]);
+        assert_eq!(tracker.update("eth0", at(2000), 9000, 1500), (0.0, 0.0));
+    }
+
+    #[test]
+    fn handles_counters_wrapping() {
+        let max32 = u64::from(u32::MAX);
+        assert_eq!(counter_delta(max32 - 99, 100), 200);
+        assert_eq!(counter_delta(u64::MAX - 9, 5), 15);
+        assert_eq!(counter_delta(10, 10), 0);
+
+        let mut tracker = RateTracker::new();
+        let start = Instant::now();
+        let one_second = start + Duration::from_secs(1);
+        tracker.update("ppp0", start, max32 - 999, 0);
+        assert_eq!(tracker.update("ppp0", one_second, 1000, 0), (2000.0, 0.0));
+    }
+
+    #[test]
+    fn handles_counters_reset() {
+        // The interface went down and up, counting from 0 again
+        assert_eq!(counter_delta(3_000_000_000, 100), 100);
+        assert_eq!(counter_delta(50_000_000_000, 100), 100);
+        assert_eq!(counter_delta(1, 0), 0);
+
+        let mut tracker = RateTracker::new();
+        let start = Instant::now();
+        let one_second = start + Duration::from_secs(1);
+        tracker.update("eth0", start, 3_000_000_000, 8000);
+        assert_eq!(tracker.update("eth0", one_second, 100, 50), (100.0, 50.0));
+    }
+
+    #[test]
+    fn totals_external_interfaces() {
+        let interface = |name: &str, is_loopback, rx_rate, tx_rate| NetworkInterface {
+            name: name.into(),
+            is_up: true,
+            is_loopback,
+            mac: None,
+            addresses: Vec::new(),
+            rx_bytes: 0,
+            tx_bytes: 0,
+            rx_rate,
+            tx_rate,
+        };
+        let status = NetworkStatus::new(vec![
+            interface("lo", true, 5000.0, 5000.0),
+            interface("eth0", false, 100.0, 10.0),
+            interface("wlan0", false, 50.0, 5.0),
+        ]);
+        assert_eq!(status.download, 150.0);
+        assert_eq!(status.upload, 15.0);
+    }
+}
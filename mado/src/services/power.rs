@@ -0,0 +1,264 @@
+//! AC adapter and batteries.
+//!
+//! Hosts implement [`PowerService`] for their platform (Yomi reads
+//! `/sys/class/power_supply` on Linux). A [`PowerMonitor`] compares successive
+//! readings to raise [`Event::PowerChanged`](crate::events::Event::PowerChanged)
+//! when the AC adapter is plugged or unplugged, or when a discharging battery
+//! reaches the low-battery threshold.
+
+use std::{
+    collections::BTreeSet,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::{
+    events::Event,
+    protocol::CommandSpec,
+    scheduler::{Scheduler, SubscriberId},
+};
+
+pub trait PowerService {
+    fn get_power(&self) -> Result<PowerStatus, String>;
+}
+
+const SERVICE: &str = "PowerService";
+
+/// Spec of `PowerService/get_power`.
+pub fn command_specs(service: Arc<dyn PowerService + Send + Sync>) -> Vec<CommandSpec> {
+    vec![
+        CommandSpec::without_args::<PowerStatus>(SERVICE, "get_power")
+            .with_fallible_handler(move |()| service.get_power()),
+    ]
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct PowerStatus {
+    /// Whether the machine runs from an external supply, always `true`
+    /// for machines without batteries
+    #[schemars(example = false)]
+    pub on_ac: bool,
+    pub batteries: Vec<Battery>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+pub enum ChargeState {
+    Charging,
+    Discharging,
+    Full,
+    /// Plugged in but held at its charge, e.g. by a charge limit
+    NotCharging,
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct Battery {
+    #[schemars(example = &"BAT0")]
+    pub name: String,
+    /// Charge from 0.0 to 100.0
+    #[schemars(example = 42.0)]
+    pub percentage: f64,
+    pub state: ChargeState,
+    /// Power drawn from or fed to the battery, in watts
+    #[schemars(example = Some(10.5))]
+    pub energy_rate: Option<f64>,
+    /// Seconds until the battery is empty, while discharging
+    #[schemars(example = Some(7200.0))]
+    pub time_to_empty: Option<f64>,
+    /// Seconds until the battery is full, while charging
+    pub time_to_full: Option<f64>,
+}
+
+/// Payload of [`Event::PowerChanged`](crate::events::Event::PowerChanged).
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+#[serde(tag = "change")]
+pub enum PowerChange {
+    /// The machine switched to an external supply
+    Plugged,
+    /// The machine switched to its batteries
+    Unplugged,
+    /// A discharging battery reached the threshold
+    BatteryLow { battery: Battery, threshold: f64 },
+}
+
+/// Low-battery threshold monitors start with, in percent.
+pub const DEFAULT_THRESHOLD: f64 = 15.0;
+
+/// Remembers the previous reading to report what changed.
+pub struct PowerMonitor {
+    threshold: Mutex<f64>,
+    /// AC state of the previous poll, `None` before the first one
+    on_ac: Mutex<Option<bool>>,
+    /// Batteries already reported as low
+    low: Mutex<BTreeSet<String>>,
+}
+
+impl Default for PowerMonitor {
+    fn default() -> Self {
+        Self::new(DEFAULT_THRESHOLD)
+    }
+}
+
+impl PowerMonitor {
+    pub fn new(threshold: f64) -> Self {
+        Self {
+            threshold: Mutex::new(threshold),
+            on_ac: Mutex::default(),
+            low: Mutex::default(),
+        }
+    }
+
+    pub fn threshold(&self) -> f64 {
+        *self.threshold.lock().unwrap()
+    }
+
+    /// Batteries at or under `threshold` percent are reported from the next poll.
+    pub fn set_threshold(&self, threshold: f64) {
+        *self.threshold.lock().unwrap() = threshold;
+    }
+
+    /// Changes since the previous call. The first call doesn't report the AC
+    /// state, but does report batteries already low: pages must hear about them.
+    /// A battery is reported again once it charged or went back above the threshold.
+    pub fn changes(&self, status: &PowerStatus) -> Vec<PowerChange> {
+        let threshold = self.threshold();
+        let mut changes = Vec::new();
+        let previous = self.on_ac.lock().unwrap().replace(status.on_ac);
+        match (previous, status.on_ac) {
+            (Some(false), true) => changes.push(PowerChange::Plugged),
+            (Some(true), false) => changes.push(PowerChange::Unplugged),
+            _ => {}
+        }
+
+        let mut low = self.low.lock().unwrap();
+        low.retain(|name| status.batteries.iter().any(|b| &b.name == name));
+        for battery in &status.batteries {
+            let is_low =
+                battery.state == ChargeState::Discharging && battery.percentage <= threshold;
+            if is_low && low.insert(battery.name.clone()) {
+                changes.push(PowerChange::BatteryLow {
+                    battery: battery.clone(),
+                    threshold,
+                });
+            } else if !is_low {
+                low.remove(&battery.name);
+            }
+        }
+        changes
+    }
+
+    /// Spec of `PowerService/set_low_battery_threshold`, served by Mado.
+    pub fn command_specs(self: &Arc<Self>) -> Vec<CommandSpec> {
+        let monitor = self.clone();
+        vec![
+            CommandSpec::new::<f64, ()>(SERVICE, "set_low_battery_threshold")
+                .with_range(0.0, 100.0)
+                .with_handler(move |threshold| monitor.set_threshold(threshold))
+                .direct(),
+        ]
+    }
+}
+
+/// Event raised for each change `PowerMonitor` finds.
+pub const EVENT: &str = "PowerChanged";
+
+/// Reads `service` every `interval` while pages are subscribed to
+/// `PowerChanged`, raising the changes `monitor` finds to every subscriber.
+pub fn register_provider(
+    scheduler: &Scheduler,
+    service: Arc<dyn PowerService + Send + Sync>,
+    monitor: Arc<PowerMonitor>,
+    interval: Duration,
+    raise: impl Fn(SubscriberId, Event) + Send + Sync + 'static,
+) {
+    scheduler.register_polled(
+        EVENT,
+        SERVICE,
+        interval,
+        move || service.get_power(),
+        move |status| {
+            let changes = monitor.changes(&status);
+            changes.into_iter().map(Event::PowerChanged).collect()
+        },
+        raise,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(on_ac: bool, percentage: f64) -> PowerStatus {
+        PowerStatus {
+            on_ac,
+            batteries: vec![Battery {
+                name: "BAT0".into(),
+                percentage,
+                state: if on_ac {
+                    ChargeState::Charging
+                } else {
+                    ChargeState::Discharging
+                },
+                energy_rate: None,
+                time_to_empty: None,
+                time_to_full: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn reports_plugging_and_unplugging() {
+        let monitor = PowerMonitor::default();
+        assert!(monitor.changes(&status(true, 80.0)).is_empty());
+        assert!(monitor.changes(&status(true, 81.0)).is_empty());
+        assert_eq!(
+            monitor.changes(&status(false, 81.0)),
+            vec![PowerChange::Unplugged]
+        );
+        assert_eq!(
+            monitor.changes(&status(true, 80.0)),
+            vec![PowerChange::Plugged]
+        );
+    }
+
+    #[test]
+    fn reports_low_batteries_once() {
+        let monitor = PowerMonitor::new(20.0);
+        // Already low on the first poll
+        assert_eq!(
+            monitor.changes(&status(false, 12.0)),
+            vec![PowerChange::BatteryLow {
+                battery: status(false, 12.0).batteries[0].clone(),
+                threshold: 20.0
+            }]
+        );
+        assert!(monitor.changes(&status(false, 10.0)).is_empty());
+
+        // Charging re-arms the alert, and low while charging isn't worth one
+        assert_eq!(
+            monitor.changes(&status(true, 10.0)),
+            vec![PowerChange::Plugged]
+        );
+        let changes = monitor.changes(&status(false, 10.0));
+        assert_eq!(changes.len(), 2);
+        assert!(matches!(changes[1], PowerChange::BatteryLow { .. }));
+
+        monitor.set_threshold(5.0);
+        monitor.changes(&status(true, 10.0));
+        assert_eq!(
+            monitor.changes(&status(false, 10.0)),
+            vec![PowerChange::Unplugged]
+        );
+    }
+
+    #[test]
+    fn serializes_changes_with_their_kind() {
+        let json = serde_json::to_value(PowerChange::Unplugged).unwrap();
+        assert_eq!(json, serde_json::json!({ "change": "Unplugged" }));
+        let json = serde_json::to_value(status(true, 50.0)).unwrap();
+        assert_eq!(json["batteries"][0]["state"], "Charging");
+    }
+}
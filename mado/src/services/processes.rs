@@ -0,0 +1,135 @@
+//! Running processes and the ones using the most resources.
+//!
+//! Hosts implement [`ProcessService`] for their platform (Yomi reads
+//! `/proc/<pid>` on Linux). `top_n` is served from `list_processes` unless
+//! the host has a cheaper way to answer it.
+
+use std::{cmp::Ordering, sync::Arc};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::CommandSpec;
+
+pub trait ProcessService {
+    /// Running processes by PID. CPU usage is measured since the previous
+    /// call, so the first call reports it as `0`.
+    fn list_processes(&self) -> Result<Vec<Process>, String>;
+
+    /// The processes using the most of `query.sort_by`, heaviest first.
+    fn top_n(&self, query: TopQuery) -> Result<Vec<Process>, String> {
+        Ok(top_n(self.list_processes()?, &query))
+    }
+}
+
+const SERVICE: &str = "ProcessService";
+
+/// Specs of `ProcessService/list_processes` and `ProcessService/top_n`.
+pub fn command_specs(service: Arc<dyn ProcessService + Send + Sync>) -> Vec<CommandSpec> {
+    let s = service.clone();
+    vec![
+        CommandSpec::without_args::<Vec<Process>>(SERVICE, "list_processes")
+            .with_fallible_handler(move |()| s.list_processes()),
+        CommandSpec::new::<TopQuery, Vec<Process>>(SERVICE, "top_n")
+            .with_fallible_handler(move |query| service.top_n(query)),
+    ]
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct Process {
+    #[schemars(example = 4242)]
+    pub pid: u32,
+    #[schemars(example = &"firefox")]
+    pub name: String,
+    /// Arguments joined by spaces, empty for kernel threads
+    #[schemars(example = &"/usr/lib/firefox/firefox --new-window")]
+    pub command_line: String,
+    /// Share of the time of all CPUs (0.0 to 1.0), like `SystemMetricsService`
+    #[schemars(example = 0.12)]
+    pub cpu: f64,
+    /// Resident memory in bytes
+    #[schemars(example = 450_000_000u64)]
+    pub memory: u64,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+pub enum ProcessSort {
+    #[default]
+    Cpu,
+    Memory,
+}
+
+/// Argument of `ProcessService/top_n`.
+#[derive(Debug, Clone, PartialEq, Deserialize, JsonSchema)]
+pub struct TopQuery {
+    /// How many processes to return
+    #[schemars(example = 5)]
+    pub count: usize,
+    #[serde(default)]
+    pub sort_by: ProcessSort,
+}
+
+/// The `query.count` processes using the most of `query.sort_by`, heaviest
+/// first. Ties go to the lowest PID.
+pub fn top_n(mut processes: Vec<Process>, query: &TopQuery) -> Vec<Process> {
+    processes.sort_by(|a, b| {
+        let heavier = match query.sort_by {
+            ProcessSort::Cpu => b.cpu.partial_cmp(&a.cpu).unwrap_or(Ordering::Equal),
+            ProcessSort::Memory => b.memory.cmp(&a.memory),
+        };
+        heavier.then(a.pid.cmp(&b.pid))
+    });
+    processes.truncate(query.count);
+    processes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(pid: u32, cpu: f64, memory: u64) -> Process {
+        Process {
+            pid,
+            name: format!("process{pid}"),
+            command_line: String::new(),
+            cpu,
+            memory,
+        }
+    }
+
+    #[test]
+    fn sorts_heaviest_first() {
+        let processes = vec![
+            process(1, 0.01, 300),
+            process(2, 0.30, 100),
+            process(3, 0.10, 900),
+            process(4, 0.30, 50),
+        ];
+        let pids = |sort_by, count| {
+            top_n(processes.clone(), &TopQuery { count, sort_by })
+                .iter()
+                .map(|p| p.pid)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(pids(ProcessSort::Cpu, 3), vec![2, 4, 3]);
+        assert_eq!(pids(ProcessSort::Memory, 2), vec![3, 1]);
+        assert_eq!(pids(ProcessSort::Memory, 10).len(), 4);
+        assert!(pids(ProcessSort::Cpu, 0).is_empty());
+    }
+
+    #[test]
+    fn serves_top_n_from_the_list() {
+        struct Fixed;
+        impl ProcessService for Fixed {
+            fn list_processes(&self) -> Result<Vec<Process>, String> {
+                Ok(vec![process(1, 0.5, 10), process(2, 0.2, 20)])
+            }
+        }
+        let query: TopQuery = serde_json::from_str(r#"{ "count": 1 }"#).unwrap();
+        assert_eq!(query.sort_by, ProcessSort::Cpu);
+        assert_eq!(
+            ProcessService::top_n(&Fixed, query).unwrap(),
+            vec![process(1, 0.5, 10)]
+        );
+    }
+}
@@ -0,0 +1,207 @@
+//! Values published by the page for the host to display.
+//!
+//! The page sets a number, a string and named sub-values with `mado/publish`.
+//! Hosts read them from their own callbacks, e.g. Shigure returns them as the
+//! value of its Rainmeter measure. The store is shared between the protocol
+//! handler and those callbacks, so it can be used from any thread.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::CommandSpec;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum PublishedValue {
+    Number(f64),
+    String(String),
+}
+
+impl PublishedValue {
+    /// The value as a number: strings that don't parse are `0`.
+    pub fn as_number(&self) -> f64 {
+        match self {
+            Self::Number(n) => *n,
+            Self::String(s) => s.trim().parse().unwrap_or(0.0),
+        }
+    }
+
+    pub fn as_string(&self) -> String {
+        match self {
+            Self::Number(n) => n.to_string(),
+            Self::String(s) => s.clone(),
+        }
+    }
+}
+
+/// Argument of `mado/publish`. Fields left out keep their current value.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, JsonSchema)]
+pub struct Publish {
+    /// Number value
+    #[serde(default)]
+    #[schemars(example = 42.0)]
+    pub number: Option<f64>,
+    /// String value. Without one, hosts show the number.
+    #[serde(default)]
+    #[schemars(example = &"CPU 42%")]
+    pub string: Option<String>,
+    /// Named sub-values, merged with the current ones. `null` removes a value.
+    /// Names are case-insensitive.
+    #[serde(default)]
+    pub values: HashMap<String, Option<PublishedValue>>,
+}
+
+#[derive(Default)]
+struct State {
+    number: f64,
+    string: Option<String>,
+    values: HashMap<String, PublishedValue>,
+}
+
+#[derive(Default)]
+pub struct PublishedValues {
+    state: Mutex<State>,
+}
+
+impl PublishedValues {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn publish(&self, update: Publish) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(number) = update.number {
+            state.number = number;
+        }
+        if let Some(string) = update.string {
+            state.string = Some(string);
+        }
+        for (name, value) in update.values {
+            match value {
+                Some(value) => state.values.insert(name.to_lowercase(), value),
+                None => state.values.remove(&name.to_lowercase()),
+            };
+        }
+    }
+
+    /// Back to no string, a number of `0` and no sub-values.
+    pub fn clear(&self) {
+        *self.state.lock().unwrap() = State::default();
+    }
+
+    pub fn number(&self) -> f64 {
+        self.state.lock().unwrap().number
+    }
+
+    pub fn string(&self) -> Option<String> {
+        self.state.lock().unwrap().string.clone()
+    }
+
+    pub fn value(&self, name: &str) -> Option<PublishedValue> {
+        self.state
+            .lock()
+            .unwrap()
+            .values
+            .get(&name.to_lowercase())
+            .cloned()
+    }
+
+    /// Specs of `mado/publish` and `mado/clear_published`, served by Mado.
+    pub fn command_specs(self: &Arc<Self>) -> Vec<CommandSpec> {
+        let (publish, clear) = (self.clone(), self.clone());
+        vec![
+            CommandSpec::new::<Publish, ()>("mado", "publish")
+                .with_handler(move |update| publish.publish(update))
+                .parallel_safe()
+                .direct(),
+            CommandSpec::without_args::<()>("mado", "clear_published")
+                .with_handler(move |()| clear.clear())
+                .parallel_safe()
+                .direct(),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::CommandRegistry;
+    use serde_json::json;
+
+    #[test]
+    fn merges_updates() {
+        let store = PublishedValues::new();
+        assert_eq!(store.number(), 0.0);
+        assert_eq!(store.string(), None);
+
+        store.publish(Publish {
+            number: Some(42.0),
+            ..Default::default()
+        });
+        store.publish(Publish {
+            string: Some("CPU 42%".into()),
+            ..Default::default()
+        });
+        assert_eq!(store.number(), 42.0);
+        assert_eq!(store.string().as_deref(), Some("CPU 42%"));
+
+        store.clear();
+        assert_eq!(store.number(), 0.0);
+        assert_eq!(store.string(), None);
+    }
+
+    #[test]
+    fn keeps_sub_values_by_name() {
+        let store = PublishedValues::new();
+        let values = |pairs: &[(&str, Option<PublishedValue>)]| Publish {
+            values: pairs
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect(),
+            ..Default::default()
+        };
+        store.publish(values(&[
+            ("Cpu", Some(PublishedValue::Number(12.5))),
+            ("label", Some(PublishedValue::String("7".into()))),
+        ]));
+        store.publish(values(&[("label", None)]));
+
+        let cpu = store.value("CPU").unwrap();
+        assert_eq!(cpu.as_number(), 12.5);
+        assert_eq!(cpu.as_string(), "12.5");
+        assert_eq!(store.value("label"), None);
+        assert_eq!(PublishedValue::String("7".into()).as_number(), 7.0);
+        assert_eq!(PublishedValue::String("n/a".into()).as_number(), 0.0);
+    }
+
+    #[test]
+    fn publishes_from_commands() {
+        let store = Arc::new(PublishedValues::new());
+        let mut registry = CommandRegistry::new();
+        registry.extend(store.command_specs());
+
+        let update = json!({ "number": 3, "values": { "temp": "41°C", "fan": 1200 } });
+        registry.invoke("mado/publish", update).unwrap();
+        assert_eq!(store.number(), 3.0);
+        assert_eq!(
+            store.value("temp"),
+            Some(PublishedValue::String("41°C".into()))
+        );
+        assert_eq!(store.value("fan"), Some(PublishedValue::Number(1200.0)));
+        assert!(
+            registry
+                .invoke("mado/publish", json!({ "number": "3" }))
+                .is_err()
+        );
+
+        registry
+            .invoke("mado/clear_published", json!(null))
+            .unwrap();
+        assert_eq!(store.value("fan"), None);
+    }
+}
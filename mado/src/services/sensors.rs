@@ -0,0 +1,231 @@
+//! Hardware sensors: temperatures, fan speeds and voltages.
+//!
+//! Hosts implement [`SensorsService`] for their platform (Yomi reads
+//! `/sys/class/hwmon` on Linux). A [`SensorMonitor`] compares successive
+//! readings to raise [`Event::SensorAlert`](crate::events::Event::SensorAlert)
+//! when a sensor reaches its critical threshold and when it goes back under.
+
+use std::{
+    collections::BTreeSet,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::{
+    events::Event,
+    protocol::CommandSpec,
+    scheduler::{Scheduler, SubscriberId},
+};
+
+pub trait SensorsService {
+    /// Sensor chips with their readable sensors.
+    fn list_chips(&self) -> Result<Vec<SensorChip>, String>;
+}
+
+const SERVICE: &str = "SensorsService";
+
+/// Spec of `SensorsService/list_chips`.
+pub fn command_specs(service: Arc<dyn SensorsService + Send + Sync>) -> Vec<CommandSpec> {
+    vec![
+        CommandSpec::without_args::<Vec<SensorChip>>(SERVICE, "list_chips")
+            .with_fallible_handler(move |()| service.list_chips()),
+    ]
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct SensorChip {
+    /// Identifies the chip while the machine runs, e.g. its hwmon directory
+    #[schemars(example = &"hwmon2")]
+    pub id: String,
+    /// Driver name, which several chips can share
+    #[schemars(example = &"coretemp")]
+    pub name: String,
+    pub sensors: Vec<Sensor>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, JsonSchema)]
+pub enum SensorKind {
+    /// In degrees Celsius
+    Temperature,
+    /// In revolutions per minute
+    Fan,
+    /// In volts
+    Voltage,
+}
+
+/// Reading and thresholds, in the unit of the sensor kind.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct Sensor {
+    /// Identifies the sensor on its chip
+    #[schemars(example = &"temp1")]
+    pub id: String,
+    /// Label given by the driver, the ID when there is none
+    #[schemars(example = &"Package id 0")]
+    pub label: String,
+    pub kind: SensorKind,
+    #[schemars(example = 45.0)]
+    pub input: f64,
+    pub min: Option<f64>,
+    #[schemars(example = Some(80.0))]
+    pub max: Option<f64>,
+    /// Reading at which the hardware is in danger
+    #[schemars(example = Some(100.0))]
+    pub critical: Option<f64>,
+}
+
+impl Sensor {
+    /// Whether the reading reached the critical threshold.
+    pub fn is_critical(&self) -> bool {
+        self.critical.is_some_and(|critical| self.input >= critical)
+    }
+}
+
+/// Payload of [`Event::SensorAlert`](crate::events::Event::SensorAlert).
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+#[serde(tag = "change")]
+pub enum SensorAlert {
+    /// The reading reached the critical threshold
+    Critical { chip: String, sensor: Sensor },
+    /// The reading went back under the critical threshold
+    Recovered { chip: String, sensor: Sensor },
+}
+
+/// Remembers which sensors are critical to report when that changes.
+#[derive(Debug, Default)]
+pub struct SensorMonitor {
+    /// `(chip id, sensor id)` of the sensors at or above their critical threshold
+    critical: Mutex<BTreeSet<(String, String)>>,
+}
+
+impl SensorMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Alerts since the previous call. Unlike mounts, sensors already critical
+    /// on the first call are reported: pages must hear about them.
+    pub fn alerts(&self, chips: &[SensorChip]) -> Vec<SensorAlert> {
+        let mut critical = self.critical.lock().unwrap();
+        let mut seen = BTreeSet::new();
+        let mut alerts = Vec::new();
+        for chip in chips {
+            for sensor in &chip.sensors {
+                let key = (chip.id.clone(), sensor.id.clone());
+                seen.insert(key.clone());
+                if sensor.is_critical() && critical.insert(key.clone()) {
+                    alerts.push(SensorAlert::Critical {
+                        chip: chip.id.clone(),
+                        sensor: sensor.clone(),
+                    });
+                } else if !sensor.is_critical() && critical.remove(&key) {
+                    alerts.push(SensorAlert::Recovered {
+                        chip: chip.id.clone(),
+                        sensor: sensor.clone(),
+                    });
+                }
+            }
+        }
+        // Sensors that disappeared can't be critical anymore, nor recover
+        critical.retain(|key| seen.contains(key));
+        alerts
+    }
+}
+
+/// Event raised for each alert `SensorMonitor` finds.
+pub const EVENT: &str = "SensorAlert";
+
+/// Reads the sensors of `service` every `interval` while pages are subscribed
+/// to `SensorAlert`, raising the alerts `monitor` finds to every subscriber.
+pub fn register_provider(
+    scheduler: &Scheduler,
+    service: Arc<dyn SensorsService + Send + Sync>,
+    monitor: Arc<SensorMonitor>,
+    interval: Duration,
+    raise: impl Fn(SubscriberId, Event) + Send + Sync + 'static,
+) {
+    scheduler.register_polled(
+        EVENT,
+        SERVICE,
+        interval,
+        move || service.list_chips(),
+        move |chips| {
+            let alerts = monitor.alerts(&chips);
+            alerts.into_iter().map(Event::SensorAlert).collect()
+        },
+        raise,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chip(temperatures: &[f64]) -> SensorChip {
+        SensorChip {
+            id: "hwmon1".into(),
+            name: "coretemp".into(),
+            sensors: temperatures
+                .iter()
+                .enumerate()
+                .map(|(i, &input)| Sensor {
+                    id: format!("temp{}", i + 1),
+                    label: format!("Core {i}"),
+                    kind: SensorKind::Temperature,
+                    input,
+                    min: None,
+                    max: Some(80.0),
+                    critical: Some(100.0),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn reports_critical_sensors_once() {
+        let monitor = SensorMonitor::new();
+        let alerts = monitor.alerts(&[chip(&[45.0, 100.0])]);
+        assert_eq!(
+            alerts,
+            vec![SensorAlert::Critical {
+                chip: "hwmon1".into(),
+                sensor: chip(&[45.0, 100.0]).sensors[1].clone()
+            }]
+        );
+        assert!(monitor.alerts(&[chip(&[50.0, 104.0])]).is_empty());
+
+        let alerts = monitor.alerts(&[chip(&[101.0, 95.0])]);
+        assert_eq!(alerts.len(), 2);
+        assert!(matches!(&alerts[0], SensorAlert::Critical { sensor, .. } if sensor.id == "temp1"));
+        assert!(
+            matches!(&alerts[1], SensorAlert::Recovered { sensor, .. } if sensor.id == "temp2")
+        );
+    }
+
+    #[test]
+    fn ignores_sensors_without_critical_threshold() {
+        let mut chips = vec![chip(&[120.0])];
+        chips[0].sensors[0].critical = None;
+        let monitor = SensorMonitor::new();
+        assert!(monitor.alerts(&chips).is_empty());
+
+        // A critical sensor that disappears is reported again when it comes back
+        assert_eq!(monitor.alerts(&[chip(&[120.0])]).len(), 1);
+        assert!(monitor.alerts(&[]).is_empty());
+        assert_eq!(monitor.alerts(&[chip(&[120.0])]).len(), 1);
+    }
+
+    #[test]
+    fn serializes_alerts_with_their_kind() {
+        let alert = SensorAlert::Recovered {
+            chip: "hwmon1".into(),
+            sensor: chip(&[45.0]).sensors[0].clone(),
+        };
+        let json = serde_json::to_value(&alert).unwrap();
+        assert_eq!(json["change"], "Recovered");
+        assert_eq!(json["sensor"]["kind"], "Temperature");
+        assert_eq!(json["sensor"]["min"], serde_json::Value::Null);
+    }
+}
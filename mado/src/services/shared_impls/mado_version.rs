@@ -1,7 +1,7 @@
 use shadow_rs::shadow;
 use wry_cmd::commands;
 
-use crate::services::mado_version::MadoVersionService;
+use crate::{protocol::CommandSpec, services::mado_version::MadoVersionService};
 
 pub struct MadoVersion;
 static INSTANCE: MadoVersion = MadoVersion;
@@ -26,3 +26,41 @@ impl MadoVersionService for MadoVersion {
         return build_info::BRANCH.to_string();
     }
 }
+
+/// Specs of the `MadoVersionService` commands. They only read build constants,
+/// so they are safe to run in parallel.
+pub fn command_specs() -> Vec<CommandSpec> {
+    const SERVICE: &str = "MadoVersionService";
+    vec![
+        CommandSpec::without_args::<String>(SERVICE, "get_version")
+            .with_handler(|()| INSTANCE.get_version())
+            .parallel_safe(),
+        CommandSpec::without_args::<String>(SERVICE, "get_tag")
+            .with_handler(|()| INSTANCE.get_tag())
+            .parallel_safe(),
+        CommandSpec::without_args::<String>(SERVICE, "get_commit")
+            .with_handler(|()| INSTANCE.get_commit())
+            .parallel_safe(),
+        CommandSpec::without_args::<String>(SERVICE, "get_branch")
+            .with_handler(|()| INSTANCE.get_branch())
+            .parallel_safe(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::validation::commands_signatures;
+
+    #[test]
+    fn specs_match_the_served_commands() {
+        let mut served = commands_signatures(include_str!("mado_version.rs"));
+        let mut specs: Vec<String> = command_specs()
+            .into_iter()
+            .map(|spec| spec.signature)
+            .collect();
+        served.sort();
+        specs.sort();
+        assert_eq!(specs, served);
+    }
+}
@@ -0,0 +1,164 @@
+//! CPU, memory, load and uptime of the machine running the host.
+//!
+//! Hosts implement [`SystemMetricsService`] for their platform (Yomi reads
+//! procfs on Linux) and register it with [`register_provider`], so pages
+//! subscribed to `SystemMetricsUpdate` receive the metrics periodically.
+
+use std::{sync::Arc, time::Duration};
+
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::{
+    events::Event,
+    protocol::CommandSpec,
+    scheduler::{Scheduler, SubscriberId},
+};
+
+pub trait SystemMetricsService {
+    /// Reads the current metrics. CPU usage is measured since the previous
+    /// call, so the first call reports the average since boot.
+    fn get_metrics(&self) -> Result<SystemMetrics, String>;
+}
+
+const SERVICE: &str = "SystemMetricsService";
+
+/// Spec of `SystemMetricsService/get_metrics`.
+pub fn command_specs(service: Arc<dyn SystemMetricsService + Send + Sync>) -> Vec<CommandSpec> {
+    vec![
+        CommandSpec::without_args::<SystemMetrics>(SERVICE, "get_metrics")
+            .with_fallible_handler(move |()| service.get_metrics()),
+    ]
+}
+
+/// Event carrying the metrics, raised on every poll.
+pub const EVENT: &str = "SystemMetricsUpdate";
+
+/// Reads `service` every `interval` while pages are subscribed to
+/// `SystemMetricsUpdate`, once for all of them. `raise` delivers the event to
+/// one subscriber.
+pub fn register_provider(
+    scheduler: &Scheduler,
+    service: Arc<dyn SystemMetricsService + Send + Sync>,
+    interval: Duration,
+    raise: impl Fn(SubscriberId, Event) + Send + Sync + 'static,
+) {
+    scheduler.register_polled(
+        EVENT,
+        SERVICE,
+        interval,
+        move || service.get_metrics(),
+        |metrics| vec![Event::SystemMetricsUpdate(metrics)],
+        raise,
+    );
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct SystemMetrics {
+    pub cpu: CpuUsage,
+    pub memory: MemoryUsage,
+    pub load: LoadAverage,
+    /// Time since boot, in seconds
+    #[schemars(example = 86400.5)]
+    pub uptime: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct CpuUsage {
+    /// Usage of all cores together (0.0 to 1.0)
+    #[schemars(example = 0.12)]
+    pub total: f64,
+    /// Usage of each core (0.0 to 1.0)
+    #[schemars(example = vec![0.2, 0.04])]
+    pub cores: Vec<f64>,
+}
+
+/// Sizes in bytes.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct MemoryUsage {
+    #[schemars(example = 16_000_000_000u64)]
+    pub total: u64,
+    /// Memory in use, not counting what the system can reclaim (caches, buffers)
+    #[schemars(example = 6_000_000_000u64)]
+    pub used: u64,
+    /// Memory available to new programs
+    #[schemars(example = 10_000_000_000u64)]
+    pub available: u64,
+    #[schemars(example = 2_000_000_000u64)]
+    pub swap_total: u64,
+    #[schemars(example = 0)]
+    pub swap_used: u64,
+}
+
+/// Average number of runnable processes.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct LoadAverage {
+    /// Over the last minute
+    #[schemars(example = 0.52)]
+    pub one: f64,
+    /// Over the last 5 minutes
+    #[schemars(example = 0.58)]
+    pub five: f64,
+    /// Over the last 15 minutes
+    #[schemars(example = 0.59)]
+    pub fifteen: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scheduler::FakeClock;
+    use std::sync::Mutex;
+
+    struct FakeMetrics(Mutex<u32>);
+
+    impl SystemMetricsService for FakeMetrics {
+        fn get_metrics(&self) -> Result<SystemMetrics, String> {
+            *self.0.lock().unwrap() += 1;
+            Ok(SystemMetrics {
+                cpu: CpuUsage {
+                    total: 0.5,
+                    cores: vec![0.5],
+                },
+                memory: MemoryUsage {
+                    total: 2,
+                    used: 1,
+                    available: 1,
+                    swap_total: 0,
+                    swap_used: 0,
+                },
+                load: LoadAverage {
+                    one: 1.0,
+                    five: 1.0,
+                    fifteen: 1.0,
+                },
+                uptime: 10.0,
+            })
+        }
+    }
+
+    #[test]
+    fn reads_once_per_poll_for_every_subscriber() {
+        let service = Arc::new(FakeMetrics(Mutex::new(0)));
+        let scheduler = Scheduler::new(Arc::new(FakeClock::new()));
+        let raised = Arc::new(Mutex::new(Vec::new()));
+        let record = raised.clone();
+        register_provider(
+            &scheduler,
+            service.clone(),
+            Duration::from_secs(1),
+            move |subscriber, event| {
+                assert!(matches!(event, Event::SystemMetricsUpdate(_)));
+                record.lock().unwrap().push(subscriber);
+            },
+        );
+
+        scheduler.tick();
+        assert_eq!(*service.0.lock().unwrap(), 0);
+        scheduler.subscribe(EVENT, 1).unwrap();
+        scheduler.subscribe(EVENT, 2).unwrap();
+        scheduler.tick();
+        assert_eq!(*service.0.lock().unwrap(), 1);
+        assert_eq!(*raised.lock().unwrap(), vec![1, 2]);
+    }
+}
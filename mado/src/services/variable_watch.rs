@@ -0,0 +1,167 @@
+//! Change notifications for host variables.
+//!
+//! The page watches variables with `host/watch_variable`. The host polls the
+//! watched set regularly (Shigure on every Rainmeter update) and raises
+//! [`Event::VariableChanged`](crate::events::Event::VariableChanged) for each
+//! value that differs from the previous poll. Nothing here is specific to a
+//! host: a variable is anything the host can read by name, like a Rainmeter
+//! variable or a setting.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::protocol::CommandSpec;
+
+/// Payload of [`Event::VariableChanged`](crate::events::Event::VariableChanged).
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct VariableChanged {
+    /// Name the variable was watched with
+    #[schemars(example = &"Color")]
+    pub name: String,
+    /// New value, `null` if the variable no longer exists
+    #[schemars(example = &"255,0,0")]
+    pub value: Option<String>,
+    /// Value at the previous poll
+    #[schemars(example = &"0,0,255")]
+    pub previous: Option<String>,
+}
+
+/// Reads a variable of the host, `None` if it doesn't exist.
+pub type VariableReader = dyn Fn(&str) -> Option<String> + Send + Sync;
+
+/// Watched variables with their last known value.
+#[derive(Default)]
+pub struct VariableWatcher {
+    watched: Mutex<BTreeMap<String, Option<String>>>,
+}
+
+impl VariableWatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts watching `name`, with `value` as its current value.
+    /// Watching a variable twice only updates its value.
+    pub fn watch(&self, name: &str, value: Option<String>) {
+        self.watched.lock().unwrap().insert(name.to_string(), value);
+    }
+
+    /// Returns `false` if `name` wasn't watched.
+    pub fn unwatch(&self, name: &str) -> bool {
+        self.watched.lock().unwrap().remove(name).is_some()
+    }
+
+    pub fn is_watched(&self, name: &str) -> bool {
+        self.watched.lock().unwrap().contains_key(name)
+    }
+
+    /// Reads every watched variable and returns those that changed since the
+    /// last poll, in name order.
+    pub fn poll(&self, read: impl Fn(&str) -> Option<String>) -> Vec<VariableChanged> {
+        let mut watched = self.watched.lock().unwrap();
+        let mut changes = Vec::new();
+        for (name, last) in watched.iter_mut() {
+            let value = read(name);
+            if value != *last {
+                changes.push(VariableChanged {
+                    name: name.clone(),
+                    value: value.clone(),
+                    previous: std::mem::replace(last, value),
+                });
+            }
+        }
+        changes
+    }
+
+    /// Specs of `host/watch_variable` and `host/unwatch_variable`, served by Mado.
+    /// `watch_variable` answers with the current value, read with `read`.
+    pub fn command_specs(self: &Arc<Self>, read: Arc<VariableReader>) -> Vec<CommandSpec> {
+        let (watch, unwatch) = (self.clone(), self.clone());
+        vec![
+            CommandSpec::new::<String, Option<String>>("host", "watch_variable")
+                .with_handler(move |name: String| {
+                    let value = read(&name);
+                    watch.watch(&name, value.clone());
+                    value
+                })
+                .direct(),
+            CommandSpec::new::<String, bool>("host", "unwatch_variable")
+                .with_handler(move |name: String| unwatch.unwatch(&name))
+                .direct(),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::CommandRegistry;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    fn variables(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn reports_changes_once() {
+        let watcher = VariableWatcher::new();
+        watcher.watch("Color", Some("blue".into()));
+        watcher.watch("Size", Some("10".into()));
+
+        let vars = variables(&[("Color", "red"), ("Size", "10")]);
+        let read = move |name: &str| vars.get(name).cloned();
+        assert_eq!(
+            watcher.poll(&read),
+            vec![VariableChanged {
+                name: "Color".into(),
+                value: Some("red".into()),
+                previous: Some("blue".into()),
+            }]
+        );
+        assert!(watcher.poll(&read).is_empty());
+
+        // A variable going away is a change too
+        let changes = watcher.poll(|_: &str| None);
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[1].name, "Size");
+        assert_eq!(changes[1].value, None);
+    }
+
+    #[test]
+    fn stops_reporting_unwatched_variables() {
+        let watcher = VariableWatcher::new();
+        watcher.watch("Color", None);
+        assert!(watcher.unwatch("Color"));
+        assert!(!watcher.unwatch("Color"));
+        assert!(watcher.poll(|_: &str| Some("red".into())).is_empty());
+    }
+
+    #[test]
+    fn watches_from_commands() {
+        let watcher = Arc::new(VariableWatcher::new());
+        let mut registry = CommandRegistry::new();
+        let read: Arc<VariableReader> = Arc::new(|name: &str| Some(format!("value of {name}")));
+        registry.extend(watcher.command_specs(read));
+
+        let value = registry.invoke("host/watch_variable", json!("Color"));
+        assert_eq!(value, Ok(json!("value of Color")));
+        assert!(watcher.is_watched("Color"));
+        // The value returned is the baseline: nothing changed yet
+        assert!(
+            watcher
+                .poll(|name: &str| Some(format!("value of {name}")))
+                .is_empty()
+        );
+        let unwatched = registry.invoke("host/unwatch_variable", json!("Color"));
+        assert_eq!(unwatched, Ok(json!(true)));
+    }
+}
@@ -1,7 +1,7 @@
 use mado::events::Event;
-use schemars::{Schema, schema_for};
-use serde_json::Value;
-use std::{env, fs, path::PathBuf};
+use schemars::schema_for;
+use serde_json::{Map, Value, json};
+use std::{env, fmt::Write, fs, path::PathBuf};
 
 fn main() {
     let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
@@ -17,4 +17,248 @@ fn main() {
     let event_schema = schema_for!(Event);
     let event_json = serde_json::to_string_pretty(&event_schema).unwrap();
     fs::write(events_docs.join("schema.json"), event_json).unwrap();
+
+    let event_markdown = render_events(event_schema.as_value());
+    fs::write(events_docs.join("events.md"), event_markdown).unwrap();
+}
+
+/// Renders the `Event` schema into a Markdown reference, in the same layout
+/// `wry_cmd` uses for the command docs.
+fn render_events(schema: &Value) -> String {
+    let empty = Map::new();
+    let defs = schema["$defs"].as_object().unwrap_or(&empty);
+    let variants = schema["oneOf"].as_array().cloned().unwrap_or_default();
+
+    let mut md = String::new();
+    md.push_str("# Events\n\n");
+    md.push_str(
+        "Events are delivered to the page by calling `window.ipcEvent(event)`, \
+         where `event` is an object of the form `{ \"kind\": ..., \"value\": ... }`.\n\n",
+    );
+    md.push_str("| Event | Payload | Description |\n");
+    md.push_str("|-------|---------|-------------|\n");
+    for variant in &variants {
+        let kind = variant_kind(variant);
+        let _ = writeln!(
+            md,
+            "| [{kind}](#{}) | `{}` | {} |",
+            kind.to_lowercase(),
+            payload_type(variant),
+            table_text(description(variant)),
+        );
+    }
+    md.push('\n');
+
+    let mut referenced = Vec::new();
+    for variant in &variants {
+        let kind = variant_kind(variant);
+        let _ = writeln!(md, "## {kind}\n");
+        let _ = writeln!(md, "**Payload:** `{}`\n", payload_type(variant));
+        if let Some(desc) = description(variant) {
+            let _ = writeln!(md, "**Description:**  \n{desc}\n");
+        }
+
+        let value = &variant["properties"]["value"];
+        if let Some(name) = ref_name(value) {
+            if let Some(def) = defs.get(name) {
+                render_fields(&mut md, def);
+            }
+            collect_refs(value, defs, &mut referenced);
+        }
+
+        let mut example = Map::new();
+        example.insert("kind".into(), Value::String(kind.to_string()));
+        if !value.is_null() {
+            example.insert("value".into(), example_value(value, defs));
+        }
+        let _ = writeln!(
+            md,
+            "**Example:**\n\n```json\n{}\n```\n",
+            serde_json::to_string_pretty(&Value::Object(example)).unwrap()
+        );
+    }
+
+    // Payload structs are documented inline above, only list the types they use
+    let payloads: Vec<&str> = variants
+        .iter()
+        .filter_map(|v| ref_name(&v["properties"]["value"]))
+        .collect();
+    referenced.retain(|name| !payloads.contains(&name.as_str()));
+    if !referenced.is_empty() {
+        md.push_str("\n# Type Reference\n");
+        for name in &referenced {
+            let def = &defs[name];
+            let _ = writeln!(md, "\n## `{name}`\n");
+            if let Some(desc) = description(def) {
+                let _ = writeln!(md, "{desc}\n");
+            }
+            if let Some(values) = def["enum"].as_array() {
+                let values: Vec<String> = values.iter().map(|v| format!("`{v}`")).collect();
+                let _ = writeln!(md, "One of: {}\n", values.join(", "));
+            } else {
+                render_fields(&mut md, def);
+            }
+        }
+    }
+    md
+}
+
+fn render_fields(md: &mut String, def: &Value) {
+    let Some(properties) = def["properties"].as_object() else {
+        return;
+    };
+    let required: Vec<&str> = def["required"]
+        .as_array()
+        .map(|r| r.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    md.push_str("| Field | Type | Description |\n");
+    md.push_str("|-------|------|-------------|\n");
+    // `required` keeps the declaration order, `properties` is sorted by name
+    let mut names: Vec<&String> = properties.keys().collect();
+    names.sort_by_key(|n| required.iter().position(|r| r == n).unwrap_or(usize::MAX));
+    for name in names {
+        let field = &properties[name];
+        let _ = writeln!(
+            md,
+            "| `{name}` | `{}` | {} |",
+            type_name(field),
+            table_text(description(field))
+        );
+    }
+    md.push('\n');
+}
+
+fn variant_kind(variant: &Value) -> &str {
+    variant["properties"]["kind"]["const"]
+        .as_str()
+        .or_else(|| variant["const"].as_str())
+        .unwrap_or("Unknown")
+}
+
+fn payload_type(variant: &Value) -> String {
+    match variant["properties"].get("value") {
+        Some(value) => type_name(value),
+        None => "()".to_string(),
+    }
+}
+
+fn description(schema: &Value) -> Option<&str> {
+    schema["description"].as_str()
+}
+
+fn table_text(text: Option<&str>) -> String {
+    text.unwrap_or_default().replace('\n', " ").replace('|', "\\|")
+}
+
+fn ref_name(schema: &Value) -> Option<&str> {
+    schema["$ref"].as_str()?.strip_prefix("#/$defs/")
+}
+
+/// Maps a JSON schema to the Rust type name it was generated from.
+fn type_name(schema: &Value) -> String {
+    if let Some(name) = ref_name(schema) {
+        return name.to_string();
+    }
+    if let Some(any_of) = schema["anyOf"].as_array() {
+        let types: Vec<&Value> = any_of.iter().filter(|s| s["type"] != "null").collect();
+        if types.len() == 1 && types.len() < any_of.len() {
+            return format!("Option<{}>", type_name(types[0]));
+        }
+    }
+    let ty = match &schema["type"] {
+        Value::Array(types) => {
+            let inner: Vec<&Value> = types.iter().filter(|t| *t != "null").collect();
+            if inner.len() == 1 {
+                let mut inner_schema = schema.clone();
+                inner_schema["type"] = inner[0].clone();
+                return format!("Option<{}>", type_name(&inner_schema));
+            }
+            return "Value".to_string();
+        }
+        Value::String(ty) => ty.as_str(),
+        _ => return "Value".to_string(),
+    };
+    match (ty, schema["format"].as_str()) {
+        ("string", _) => "String".to_string(),
+        ("boolean", _) => "bool".to_string(),
+        ("number", Some("float")) => "f32".to_string(),
+        ("number", _) => "f64".to_string(),
+        ("integer", Some(format)) => format.replace("int", "i").replace("ui", "u"),
+        ("integer", None) => "i64".to_string(),
+        ("array", _) => format!("Vec<{}>", type_name(&schema["items"])),
+        ("object", _) => match schema.get("additionalProperties") {
+            Some(values) if values.is_object() => {
+                format!("HashMap<String, {}>", type_name(values))
+            }
+            _ => "Object".to_string(),
+        },
+        _ => "Value".to_string(),
+    }
+}
+
+/// Adds every `$defs` entry reachable from `schema` to `out`, in discovery order.
+fn collect_refs(schema: &Value, defs: &Map<String, Value>, out: &mut Vec<String>) {
+    match schema {
+        Value::Object(map) => {
+            if let Some(name) = ref_name(schema)
+                && !out.iter().any(|n| n == name)
+            {
+                out.push(name.to_string());
+                if let Some(def) = defs.get(name) {
+                    collect_refs(def, defs, out);
+                }
+            }
+            for value in map.values() {
+                collect_refs(value, defs, out);
+            }
+        }
+        Value::Array(items) => items.iter().for_each(|v| collect_refs(v, defs, out)),
+        _ => {}
+    }
+}
+
+/// Builds a placeholder value matching `schema`, preferring declared examples.
+fn example_value(schema: &Value, defs: &Map<String, Value>) -> Value {
+    if let Some(example) = schema["examples"].as_array().and_then(|e| e.first()) {
+        return example.clone();
+    }
+    if let Some(name) = ref_name(schema) {
+        return defs
+            .get(name)
+            .map(|def| example_value(def, defs))
+            .unwrap_or(Value::Null);
+    }
+    if let Some(value) = schema.get("const") {
+        return value.clone();
+    }
+    if let Some(first) = schema["enum"].as_array().and_then(|e| e.first()) {
+        return first.clone();
+    }
+    if let Some(first) = schema["anyOf"].as_array().and_then(|a| a.first()) {
+        return example_value(first, defs);
+    }
+    if let Some(first) = schema["oneOf"].as_array().and_then(|a| a.first()) {
+        return example_value(first, defs);
+    }
+    let ty = match &schema["type"] {
+        Value::Array(types) => types.iter().find(|t| *t != "null").cloned(),
+        ty => Some(ty.clone()),
+    };
+    match ty.as_ref().and_then(Value::as_str) {
+        Some("string") => json!(""),
+        Some("boolean") => json!(false),
+        Some("number") => json!(0.0),
+        Some("integer") => json!(0),
+        Some("array") => json!([example_value(&schema["items"], defs)]),
+        Some("object") => {
+            let mut object = Map::new();
+            if let Some(properties) = schema["properties"].as_object() {
+                for (name, field) in properties {
+                    object.insert(name.clone(), example_value(field, defs));
+                }
+            }
+            Value::Object(object)
+        }
+        _ => Value::Null,
+    }
 }
@@ -0,0 +1,349 @@
+//! Typed Rainmeter bangs.
+//!
+//! Every parameter is quoted when the bang is rendered, so values coming from
+//! the page can't spill into other parameters or inject more bangs.
+//! Pages send the same bangs as JSON to `host/execute_bangs`:
+//! ```json
+//! [
+//!     { "bang": "SetVariable", "name": "Size", "value": "10" },
+//!     { "bang": "UpdateMeter", "meter": "Background" },
+//!     { "bang": "Redraw" }
+//! ]
+//! ```
+
+use std::fmt;
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::context::{LogLevel, RmContext};
+
+/// A Rainmeter bang. `config` defaults to the skin of the measure.
+#[derive(Debug, Clone, PartialEq, Deserialize, JsonSchema)]
+#[serde(tag = "bang")]
+pub enum Bang {
+    /// `[!SetVariable Name Value Config]`
+    SetVariable {
+        name: String,
+        value: String,
+        #[serde(default)]
+        config: Option<String>,
+    },
+    /// `[!SetOption Section Key Value Config]`
+    SetOption {
+        section: String,
+        key: String,
+        value: String,
+        #[serde(default)]
+        config: Option<String>,
+    },
+    /// `[!CommandMeasure Measure Arguments Config]`
+    CommandMeasure {
+        measure: String,
+        arguments: String,
+        #[serde(default)]
+        config: Option<String>,
+    },
+    /// `[!UpdateMeasure Measure Config]`
+    UpdateMeasure {
+        measure: String,
+        #[serde(default)]
+        config: Option<String>,
+    },
+    /// `[!UpdateMeter Meter Config]`
+    UpdateMeter {
+        meter: String,
+        #[serde(default)]
+        config: Option<String>,
+    },
+    /// `[!ShowMeter Meter Config]`
+    ShowMeter {
+        meter: String,
+        #[serde(default)]
+        config: Option<String>,
+    },
+    /// `[!HideMeter Meter Config]`
+    HideMeter {
+        meter: String,
+        #[serde(default)]
+        config: Option<String>,
+    },
+    /// `[!ToggleMeter Meter Config]`
+    ToggleMeter {
+        meter: String,
+        #[serde(default)]
+        config: Option<String>,
+    },
+    /// `[!Update Config]`
+    Update {
+        #[serde(default)]
+        config: Option<String>,
+    },
+    /// `[!Redraw Config]`
+    Redraw {
+        #[serde(default)]
+        config: Option<String>,
+    },
+    /// `[!Refresh Config]`
+    Refresh {
+        #[serde(default)]
+        config: Option<String>,
+    },
+    /// `[!ActivateConfig Config File]`
+    ActivateConfig {
+        config: String,
+        #[serde(default)]
+        file: Option<String>,
+    },
+    /// `[!DeactivateConfig Config]`
+    DeactivateConfig {
+        #[serde(default)]
+        config: Option<String>,
+    },
+    /// `[!Log Message Level]`
+    Log {
+        message: String,
+        #[serde(default)]
+        level: Option<LogLevel>,
+    },
+}
+
+/// A parameter that can't be quoted for Rainmeter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BangError {
+    pub bang: &'static str,
+    pub parameter: String,
+}
+
+impl fmt::Display for BangError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "!{}: {:?} can't be passed to Rainmeter: it contains \"\"\" or ends with a quote",
+            self.bang, self.parameter
+        )
+    }
+}
+
+impl Bang {
+    pub fn set_variable(name: &str, value: impl ToString) -> Self {
+        Self::SetVariable {
+            name: name.to_string(),
+            value: value.to_string(),
+            config: None,
+        }
+    }
+
+    pub fn set_option(section: &str, key: &str, value: impl ToString) -> Self {
+        Self::SetOption {
+            section: section.to_string(),
+            key: key.to_string(),
+            value: value.to_string(),
+            config: None,
+        }
+    }
+
+    pub fn command_measure(measure: &str, arguments: impl ToString) -> Self {
+        Self::CommandMeasure {
+            measure: measure.to_string(),
+            arguments: arguments.to_string(),
+            config: None,
+        }
+    }
+
+    pub fn update_meter(meter: &str) -> Self {
+        Self::UpdateMeter {
+            meter: meter.to_string(),
+            config: None,
+        }
+    }
+
+    pub fn redraw() -> Self {
+        Self::Redraw { config: None }
+    }
+
+    /// Targets the skin `config` instead of the skin of the measure.
+    /// Has no effect on bangs without a config parameter.
+    pub fn in_config(mut self, name: &str) -> Self {
+        match &mut self {
+            Self::SetVariable { config, .. }
+            | Self::SetOption { config, .. }
+            | Self::CommandMeasure { config, .. }
+            | Self::UpdateMeasure { config, .. }
+            | Self::UpdateMeter { config, .. }
+            | Self::ShowMeter { config, .. }
+            | Self::HideMeter { config, .. }
+            | Self::ToggleMeter { config, .. }
+            | Self::Update { config }
+            | Self::Redraw { config }
+            | Self::Refresh { config }
+            | Self::DeactivateConfig { config } => *config = Some(name.to_string()),
+            Self::ActivateConfig { .. } | Self::Log { .. } => {}
+        }
+        self
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::SetVariable { .. } => "SetVariable",
+            Self::SetOption { .. } => "SetOption",
+            Self::CommandMeasure { .. } => "CommandMeasure",
+            Self::UpdateMeasure { .. } => "UpdateMeasure",
+            Self::UpdateMeter { .. } => "UpdateMeter",
+            Self::ShowMeter { .. } => "ShowMeter",
+            Self::HideMeter { .. } => "HideMeter",
+            Self::ToggleMeter { .. } => "ToggleMeter",
+            Self::Update { .. } => "Update",
+            Self::Redraw { .. } => "Redraw",
+            Self::Refresh { .. } => "Refresh",
+            Self::ActivateConfig { .. } => "ActivateConfig",
+            Self::DeactivateConfig { .. } => "DeactivateConfig",
+            Self::Log { .. } => "Log",
+        }
+    }
+
+    /// Parameters in order, the trailing optional one only when set.
+    fn parameters(&self) -> Vec<&str> {
+        let (mut parameters, optional) = match self {
+            Self::SetVariable {
+                name,
+                value,
+                config,
+            } => (vec![name.as_str(), value], config.as_deref()),
+            Self::SetOption {
+                section,
+                key,
+                value,
+                config,
+            } => (vec![section.as_str(), key, value], config.as_deref()),
+            Self::CommandMeasure {
+                measure,
+                arguments,
+                config,
+            } => (vec![measure.as_str(), arguments], config.as_deref()),
+            Self::UpdateMeasure { measure, config } => (vec![measure.as_str()], config.as_deref()),
+            Self::UpdateMeter { meter, config }
+            | Self::ShowMeter { meter, config }
+            | Self::HideMeter { meter, config }
+            | Self::ToggleMeter { meter, config } => (vec![meter.as_str()], config.as_deref()),
+            Self::Update { config }
+            | Self::Redraw { config }
+            | Self::Refresh { config }
+            | Self::DeactivateConfig { config } => (vec![], config.as_deref()),
+            Self::ActivateConfig { config, file } => (vec![config.as_str()], file.as_deref()),
+            Self::Log { message, level } => (vec![message.as_str()], level.map(LogLevel::as_str)),
+        };
+        parameters.extend(optional);
+        parameters
+    }
+
+    /// Renders the bang, like `[!SetVariable "Size" "10"]`.
+    pub fn render(&self) -> Result<String, BangError> {
+        let mut bang = format!("[!{}", self.name());
+        for parameter in self.parameters() {
+            bang.push(' ');
+            bang.push_str(&quote(parameter).ok_or_else(|| BangError {
+                bang: self.name(),
+                parameter: parameter.to_string(),
+            })?);
+        }
+        bang.push(']');
+        Ok(bang)
+    }
+}
+
+/// Renders several bangs as a single command, executed in order by Rainmeter.
+pub fn chain(bangs: &[Bang]) -> Result<String, BangError> {
+    bangs.iter().map(Bang::render).collect()
+}
+
+/// Executes `bangs` in order. Nothing is executed if one of them can't be rendered.
+pub fn execute(rm: &dyn RmContext, bangs: &[Bang]) -> Result<(), BangError> {
+    let command = chain(bangs)?;
+    if !command.is_empty() {
+        rm.execute(&command);
+    }
+    Ok(())
+}
+
+/// Quotes a parameter, using magic quotes (`"""..."""`) when it contains quotes.
+/// Rainmeter has no escape sequences, so a few values can't be quoted at all.
+fn quote(parameter: &str) -> Option<String> {
+    if !parameter.contains('"') {
+        return Some(format!("\"{parameter}\""));
+    }
+    if parameter.contains("\"\"\"") || parameter.ends_with('"') {
+        return None;
+    }
+    Some(format!("\"\"\"{parameter}\"\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::FakeRainmeter;
+
+    #[test]
+    fn quotes_every_parameter() {
+        assert_eq!(
+            Bang::set_variable("Size", 10).render().unwrap(),
+            r#"[!SetVariable "Size" "10"]"#
+        );
+        assert_eq!(
+            Bang::command_measure("MadoWNPTitle", "SetVolume 0.5")
+                .in_config("Mado\\Player")
+                .render()
+                .unwrap(),
+            r#"[!CommandMeasure "MadoWNPTitle" "SetVolume 0.5" "Mado\Player"]"#
+        );
+        assert_eq!(Bang::redraw().render().unwrap(), "[!Redraw]");
+        let log = Bang::Log {
+            message: "hi".into(),
+            level: Some(LogLevel::Warning),
+        };
+        assert_eq!(log.render().unwrap(), r#"[!Log "hi" "Warning"]"#);
+    }
+
+    #[test]
+    fn keeps_injections_inside_their_parameter() {
+        let bang = Bang::set_option("Text", "Text", r#"a" "b"][!Quit]"#);
+        assert_eq!(
+            bang.render().unwrap(),
+            r#"[!SetOption "Text" "Text" """a" "b"][!Quit]"""]"#
+        );
+        let err = Bang::set_variable("Quote", r#"say "hi""#)
+            .render()
+            .unwrap_err();
+        assert_eq!(err.bang, "SetVariable");
+        assert!(Bang::set_variable("Quote", r#"a"""b"#).render().is_err());
+    }
+
+    #[test]
+    fn executes_chains_atomically() {
+        let rm = FakeRainmeter::new();
+        execute(&rm, &[Bang::update_meter("Title"), Bang::redraw()]).unwrap();
+        assert_eq!(rm.take_bangs(), vec![r#"[!UpdateMeter "Title"][!Redraw]"#]);
+
+        let bangs = [Bang::redraw(), Bang::set_variable("Bad", "\"")];
+        assert!(execute(&rm, &bangs).is_err());
+        assert!(rm.bangs().is_empty());
+    }
+
+    #[test]
+    fn deserializes_json_bangs() {
+        let bangs: Vec<Bang> = serde_json::from_str(
+            r#"[
+                { "bang": "SetVariable", "name": "Size", "value": "10", "config": "Mado" },
+                { "bang": "Redraw" }
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            bangs,
+            vec![
+                Bang::set_variable("Size", 10).in_config("Mado"),
+                Bang::redraw()
+            ]
+        );
+    }
+}
@@ -0,0 +1,91 @@
+// Generates the Rainmeter skin for a Mado page.
+//
+// shigure_skin <skins folder> [--name Mado] [--url URL] [--width 400] [--height 300]
+//              [--update 100] [--services music_player,...]
+
+use std::{env, path::PathBuf, process::ExitCode, str::FromStr};
+
+use shigure::skin::{SkinOptions, SkinService, write_skin};
+
+const USAGE: &str = "usage: shigure_skin <skins folder> [--name NAME] [--url URL] \
+[--width PX] [--height PX] [--update MS] [--services music_player,...]";
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<(PathBuf, SkinOptions), String> {
+    let mut options = SkinOptions::default();
+    let mut skins_dir = None;
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{arg} needs a value"));
+        match arg.as_str() {
+            "--name" => options.name = folder_name(&arg, value()?)?,
+            "--url" => options.url = value()?,
+            "--width" => options.width = number(&arg, &value()?)?,
+            "--height" => options.height = number(&arg, &value()?)?,
+            "--update" => options.update = number(&arg, &value()?)?,
+            "--services" => {
+                options.services = value()?
+                    .split(',')
+                    .filter(|s| !s.is_empty())
+                    .map(|s| SkinService::from_name(s).ok_or(format!("unknown service {s}")))
+                    .collect::<Result<_, _>>()?
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown option {arg}")),
+            _ => skins_dir = Some(PathBuf::from(arg)),
+        }
+    }
+    Ok((skins_dir.ok_or("missing skins folder")?, options))
+}
+
+/// `value` if it names a folder of the skins folder, not a path leading out of it.
+fn folder_name(arg: &str, value: String) -> Result<String, String> {
+    let path = value.is_empty() || value.contains(['/', '\\', ':']) || value.contains("..");
+    if path {
+        return Err(format!("{arg} expects a folder name, got {value}"));
+    }
+    Ok(value)
+}
+
+fn number<T: FromStr>(arg: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{arg} expects a number, got {value}"))
+}
+
+fn main() -> ExitCode {
+    let (skins_dir, options) = match parse_args(env::args().skip(1)) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{e}\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match write_skin(&options, &skins_dir) {
+        Ok(ini) => {
+            println!("Wrote {}", ini.display());
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("failed to write the skin: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<(PathBuf, SkinOptions), String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn keeps_skins_inside_the_skins_folder() {
+        let (_, options) = parse(&["Skins", "--name", "Now Playing"]).unwrap();
+        assert_eq!(options.name, "Now Playing");
+        for name in ["", "..", "../x", "a/b", r"a\b", "C:x"] {
+            let error = parse(&["Skins", "--name", name]).unwrap_err();
+            assert!(error.contains("expects a folder name"), "{name}: {error}");
+        }
+        assert!(parse(&["Skins", "--width", "wide"]).is_err());
+    }
+}
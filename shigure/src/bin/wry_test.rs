@@ -1,13 +1,19 @@
 // src/bin/test_webview.rs
 
+// WebView2 smoke test, Windows only like the plugin itself.
+
+#[cfg(windows)]
 use std::{env, fs, path::PathBuf};
+#[cfg(windows)]
 use tao::{
     event::{Event, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
     window::WindowBuilder,
 };
+#[cfg(windows)]
 use wry::{WebContext, WebViewBuilder};
 
+#[cfg(windows)]
 fn make_webview_data_dir() -> PathBuf {
     // 1) Base it on %LOCALAPPDATA%\Rainmeter\OverlayMeter
     let base = env::var_os("LOCALAPPDATA")
@@ -27,6 +33,7 @@ fn make_webview_data_dir() -> PathBuf {
     base
 }
 
+#[cfg(windows)]
 fn main() -> wry::Result<()> {
     // 1) Create the event loop & window
     let event_loop = EventLoop::new();
@@ -69,3 +76,6 @@ fn main() -> wry::Result<()> {
         }
     });
 }
+
+#[cfg(not(windows))]
+fn main() {}
@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+
+use parking_lot::Mutex;
+
+use super::{LogLevel, RmContext};
+
+/// In-memory stand-in for Rainmeter, for tests.
+///
+/// It is scripted with an INI-like table:
+/// ```ini
+/// [Variables]
+/// Size=3
+///
+/// [Measure]
+/// ; Options of the Shigure measure
+/// Url=https://example.com/#Size#
+///
+/// [MadoWNPTitle]
+/// ; String value of another measure, for [MadoWNPTitle]
+/// Value=Song
+/// ```
+/// Executed bangs and log lines are recorded instead of acted upon.
+#[derive(Default)]
+pub struct FakeRainmeter {
+    skin_name: String,
+    options: Mutex<HashMap<String, String>>,
+    variables: Mutex<HashMap<String, String>>,
+    sections: Mutex<HashMap<String, String>>,
+    bangs: Mutex<Vec<String>>,
+    logs: Mutex<Vec<(LogLevel, String)>>,
+}
+
+impl FakeRainmeter {
+    pub fn new() -> Self {
+        Self {
+            skin_name: "Fake\\Skin".to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Builds a fake from an INI-like table, see the type documentation.
+    pub fn from_ini(ini: &str) -> Self {
+        let fake = Self::new();
+        let mut section = String::new();
+        for line in ini.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = name.to_string();
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let (key, value) = (key.trim(), value.trim());
+            match section.to_lowercase().as_str() {
+                "variables" => fake.set_variable(key, value),
+                "measure" => fake.set_option(key, value),
+                _ if key.eq_ignore_ascii_case("value") => fake.set_section_value(&section, value),
+                _ => {}
+            }
+        }
+        fake
+    }
+
+    pub fn with_skin_name(mut self, name: &str) -> Self {
+        self.skin_name = name.to_string();
+        self
+    }
+
+    pub fn set_option(&self, key: &str, value: &str) {
+        self.options
+            .lock()
+            .insert(key.to_lowercase(), value.to_string());
+    }
+
+    pub fn set_variable(&self, name: &str, value: &str) {
+        self.variables
+            .lock()
+            .insert(name.to_lowercase(), value.to_string());
+    }
+
+    /// Sets the string value of the section `name`, as read by `[name]`.
+    pub fn set_section_value(&self, name: &str, value: &str) {
+        self.sections
+            .lock()
+            .insert(name.to_lowercase(), value.to_string());
+    }
+
+    /// Bangs executed so far, oldest first.
+    pub fn bangs(&self) -> Vec<String> {
+        self.bangs.lock().clone()
+    }
+
+    /// Empties the bang log, returning what it contained.
+    pub fn take_bangs(&self) -> Vec<String> {
+        std::mem::take(&mut *self.bangs.lock())
+    }
+
+    pub fn logs(&self) -> Vec<(LogLevel, String)> {
+        self.logs.lock().clone()
+    }
+
+    fn option(&self, key: &str) -> Option<String> {
+        let value = self.options.lock().get(&key.to_lowercase()).cloned()?;
+        Some(self.replace_variables(&value))
+    }
+
+    /// Replaces `open name close` references with `lookup(name)`, keeping unknown ones.
+    fn replace(
+        text: &str,
+        open: &str,
+        close: &str,
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find(open) {
+            let after = &rest[start + open.len()..];
+            let Some(end) = after.find(close) else {
+                break;
+            };
+            let name = &after[..end];
+            match lookup(name) {
+                Some(value) if !name.is_empty() => {
+                    out.push_str(&rest[..start]);
+                    out.push_str(&value);
+                    rest = &after[end + close.len()..];
+                }
+                // Names have no spaces, so this is an unknown reference: keep it as is
+                _ if !name.is_empty() && !name.contains(char::is_whitespace) => {
+                    let skipped = start + open.len() + end + close.len();
+                    out.push_str(&rest[..skipped]);
+                    rest = &rest[skipped..];
+                }
+                _ => {
+                    out.push_str(&rest[..start + open.len()]);
+                    rest = after;
+                }
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+impl RmContext for FakeRainmeter {
+    fn read_string(&self, key: &str, default: &str) -> String {
+        self.option(key).unwrap_or_else(|| default.to_string())
+    }
+
+    fn read_double(&self, key: &str, default: f64) -> f64 {
+        self.option(key)
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(default)
+    }
+
+    fn read_formula(&self, key: &str, default: f64) -> f64 {
+        // Only plain numbers, optionally in parentheses, are supported
+        self.option(key)
+            .and_then(|v| {
+                let v = v.trim();
+                let v = v
+                    .strip_prefix('(')
+                    .and_then(|v| v.strip_suffix(')'))
+                    .unwrap_or(v);
+                v.trim().parse().ok()
+            })
+            .unwrap_or(default)
+    }
+
+    fn read_int(&self, key: &str, default: i32) -> i32 {
+        self.option(key)
+            .and_then(|v| v.trim().parse::<f64>().ok())
+            .map(|v| v as i32)
+            .unwrap_or(default)
+    }
+
+    fn replace_variables(&self, text: &str) -> String {
+        let variables = self.variables.lock().clone();
+        let sections = self.sections.lock().clone();
+        let text = Self::replace(text, "#", "#", |name| {
+            variables.get(&name.to_lowercase()).cloned()
+        });
+        Self::replace(&text, "[", "]", |name| {
+            let name = name.strip_prefix('&').unwrap_or(name);
+            sections.get(&name.to_lowercase()).cloned()
+        })
+    }
+
+    fn execute(&self, bang: &str) {
+        self.bangs.lock().push(bang.to_string());
+    }
+
+    fn log(&self, level: LogLevel, message: &str) {
+        self.logs.lock().push((level, message.to_string()));
+    }
+
+    fn get_skin_name(&self) -> String {
+        self.skin_name.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INI: &str = "
+        [Variables]
+        Size=3
+        Name=World
+
+        [Measure]
+        Url=https://example.com/#Size#
+        Width=(400)
+        Ratio=0.5
+
+        [MadoWNPTitle]
+        Value=Song
+    ";
+
+    #[test]
+    fn reads_options_with_variables() {
+        let rm = FakeRainmeter::from_ini(INI);
+        assert_eq!(rm.read_string("url", ""), "https://example.com/3");
+        assert_eq!(rm.read_string("Missing", "default"), "default");
+        assert_eq!(rm.read_formula("Width", 0.0), 400.0);
+        assert_eq!(rm.read_double("Ratio", 0.0), 0.5);
+        assert_eq!(rm.read_int("Url", 7), 7);
+    }
+
+    #[test]
+    fn replaces_variables_and_sections() {
+        let rm = FakeRainmeter::from_ini(INI);
+        assert_eq!(
+            rm.replace_variables("Hello #Name#, #Unknown# [MadoWNPTitle] [&MadoWNPTitle] [Nope]"),
+            "Hello World, #Unknown# Song Song [Nope]"
+        );
+        assert_eq!(rm.replace_variables("1 # 2 #Size#"), "1 # 2 3");
+    }
+
+    #[test]
+    fn records_bangs_and_logs() {
+        let rm = FakeRainmeter::new();
+        rm.execute("[!Redraw]");
+        rm.log(LogLevel::Notice, "hello");
+        assert_eq!(rm.bangs(), vec!["[!Redraw]"]);
+        assert_eq!(rm.take_bangs(), vec!["[!Redraw]"]);
+        assert!(rm.bangs().is_empty());
+        assert_eq!(rm.logs(), vec![(LogLevel::Notice, "hello".to_string())]);
+    }
+}
@@ -0,0 +1,48 @@
+//! Abstraction over the Rainmeter API used by the services.
+//!
+//! The real [`rainmeter::RainmeterContext`] implements [`RmContext`] on Windows,
+//! and [`FakeRainmeter`] lets the service logic run in tests on any platform.
+
+mod fake;
+#[cfg(windows)]
+mod native;
+
+pub use fake::FakeRainmeter;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+pub enum LogLevel {
+    Error,
+    Warning,
+    Notice,
+    Debug,
+}
+
+impl LogLevel {
+    /// Name of the level in Rainmeter, as used by `[!Log]`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Error => "Error",
+            Self::Warning => "Warning",
+            Self::Notice => "Notice",
+            Self::Debug => "Debug",
+        }
+    }
+}
+
+/// The Rainmeter operations Shigure relies on, scoped to the plugin measure.
+pub trait RmContext: Send + Sync {
+    /// Reads an option of the measure, with variables replaced.
+    fn read_string(&self, key: &str, default: &str) -> String;
+    fn read_double(&self, key: &str, default: f64) -> f64;
+    /// Reads an option of the measure, evaluating it as a formula.
+    fn read_formula(&self, key: &str, default: f64) -> f64;
+    fn read_int(&self, key: &str, default: i32) -> i32;
+    /// Replaces `#Variables#` and `[Section]` variables in `text`.
+    fn replace_variables(&self, text: &str) -> String;
+    /// Executes a bang, like `[!SetVariable SomeVar 10]`.
+    fn execute(&self, bang: &str);
+    fn log(&self, level: LogLevel, message: &str);
+    fn get_skin_name(&self) -> String;
+}
@@ -0,0 +1,43 @@
+use rainmeter::{RainmeterContext, RmLogLevel};
+
+use super::{LogLevel, RmContext};
+
+impl RmContext for RainmeterContext {
+    fn read_string(&self, key: &str, default: &str) -> String {
+        RainmeterContext::read_string(self, key, default)
+    }
+
+    fn read_double(&self, key: &str, default: f64) -> f64 {
+        RainmeterContext::read_double(self, key, default)
+    }
+
+    fn read_formula(&self, key: &str, default: f64) -> f64 {
+        RainmeterContext::read_formula(self, key, default)
+    }
+
+    fn read_int(&self, key: &str, default: i32) -> i32 {
+        RainmeterContext::read_int(self, key, default)
+    }
+
+    fn replace_variables(&self, text: &str) -> String {
+        RainmeterContext::replace_variables(self, text)
+    }
+
+    fn execute(&self, bang: &str) {
+        RainmeterContext::execute(self, bang)
+    }
+
+    fn log(&self, level: LogLevel, message: &str) {
+        let level = match level {
+            LogLevel::Error => RmLogLevel::LogError,
+            LogLevel::Warning => RmLogLevel::LogWarning,
+            LogLevel::Notice => RmLogLevel::LogNotice,
+            LogLevel::Debug => RmLogLevel::LogDebug,
+        };
+        RainmeterContext::log(self, level, message)
+    }
+
+    fn get_skin_name(&self) -> String {
+        RainmeterContext::get_skin_name(self)
+    }
+}
@@ -1,10 +1,10 @@
-use rainmeter::RainmeterContext;
-
-use crate::OverlayMeter;
+use crate::{instances::Instance, plugin::OverlayMeter};
 
 impl OverlayMeter {
-    pub fn poll_updates(&mut self, _rm: &RainmeterContext) {
-        // Tick the music player service to update its state
-        crate::services::music_player::tick_music_player();
+    /// Called from `update`, inside `instances::enter(instance, ..)`.
+    pub fn poll_updates(&mut self, instance: &Instance) {
+        // Providers are shared: the first instance updating once they are due polls them
+        instance.scheduler.tick();
+        crate::services::variables::poll_variables(instance);
     }
 }
@@ -0,0 +1,418 @@
+//! Shigure measures loaded in Rainmeter.
+//!
+//! Every Shigure measure is an instance with its own WebView, Rainmeter
+//! context and page state, so several skins can use Shigure at once. Code
+//! working for an instance runs inside [`enter`]: the plugin callbacks on the
+//! Rainmeter thread and the protocol handler on the WebView thread of the
+//! instance. Services then reach the right skin through [`current`], which is
+//! what [`get_rainmeter`](crate::get_rainmeter) and
+//! [`raise_event`](crate::raise_event) use.
+//!
+//! The current instance is thread-local: work moved to another thread must
+//! carry its instance along, like [`InstanceEventRaiser`] does. Commands Mado
+//! runs in the background, like batches and the `host` commands, go through
+//! [`InstanceExecutor`], which does it for them.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    sync::{
+        Arc, Weak,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        mpsc::Sender,
+    },
+    thread,
+};
+
+use mado::{
+    events::{ErrorData, Event, EventRaiser},
+    executor::{BoxFuture, Executor, block_on},
+    scheduler::Scheduler,
+    services::{
+        messaging::{MessageBus, MessageClient},
+        music_player::MusicPlayerState,
+        published_values::PublishedValues,
+        variable_watch::VariableWatcher,
+    },
+};
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
+
+use crate::{Command, context::RmContext, services};
+
+pub type InstanceId = u64;
+
+pub struct Instance {
+    id: InstanceId,
+    /// Address Rainmeter identifies the measure with in section variables
+    plugin_data: AtomicUsize,
+    rm: Arc<dyn RmContext>,
+    commands: Mutex<Sender<Command>>,
+    /// Values published by the page with `mado/publish`
+    pub published: Arc<PublishedValues>,
+    /// Variables watched by the page with `host/watch_variable`
+    pub variables: Arc<VariableWatcher>,
+    /// Connection of the page to the channels shared by every instance
+    pub messages: Arc<MessageClient>,
+    /// Polls the providers the page subscribed to, shared by every instance
+    pub scheduler: Arc<Scheduler>,
+    /// Music player state last sent to the page
+    pub music: Mutex<Option<MusicPlayerState>>,
+    /// Problem with the music player measures, kept until the page listens
+    pub music_error: Mutex<Option<ErrorData>>,
+}
+
+impl Instance {
+    pub fn id(&self) -> InstanceId {
+        self.id
+    }
+
+    pub fn rm(&self) -> Arc<dyn RmContext> {
+        self.rm.clone()
+    }
+
+    /// Identifies the instance with `plugin_data`, from [`plugin_data`].
+    pub fn set_plugin_data(&self, plugin_data: usize) {
+        self.plugin_data.store(plugin_data, Ordering::SeqCst);
+    }
+
+    /// Sends a command to the WebView of the instance.
+    pub fn send(&self, command: Command) {
+        let _ = self.commands.lock().send(command);
+    }
+
+    /// Pushes `event` to the page of the instance.
+    pub fn raise_event(&self, event: &Event) {
+        if let Ok(json) = serde_json::to_string(event) {
+            self.send(Command::Event(json));
+        }
+    }
+}
+
+/// Every running instance.
+#[derive(Default)]
+pub struct Instances {
+    next_id: AtomicU64,
+    instances: RwLock<HashMap<InstanceId, Arc<Instance>>>,
+    /// Lets the pages of the instances talk to each other
+    messages: Arc<MessageBus>,
+    scheduler: Arc<Scheduler>,
+}
+
+impl Instances {
+    pub fn register(
+        &self,
+        rm: Arc<dyn RmContext>,
+        commands: Sender<Command>,
+        plugin_data: usize,
+    ) -> Arc<Instance> {
+        let instance = Arc::new_cyclic(|instance| Instance {
+            id: self.next_id.fetch_add(1, Ordering::SeqCst) + 1,
+            plugin_data: AtomicUsize::new(plugin_data),
+            rm,
+            commands: Mutex::new(commands),
+            published: Default::default(),
+            variables: Default::default(),
+            messages: Arc::new(
+                self.messages
+                    .connect(Arc::new(InstanceEventRaiser(instance.clone()))),
+            ),
+            scheduler: self.scheduler.clone(),
+            music: Mutex::new(None),
+            music_error: Mutex::new(None),
+        });
+        self.instances.write().insert(instance.id, instance.clone());
+        instance
+    }
+
+    pub fn unregister(&self, id: InstanceId) {
+        self.instances.write().remove(&id);
+        self.scheduler.unsubscribe_all(id);
+    }
+
+    pub fn get(&self, id: InstanceId) -> Option<Arc<Instance>> {
+        self.instances.read().get(&id).cloned()
+    }
+
+    /// Instance of the measure Rainmeter knows as `plugin_data`.
+    pub fn by_plugin_data(&self, plugin_data: usize) -> Option<Arc<Instance>> {
+        let instances = self.instances.read();
+        let mut all = instances.values();
+        all.find(|i| i.plugin_data.load(Ordering::SeqCst) == plugin_data)
+            .cloned()
+    }
+}
+
+/// Key Rainmeter knows the measure of `plugin` as: the address of its plugin
+/// data, which Rainmeter passes back as the `data` of every call.
+pub fn plugin_data<T>(plugin: &T) -> usize {
+    plugin as *const T as usize
+}
+
+/// Instances of the Shigure measures loaded in Rainmeter.
+pub static INSTANCES: Lazy<Instances> = Lazy::new(|| {
+    let instances = Instances::default();
+    services::register_providers(&instances.scheduler);
+    instances
+});
+
+thread_local! {
+    static CURRENT: RefCell<Vec<Arc<Instance>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Runs `f` with `instance` as the current instance of this thread.
+pub fn enter<R>(instance: &Arc<Instance>, f: impl FnOnce() -> R) -> R {
+    struct Exit;
+    impl Drop for Exit {
+        fn drop(&mut self) {
+            CURRENT.with(|current| current.borrow_mut().pop());
+        }
+    }
+
+    CURRENT.with(|current| current.borrow_mut().push(instance.clone()));
+    let _exit = Exit;
+    f()
+}
+
+/// The instance this thread is working for, if any.
+pub fn current() -> Option<Arc<Instance>> {
+    CURRENT.with(|current| current.borrow().last().cloned())
+}
+
+/// Runs every task on its own thread, for the instance that spawned it.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct InstanceExecutor;
+
+impl Executor for InstanceExecutor {
+    fn spawn(&self, future: BoxFuture<'static, ()>) {
+        let instance = current();
+        thread::spawn(move || match instance {
+            Some(instance) => enter(&instance, || block_on(future)),
+            None => block_on(future),
+        });
+    }
+}
+
+/// Raises events on the page of one instance, from any thread.
+pub struct InstanceEventRaiser(Weak<Instance>);
+
+impl InstanceEventRaiser {
+    pub fn new(instance: &Arc<Instance>) -> Self {
+        Self(Arc::downgrade(instance))
+    }
+}
+
+impl EventRaiser for InstanceEventRaiser {
+    fn raise_event(&self, event: Event) {
+        if let Some(instance) = self.0.upgrade() {
+            instance.raise_event(&event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{context::FakeRainmeter, get_rainmeter, raise_event};
+    use mado::{
+        events::CustomEvent,
+        protocol::{BatchCall, CommandRegistry, CommandSpec},
+        services::messaging::PostMessage,
+    };
+    use std::{
+        ffi::c_void,
+        sync::mpsc::{Receiver, channel},
+        thread,
+        time::Duration,
+    };
+
+    fn fake_instance(instances: &Instances, skin: &str) -> (Arc<Instance>, Receiver<Command>) {
+        let (tx, rx) = channel();
+        let rm = Arc::new(FakeRainmeter::new().with_skin_name(skin));
+        let data = skin.len();
+        (instances.register(rm, tx, data), rx)
+    }
+
+    fn ping(name: &str) -> Event {
+        Event::Custom(CustomEvent {
+            name: name.to_string(),
+            data: serde_json::Value::Null,
+        })
+    }
+
+    fn received(rx: &Receiver<Command>) -> Vec<String> {
+        rx.try_iter()
+            .map(|command| match command {
+                Command::Event(json) => json,
+                _ => panic!("expected an event"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn routes_to_the_current_instance() {
+        let instances = Instances::default();
+        let (a, a_rx) = fake_instance(&instances, "A");
+        let (b, b_rx) = fake_instance(&instances, "Skin B");
+
+        enter(&a, || {
+            assert_eq!(get_rainmeter().unwrap().get_skin_name(), "A");
+            raise_event(ping("a"));
+            // Nested calls work for the inner instance, then back to the outer one
+            enter(&b, || {
+                assert_eq!(get_rainmeter().unwrap().get_skin_name(), "Skin B");
+                raise_event(ping("b"));
+            });
+            assert_eq!(current().unwrap().id(), a.id());
+        });
+        // Outside of any instance, events have nowhere to go
+        assert!(current().is_none());
+        raise_event(ping("lost"));
+
+        let a_events = received(&a_rx);
+        assert_eq!(a_events.len(), 1);
+        assert!(a_events[0].contains(r#""name":"a""#));
+        let b_events = received(&b_rx);
+        assert_eq!(b_events.len(), 1);
+        assert!(b_events[0].contains(r#""name":"b""#));
+    }
+
+    #[test]
+    fn keeps_instances_apart_across_threads() {
+        let instances = Instances::default();
+        let (a, a_rx) = fake_instance(&instances, "A");
+        let (b, b_rx) = fake_instance(&instances, "Skin B");
+
+        let threads: Vec<_> = [a.clone(), b.clone()]
+            .into_iter()
+            .map(|instance| {
+                thread::spawn(move || {
+                    enter(&instance, || {
+                        for _ in 0..10 {
+                            let skin = get_rainmeter().unwrap().get_skin_name();
+                            raise_event(ping(&skin));
+                        }
+                    })
+                })
+            })
+            .collect();
+        threads.into_iter().for_each(|t| t.join().unwrap());
+
+        assert!(received(&a_rx).iter().all(|e| e.contains(r#""name":"A""#)));
+        assert_eq!(received(&b_rx).len(), 10);
+
+        // Background work carries its instance along
+        InstanceEventRaiser::new(&b).raise_event(ping("background"));
+        assert!(received(&a_rx).is_empty());
+        assert_eq!(received(&b_rx).len(), 1);
+    }
+
+    #[test]
+    fn runs_background_commands_for_their_instance() {
+        let instances = Instances::default();
+        let (a, _a_rx) = fake_instance(&instances, "A");
+        let mut registry = CommandRegistry::with_executor(Arc::new(InstanceExecutor));
+        registry.register(
+            CommandSpec::without_args::<String>("test", "skin")
+                .with_handler(|()| get_rainmeter().map(|rm| rm.get_skin_name()))
+                .parallel_safe(),
+        );
+        let registry = Arc::new(registry);
+
+        // A batch spawned from the WebView thread, running calls in parallel
+        let (tx, rx) = channel();
+        let call = || BatchCall {
+            command: "test/skin".to_string(),
+            args: serde_json::Value::Null,
+        };
+        let batch = registry.clone();
+        enter(&a, || {
+            registry.executor().spawn(Box::pin(async move {
+                let _ = tx.send(batch.execute_batch(vec![call(), call()]));
+            }));
+        });
+        let results = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(results.len(), 2);
+        for result in results {
+            assert_eq!(result.result, Some("A".into()));
+        }
+    }
+
+    #[test]
+    fn passes_messages_between_instances() {
+        let instances = Instances::default();
+        let (music, music_rx) = fake_instance(&instances, "Music");
+        let (visualizer, visualizer_rx) = fake_instance(&instances, "Visualizer");
+
+        visualizer.messages.subscribe("now-playing");
+        let now_playing = |data: &str| PostMessage {
+            channel: "now-playing".to_string(),
+            data: data.into(),
+            retain: false,
+        };
+        assert_eq!(music.messages.post(now_playing("spotify")), 1);
+        assert!(received(&music_rx).is_empty());
+        assert!(received(&visualizer_rx)[0].contains(r#""data":"spotify""#));
+
+        // Unloading a skin disconnects its page and drops its subscriptions
+        instances
+            .scheduler
+            .register("Tick", Duration::from_secs(1), |_| {});
+        instances
+            .scheduler
+            .subscribe("Tick", visualizer.id())
+            .unwrap();
+        instances.unregister(visualizer.id());
+        drop(visualizer);
+        assert_eq!(music.messages.post(now_playing("mpd")), 0);
+        assert_eq!(instances.scheduler.next_poll_in(), None);
+    }
+
+    #[test]
+    fn finds_instances_by_plugin_data() {
+        let instances = Instances::default();
+        let (a, _a_rx) = fake_instance(&instances, "A");
+        // Even a single instance doesn't answer for other measures
+        assert!(instances.by_plugin_data(99).is_none());
+
+        let (b, _b_rx) = fake_instance(&instances, "Skin B");
+        assert_eq!(instances.by_plugin_data(1).unwrap().id(), a.id());
+        assert_eq!(instances.by_plugin_data(6).unwrap().id(), b.id());
+        assert!(instances.by_plugin_data(99).is_none());
+
+        instances.unregister(a.id());
+        assert!(instances.get(a.id()).is_none());
+        assert!(instances.by_plugin_data(1).is_none());
+    }
+
+    #[test]
+    fn knows_measures_by_the_data_rainmeter_passes() {
+        #[derive(Default)]
+        struct Measure {
+            _url: String,
+        }
+
+        // Like `declare_plugin!`: the measure is boxed and Rainmeter keeps the
+        // box as the data of every call, the callbacks borrowing it from there
+        let data = Box::into_raw(Box::new(Measure::default())) as *mut c_void;
+        // SAFETY: `data` comes from the box above, freed at the end
+        let measure = unsafe { &*(data as *const Measure) };
+        let instances = Instances::default();
+        let (tx, _rx) = channel();
+        let rm = Arc::new(FakeRainmeter::new());
+        let instance = instances.register(rm, tx, plugin_data(measure));
+        assert_eq!(
+            instances.by_plugin_data(data as usize).unwrap().id(),
+            instance.id()
+        );
+
+        // Initialized before being boxed, it is known once reloaded
+        let early = Measure::default();
+        instance.set_plugin_data(plugin_data(&early));
+        assert!(instances.by_plugin_data(data as usize).is_none());
+        instance.set_plugin_data(plugin_data(measure));
+        assert!(instances.by_plugin_data(data as usize).is_some());
+        // SAFETY: nothing borrows the measure anymore
+        drop(unsafe { Box::from_raw(data as *mut Measure) });
+    }
+}
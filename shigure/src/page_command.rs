@@ -0,0 +1,197 @@
+//! Commands sent to the page by the skin.
+//!
+//! Skins and other plugins drive the page with `!CommandMeasure` on the
+//! Shigure measure:
+//! ```ini
+//! LeftMouseUpAction=[!CommandMeasure Shigure """Emit clicked {"button": 1}"""]
+//! LeftMouseUpAction=[!CommandMeasure Shigure "Navigate https://example.com"]
+//! LeftMouseUpAction=[!CommandMeasure Shigure "Reload"]
+//! LeftMouseUpAction=[!CommandMeasure Shigure """EvalAllowed showPanel ["left", true]"""]
+//! ```
+//! `Emit` raises an `Event::Custom`. `EvalAllowed` calls a global function of
+//! the page, only if the Shigure measure lists it in `AllowedFunctions`.
+
+use serde_json::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PageCommand {
+    /// `Emit <name> [json]`
+    Emit { name: String, data: Value },
+    /// `Navigate <url>`
+    Navigate(String),
+    /// `Reload`
+    Reload,
+    /// `EvalAllowed <function> [args]`
+    EvalAllowed { function: String, args: Vec<Value> },
+}
+
+/// Parses the arguments of a `!CommandMeasure` bang. Command names are case-insensitive.
+pub fn parse(args: &str) -> Result<PageCommand, String> {
+    let (command, rest) = split_word(args);
+    match command.to_lowercase().as_str() {
+        "emit" => {
+            let (name, data) = split_word(rest);
+            if name.is_empty() {
+                return Err("Emit: missing event name".to_string());
+            }
+            Ok(PageCommand::Emit {
+                name: name.to_string(),
+                data: parse_value(data),
+            })
+        }
+        "navigate" if rest.is_empty() => Err("Navigate: missing URL".to_string()),
+        "navigate" => Ok(PageCommand::Navigate(rest.to_string())),
+        "reload" if rest.is_empty() => Ok(PageCommand::Reload),
+        "reload" => Err(format!("Reload: unexpected arguments {rest:?}")),
+        "evalallowed" => {
+            let (function, args) = split_word(rest);
+            if !is_identifier(function) {
+                return Err(format!("EvalAllowed: invalid function name {function:?}"));
+            }
+            let args = match parse_value(args) {
+                Value::Null if args.is_empty() => Vec::new(),
+                Value::Array(args) => args,
+                arg => vec![arg],
+            };
+            Ok(PageCommand::EvalAllowed {
+                function: function.to_string(),
+                args,
+            })
+        }
+        "" => Err("missing command".to_string()),
+        _ => Err(format!("unknown command {command:?}")),
+    }
+}
+
+/// Splits the first word off `text`, trimming both parts.
+fn split_word(text: &str) -> (&str, &str) {
+    let text = text.trim();
+    match text.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim()),
+        None => (text, ""),
+    }
+}
+
+/// JSON if it parses, otherwise the text itself as a string, so `Emit hover left`
+/// doesn't need JSON quotes. Nothing at all is `null`.
+fn parse_value(text: &str) -> Value {
+    if text.is_empty() {
+        return Value::Null;
+    }
+    serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_string()))
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
+
+/// Parses the `AllowedFunctions` option, a comma separated list of function names.
+pub fn parse_allowed_functions(option: &str) -> Vec<String> {
+    option
+        .split(',')
+        .map(str::trim)
+        .filter(|name| is_identifier(name))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Script calling the global `function` of the page with `args`, if it exists.
+pub fn call_script(function: &str, args: &[Value]) -> String {
+    let args = Value::Array(args.to_vec());
+    format!(
+        "if(typeof window[{name}]==='function')window[{name}](...{args});",
+        name = Value::String(function.to_string())
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parses_emit() {
+        assert_eq!(
+            parse(r#"Emit clicked {"button": 1}"#),
+            Ok(PageCommand::Emit {
+                name: "clicked".into(),
+                data: json!({ "button": 1 })
+            })
+        );
+        assert_eq!(
+            parse("emit hover left side"),
+            Ok(PageCommand::Emit {
+                name: "hover".into(),
+                data: json!("left side")
+            })
+        );
+        assert_eq!(
+            parse("  Emit   ping  "),
+            Ok(PageCommand::Emit {
+                name: "ping".into(),
+                data: Value::Null
+            })
+        );
+        assert!(parse("Emit").is_err());
+    }
+
+    #[test]
+    fn parses_navigation() {
+        assert_eq!(
+            parse("Navigate https://example.com/?q=a b"),
+            Ok(PageCommand::Navigate("https://example.com/?q=a b".into()))
+        );
+        assert!(parse("Navigate").is_err());
+        assert_eq!(parse("RELOAD"), Ok(PageCommand::Reload));
+        assert!(parse("Reload now").is_err());
+    }
+
+    #[test]
+    fn parses_eval_allowed() {
+        assert_eq!(
+            parse(r#"EvalAllowed showPanel ["left", true]"#),
+            Ok(PageCommand::EvalAllowed {
+                function: "showPanel".into(),
+                args: vec![json!("left"), json!(true)]
+            })
+        );
+        assert_eq!(
+            parse("EvalAllowed setVolume 0.5"),
+            Ok(PageCommand::EvalAllowed {
+                function: "setVolume".into(),
+                args: vec![json!(0.5)]
+            })
+        );
+        assert_eq!(
+            parse("EvalAllowed refresh"),
+            Ok(PageCommand::EvalAllowed {
+                function: "refresh".into(),
+                args: vec![]
+            })
+        );
+        assert!(parse("EvalAllowed alert(1)").is_err());
+        assert!(parse("EvalAllowed").is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_commands() {
+        assert_eq!(parse(""), Err("missing command".to_string()));
+        assert!(parse("Explode now").is_err());
+    }
+
+    #[test]
+    fn builds_call_scripts() {
+        assert_eq!(
+            parse_allowed_functions(" showPanel, refresh ,alert(1),"),
+            vec!["showPanel", "refresh"]
+        );
+        assert_eq!(
+            call_script("showPanel", &[json!("left"), json!(true)]),
+            r#"if(typeof window["showPanel"]==='function')window["showPanel"](...["left",true]);"#
+        );
+    }
+}
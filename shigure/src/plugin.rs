@@ -0,0 +1,400 @@
+// Shigure — Rainmeter plugin embedding Wry/WebView2 as a non-resizable child
+// with clean shutdown and dynamic URL updates
+
+use std::{
+    env,
+    ffi::c_void,
+    fs,
+    path::PathBuf,
+    ptr,
+    rc::Rc,
+    slice,
+    sync::{
+        Arc,
+        mpsc::{Receiver, Sender, channel},
+    },
+    thread,
+};
+
+use mado::{
+    events::{CustomEvent, EventRaiser},
+    protocol::wrap_protocol,
+    services::covers::wrap_covers,
+};
+use parking_lot::Mutex;
+use tao::platform::{
+    run_return::EventLoopExtRunReturn,
+    windows::{EventLoopBuilderExtWindows, WindowBuilderExtWindows, WindowExtWindows},
+};
+use tao::{
+    dpi::{LogicalPosition, LogicalSize},
+    event::{Event, WindowEvent},
+    event_loop::{ControlFlow, EventLoopBuilder},
+    window::WindowBuilder,
+};
+
+use windows::Win32::Foundation::HWND;
+use windows::Win32::System::Com::{COINIT_APARTMENTTHREADED, CoInitializeEx};
+
+use rainmeter::*;
+use softbuffer::{Context as SoftbufferContext, Surface as SoftbufferSurface};
+use wry::{WebContext, WebViewBuilder, WebViewBuilderExtWindows};
+use wry_cmd::use_wry_cmd_protocol;
+
+use crate::{
+    Command,
+    instances::{self, INSTANCES, Instance},
+    page_command::{self, PageCommand},
+    services,
+};
+
+fn make_webview_data_dir(rm: &RainmeterContext) -> PathBuf {
+    let dir = env::var_os("LOCALAPPDATA")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| {
+            rm.log(RmLogLevel::LogWarning, "LOCALAPPDATA missing; using CWD");
+            env::current_dir().unwrap()
+        })
+        .join("Rainmeter")
+        .join("OverlayMeter");
+    let _ = fs::create_dir_all(&dir);
+    rm.log(
+        RmLogLevel::LogNotice,
+        &format!("WebView2 data dir: {:?}", dir),
+    );
+    dir
+}
+
+pub struct OverlayMeter {
+    url: String,
+    width: u32,
+    height: u32,
+    x: i32,
+    y: i32,
+    /// Page functions the skin may call with `EvalAllowed`
+    allowed_functions: Vec<String>,
+
+    /// This measure in the instance registry
+    instance: Option<Arc<Instance>>,
+    hwnd_rx: Option<Receiver<isize>>,
+    shutdown_tx: Option<Sender<()>>,
+    thread_handle: Option<thread::JoinHandle<()>>,
+    hwnd: Option<isize>,
+}
+
+impl Default for OverlayMeter {
+    fn default() -> Self {
+        Self {
+            url: "https://example.com".into(),
+            width: 300,
+            height: 200,
+            x: 0,
+            y: 0,
+            allowed_functions: Vec::new(),
+            instance: None,
+            hwnd_rx: None,
+            shutdown_tx: None,
+            thread_handle: None,
+            hwnd: None,
+        }
+    }
+}
+
+impl OverlayMeter {
+    fn load_data(&mut self, rm: &RainmeterContext) {
+        self.url = rm.read_string("url", &self.url);
+        self.width = rm.read_formula("width", self.width as f64) as u32;
+        self.height = rm.read_formula("height", self.height as f64) as u32;
+        self.x = rm.read_formula("x", self.x as f64) as i32;
+        self.y = rm.read_formula("y", self.y as f64) as i32;
+        self.allowed_functions =
+            page_command::parse_allowed_functions(&rm.read_string("AllowedFunctions", ""));
+    }
+
+    fn reposition(&self) {
+        if let Some(raw) = self.hwnd {
+            let hwnd = HWND(raw as _);
+            unsafe {
+                windows::Win32::UI::WindowsAndMessaging::SetWindowPos(
+                    hwnd,
+                    Some(windows::Win32::UI::WindowsAndMessaging::HWND_TOPMOST),
+                    self.x,
+                    self.y,
+                    self.width as i32,
+                    self.height as i32,
+                    windows::Win32::UI::WindowsAndMessaging::SWP_NOACTIVATE
+                        | windows::Win32::UI::WindowsAndMessaging::SWP_SHOWWINDOW,
+                )
+                .ok();
+            }
+        }
+    }
+}
+
+impl EventRaiser for OverlayMeter {
+    fn raise_event(&self, event: mado::events::Event) {
+        if let Some(instance) = &self.instance {
+            instance.raise_event(&event);
+        }
+    }
+}
+
+impl RainmeterPlugin for OverlayMeter {
+    fn initialize(&mut self, rm: RainmeterContext) {
+        self.load_data(&rm);
+        rm.log(
+            RmLogLevel::LogNotice,
+            &format!(
+                "Overlay init: url={} size={}×{} pos={},{}",
+                self.url, self.width, self.height, self.x, self.y
+            ),
+        );
+
+        let (hwnd_tx, hwnd_rx) = channel::<isize>();
+        let (cmd_tx, cmd_rx) = channel::<Command>();
+        let (shutdown_tx, shutdown_rx) = channel::<()>();
+        self.hwnd_rx = Some(hwnd_rx);
+        self.shutdown_tx = Some(shutdown_tx.clone());
+
+        let plugin_data = instances::plugin_data(self);
+        let instance = INSTANCES.register(Arc::new(rm.clone()), cmd_tx, plugin_data);
+        self.instance = Some(instance.clone());
+        instances::enter(&instance, || services::music_player::validate_measures(&rm));
+
+        let url = self.url.clone();
+        let (w, h, x, y) = (self.width, self.height, self.x, self.y);
+        let parent = rm.get_skin_window_raw() as isize;
+        let thread_ctx = rm.clone();
+        let thread_instance = instance.clone();
+
+        let handle = thread::spawn(move || {
+            unsafe {
+                let _ = CoInitializeEx(None, COINIT_APARTMENTTHREADED).ok();
+            }
+            let mut event_loop = EventLoopBuilder::new().with_any_thread(true).build();
+            let window = WindowBuilder::new()
+                .with_decorations(false)
+                .with_resizable(false)
+                .with_transparent(true)
+                .with_parent_window(parent)
+                .with_inner_size(LogicalSize::new(w, h))
+                .with_position(LogicalPosition::new(x, y))
+                .build(&event_loop)
+                .expect("Failed to create child window");
+
+            let raw = window.hwnd() as isize;
+            hwnd_tx.send(raw).unwrap();
+            thread_ctx.log(RmLogLevel::LogDebug, &format!("Child HWND = 0x{:x}", raw));
+
+            let window = Rc::new(window);
+            let sb_context =
+                SoftbufferContext::new(window.clone()).expect("SoftbufferContext failed");
+            let mut sb_surface = SoftbufferSurface::new(&sb_context, window.clone())
+                .expect("SoftbufferSurface failed");
+
+            // Commands from the page are served for this instance only
+            let registry = services::command_registry(&thread_instance);
+            let protocol = wrap_protocol(
+                registry,
+                wrap_covers(
+                    services::covers::cache().clone(),
+                    services::covers::executor().clone(),
+                    use_wry_cmd_protocol!("mado"),
+                ),
+            );
+            let protocol = move |id, request, responder| {
+                instances::enter(&thread_instance, || protocol(id, request, responder))
+            };
+
+            let data_dir = make_webview_data_dir(&thread_ctx);
+            let mut webctx = WebContext::new(Some(data_dir));
+            let wv = WebViewBuilder::new_with_web_context(&mut webctx)
+                .with_transparent(true)
+                .with_background_color((0, 0, 0, 0))
+                .with_url(&url)
+                .with_asynchronous_custom_protocol("mado".to_string(), protocol)
+                .with_https_scheme(true)
+                .build(&window)
+                .expect("Failed to build WebView");
+
+            window.request_redraw();
+            event_loop.run_return(move |event, _, control_flow| {
+                if shutdown_rx.try_recv().is_ok() {
+                    *control_flow = ControlFlow::Exit;
+                    return;
+                }
+                if let Ok(next) = cmd_rx.try_recv() {
+                    match next {
+                        Command::Event(json) => {
+                            let script = format!("if(window.ipcEvent)window.ipcEvent({});", json);
+                            let _ = wv.evaluate_script(&script);
+                        }
+                        Command::UpdateUrl(new_url) => {
+                            thread_ctx.log(
+                                RmLogLevel::LogNotice,
+                                &format!("Updating URL to: {}", new_url),
+                            );
+                            wv.load_url(&new_url).unwrap();
+                        }
+                        Command::Reload => {
+                            let _ = wv.reload();
+                        }
+                        Command::Script(script) => {
+                            let _ = wv.evaluate_script(&script);
+                        }
+                    }
+                }
+                match event {
+                    Event::RedrawRequested(_) => {
+                        use std::num::NonZeroU32;
+                        let size = window.inner_size();
+                        sb_surface
+                            .resize(
+                                NonZeroU32::new(size.width).unwrap(),
+                                NonZeroU32::new(size.height).unwrap(),
+                            )
+                            .unwrap();
+                        let mut buffer = sb_surface.buffer_mut().unwrap();
+                        buffer.fill(0);
+                        buffer.present().unwrap();
+                    }
+                    Event::WindowEvent {
+                        event: WindowEvent::CloseRequested,
+                        ..
+                    } => *control_flow = ControlFlow::Exit,
+                    _ => *control_flow = ControlFlow::Poll,
+                }
+            });
+        });
+        self.thread_handle = Some(handle);
+    }
+
+    fn reload(&mut self, rm: RainmeterContext, _max: &mut f64) {
+        // Called through the data Rainmeter keeps for the measure, which
+        // `initialize` may not have run from yet
+        if let Some(instance) = &self.instance {
+            instance.set_plugin_data(instances::plugin_data(self));
+        }
+        let old = self.url.clone();
+        self.load_data(&rm);
+        self.reposition();
+        if self.url != old {
+            if let Some(instance) = &self.instance {
+                instance.send(Command::UpdateUrl(self.url.clone()));
+            }
+        }
+    }
+
+    fn update(&mut self, rm: RainmeterContext) -> f64 {
+        if self.hwnd.is_none() {
+            if let Some(rx) = &self.hwnd_rx {
+                if let Ok(raw) = rx.try_recv() {
+                    rm.log(
+                        RmLogLevel::LogNotice,
+                        &format!("Overlay HWND = 0x{:x}", raw),
+                    );
+                    self.hwnd = Some(raw);
+                }
+            }
+        }
+        self.reposition();
+        let Some(instance) = self.instance.clone() else {
+            return 0.0;
+        };
+        instances::enter(&instance, || self.poll_updates(&instance));
+        instance.published.number()
+    }
+
+    fn finalize(&mut self, _rm: RainmeterContext) {
+        if let Some(instance) = self.instance.take() {
+            INSTANCES.unregister(instance.id());
+        }
+
+        // 1) Tell the event loop to exit
+        if let Some(tx) = self.shutdown_tx.take() {
+            let _ = tx.send(());
+        }
+
+        // 2) Wake up the event loop (so it sees your shutdown flag right away)
+        if let Some(hwnd) = self.hwnd {
+            unsafe {
+                // Trigger a redraw; with ControlFlow::Poll your callback
+                // will run again immediately and see the shutdown flag.
+                let _ = windows::Win32::Graphics::Gdi::InvalidateRect(
+                    Some(HWND(hwnd as _)),
+                    None,
+                    false,
+                );
+            }
+        }
+
+        // 3) Wait for the thread to actually exit - *join* it
+        if let Some(handle) = self.thread_handle.take() {
+            let _ = handle.join();
+        }
+    }
+
+    fn get_string(&mut self, _rm: RainmeterContext) -> Option<String> {
+        self.instance.as_ref()?.published.string()
+    }
+    fn execute_bang(&mut self, rm: RainmeterContext, args: &str) {
+        let command = match page_command::parse(args) {
+            Ok(command) => command,
+            Err(e) => {
+                rm.log(RmLogLevel::LogError, &format!("Shigure: {e}"));
+                return;
+            }
+        };
+        let command = match command {
+            PageCommand::Emit { name, data } => {
+                self.raise_event(mado::events::Event::Custom(CustomEvent { name, data }));
+                return;
+            }
+            PageCommand::Navigate(url) => Command::UpdateUrl(url),
+            PageCommand::Reload => Command::Reload,
+            PageCommand::EvalAllowed { function, args } => {
+                if !self.allowed_functions.contains(&function) {
+                    rm.log(
+                        RmLogLevel::LogError,
+                        &format!("Shigure: {function} is not listed in AllowedFunctions"),
+                    );
+                    return;
+                }
+                Command::Script(page_command::call_script(&function, &args))
+            }
+        };
+        if let Some(instance) = &self.instance {
+            instance.send(command);
+        }
+    }
+}
+
+declare_plugin!(crate::plugin::OverlayMeter);
+
+/// Keeps the string returned by [`Value`] alive until Rainmeter copied it.
+static SECTION_VARIABLE: Mutex<Vec<u16>> = Mutex::new(Vec::new());
+
+/// Section variable `[&Shigure:Value(name)]`: the sub-value `name` published by
+/// the page of the measure. Unknown names are left unreplaced.
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Value(data: *mut c_void, argc: i32, argv: *const *const u16) -> *const u16 {
+    if argc < 1 || argv.is_null() {
+        return ptr::null();
+    }
+    // SAFETY: Rainmeter passes `argc` null-terminated UTF-16 strings
+    let name = unsafe {
+        let arg = *argv;
+        let len = (0..).take_while(|&i| *arg.add(i) != 0).count();
+        String::from_utf16_lossy(slice::from_raw_parts(arg, len))
+    };
+    let Some(instance) = INSTANCES.by_plugin_data(data as usize) else {
+        return ptr::null();
+    };
+    let Some(value) = instance.published.value(name.trim()) else {
+        return ptr::null();
+    };
+    let mut buffer = SECTION_VARIABLE.lock();
+    *buffer = value.as_string().encode_utf16().chain([0]).collect();
+    buffer.as_ptr()
+}
@@ -0,0 +1,29 @@
+use std::{env, path::PathBuf, sync::Arc};
+
+use mado::{
+    executor::PoolExecutor,
+    services::covers::{CoverCache, DEFAULT_MAX_SIZE},
+};
+use once_cell::sync::Lazy;
+
+/// Covers of every page, kept next to the WebView2 data.
+static COVERS: Lazy<Arc<CoverCache>> = Lazy::new(|| {
+    let dir = env::var_os("LOCALAPPDATA")
+        .map(PathBuf::from)
+        .unwrap_or_else(env::temp_dir)
+        .join("Rainmeter")
+        .join("OverlayMeter")
+        .join("covers");
+    Arc::new(CoverCache::new(dir, DEFAULT_MAX_SIZE))
+});
+
+/// Reads the covers of every page, a few at a time.
+static READERS: Lazy<Arc<PoolExecutor>> = Lazy::new(|| Arc::new(PoolExecutor::new(4)));
+
+pub fn cache() -> &'static Arc<CoverCache> {
+    &COVERS
+}
+
+pub fn executor() -> &'static Arc<PoolExecutor> {
+    &READERS
+}
@@ -1,8 +1,12 @@
-use mado::services::host::HostService;
+use mado::{protocol::CommandSpec, services::host::HostService};
+use schemars::JsonSchema;
 use serde::Deserialize;
 use wry_cmd::commands;
 
-use crate::get_rainmeter;
+use crate::{
+    bang::{self, Bang},
+    get_rainmeter,
+};
 
 struct Host;
 
@@ -14,11 +18,47 @@ impl HostService for Host {
         return "Shigure/Rainmeter".to_string();
     }
 }
-#[derive(Deserialize)]
+#[derive(Deserialize, JsonSchema)]
 struct RmReadParameters<T> {
     key: String,
     default: T,
 }
+
+/// Specs of the `host` commands, for validation and batching. Mado serves them
+/// on the executor of the registry, so reading from Rainmeter never blocks the
+/// WebView thread and a bang that can't be quoted is reported as a `CommandError`.
+pub fn command_specs() -> Vec<CommandSpec> {
+    vec![
+        CommandSpec::without_args::<String>("host", "get_host")
+            .with_handler(|()| INSTANCE.get_host())
+            .parallel_safe()
+            .direct(),
+        CommandSpec::new::<RmReadParameters<String>, String>("host", "read_string")
+            .with_handler(|args| INSTANCE.read_string(args))
+            .direct(),
+        CommandSpec::new::<RmReadParameters<f64>, f64>("host", "read_double")
+            .with_handler(|args| INSTANCE.read_double(args))
+            .direct(),
+        CommandSpec::new::<RmReadParameters<f64>, f64>("host", "read_formula")
+            .with_handler(|args| INSTANCE.read_formula(args))
+            .direct(),
+        CommandSpec::new::<RmReadParameters<i32>, i32>("host", "read_int")
+            .with_handler(|args| INSTANCE.read_int(args))
+            .direct(),
+        CommandSpec::without_args::<String>("host", "get_skin_name")
+            .with_handler(|()| INSTANCE.get_skin_name())
+            .direct(),
+        CommandSpec::new::<String, String>("host", "get_variable")
+            .with_handler(|var| INSTANCE.get_variable(var))
+            .direct(),
+        CommandSpec::new::<String, ()>("host", "execute_bang")
+            .with_handler(|bang| INSTANCE.execute_bang(bang))
+            .direct(),
+        CommandSpec::new::<Vec<Bang>, ()>("host", "execute_bangs")
+            .with_fallible_handler(|bangs| INSTANCE.execute_bangs(bangs))
+            .direct(),
+    ]
+}
 #[commands(name = "host")]
 impl Host {
     /// **Rainmeter Only**
@@ -75,4 +115,31 @@ impl Host {
             return rm.execute(&bang);
         }
     }
+    /// **Rainmeter Only**
+    /// Execute Rainmeter Bangs, quoting their parameters
+    /// Example: [{"bang": "SetVariable", "name": "SomeVar", "value": "10"}, {"bang": "Redraw"}]
+    fn execute_bangs(&self, bangs: Vec<Bang>) -> Result<(), String> {
+        if let Some(rm) = get_rainmeter() {
+            return bang::execute(&*rm, &bangs).map_err(|e| e.to_string());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mado::protocol::validation::commands_signatures;
+
+    #[test]
+    fn specs_match_the_served_commands() {
+        let mut served = commands_signatures(include_str!("host.rs"));
+        let mut specs: Vec<String> = command_specs()
+            .into_iter()
+            .map(|spec| spec.signature)
+            .collect();
+        served.sort();
+        specs.sort();
+        assert_eq!(specs, served);
+    }
 }
@@ -1,2 +1,82 @@
+use std::sync::Arc;
+
+use mado::{operations::OperationManager, protocol::CommandRegistry, scheduler::Scheduler};
+
+use crate::instances::{Instance, InstanceEventRaiser, InstanceExecutor};
+
+pub mod covers;
 pub mod host;
 pub mod music_player;
+pub mod variables;
+
+/// Every command spec known to Shigure, used to validate and batch calls from
+/// the page of `instance`. Commands reading from Rainmeter are served by Mado on
+/// the executor, off the WebView thread.
+pub fn command_registry(instance: &Arc<Instance>) -> Arc<CommandRegistry> {
+    let mut registry = CommandRegistry::with_executor(Arc::new(InstanceExecutor));
+    let operations = OperationManager::new(Arc::new(InstanceEventRaiser::new(instance)));
+    registry.extend(mado::services::shared_impls::mado_version::command_specs());
+    registry.extend(mado::services::music_player::async_command_specs(Arc::new(
+        music_player::MusicPlayer,
+    )));
+    registry.extend(covers::cache().command_specs(&operations));
+    registry.extend(host::command_specs());
+    registry.extend(variables::command_specs(instance));
+    registry.extend(instance.published.command_specs());
+    registry.extend(instance.messages.command_specs());
+    registry.extend(instance.scheduler.command_specs(instance.id()));
+    registry.extend(operations.command_specs());
+    Arc::new(registry)
+}
+
+/// Registers the providers of the events pages subscribe to with `mado/subscribe_events`.
+pub fn register_providers(scheduler: &Scheduler) {
+    music_player::register_provider(scheduler);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        context::FakeRainmeter,
+        instances::{Instances, enter},
+    };
+    use serde_json::json;
+    use std::{sync::mpsc::channel, thread, time::Duration};
+
+    #[test]
+    fn serves_rainmeter_commands_off_the_webview_thread() {
+        let instances = Instances::default();
+        let rm = FakeRainmeter::new();
+        rm.set_option("Title", "Now playing");
+        let (commands, _) = channel();
+        let instance = instances.register(Arc::new(rm), commands, 1);
+        let registry = command_registry(&instance);
+        for command in [
+            "host/read_string",
+            "host/execute_bang",
+            "MusicPlayerService/get_data",
+        ] {
+            let (service, name) = command.split_once('/').unwrap();
+            assert!(
+                registry.get(service, name).unwrap().is_direct(),
+                "{command}"
+            );
+        }
+
+        // What the protocol handler does with them, from the WebView thread
+        let webview = thread::current().id();
+        let (tx, rx) = channel();
+        let args = json!({ "key": "Title", "default": "" });
+        let future = registry.invoke_async("host/read_string", args);
+        enter(&instance, || {
+            registry.executor().spawn(Box::pin(async move {
+                let result = future.await;
+                let _ = tx.send((thread::current().id(), result));
+            }))
+        });
+        let (thread, result) = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_ne!(thread, webview);
+        assert_eq!(result.unwrap(), "Now playing");
+    }
+}
@@ -1,78 +1,100 @@
+use std::time::Duration;
+
 use mado::{
-    events::Event,
+    events::{ErrorData, Event},
+    scheduler::Scheduler,
     services::music_player::{MusicPlayerService, MusicPlayerState, MusicPlayerStatus},
 };
-use once_cell::sync::Lazy;
-use parking_lot::Mutex;
 use wry_cmd::commands;
 
-use crate::{get_rainmeter, raise_event};
-struct MusicPlayer;
+use crate::{
+    bang::{self, Bang},
+    context::{LogLevel, RmContext},
+    get_rainmeter,
+    instances::{self, INSTANCES},
+    raise_event,
+    services::covers,
+    wnp::{WnpMeasures, read_measure},
+};
+pub struct MusicPlayer;
 
 static INSTANCE: MusicPlayer = MusicPlayer;
-static LAST_SONG_INFO: Lazy<Mutex<Option<MusicPlayerState>>> = Lazy::new(|| Mutex::new(None));
+
+pub fn service() -> &'static MusicPlayer {
+    &INSTANCE
+}
 
 #[commands]
 impl MusicPlayerService for MusicPlayer {
     fn play(&self) {
         if let Some(rm) = get_rainmeter() {
-            rm.log(rainmeter::RmLogLevel::LogNotice, "Playing music");
-            rm.execute("[!CommandMeasure \"MadoWNPTitle\" \"Play\"]");
+            send_command(&*rm, "Playing music", "Play");
         }
     }
 
     fn pause(&self) {
         if let Some(rm) = get_rainmeter() {
-            rm.log(rainmeter::RmLogLevel::LogNotice, "Pausing music");
-            rm.execute("[!CommandMeasure \"MadoWNPTitle\" \"Pause\"]");
+            send_command(&*rm, "Pausing music", "Pause");
         }
     }
 
     fn next(&self) {
         if let Some(rm) = get_rainmeter() {
-            rm.log(rainmeter::RmLogLevel::LogNotice, "Next song");
-            rm.execute("[!CommandMeasure \"MadoWNPTitle\" \"Next\"]");
+            send_command(&*rm, "Next song", "Next");
         }
     }
 
     fn previous(&self) {
         if let Some(rm) = get_rainmeter() {
-            rm.log(rainmeter::RmLogLevel::LogNotice, "Next song");
-            rm.execute("[!CommandMeasure \"MadoWNPTitle\" \"Previous\"]");
+            send_command(&*rm, "Previous song", "Previous");
         }
     }
 
     fn set_volume(&self, volume: f64) {
         if let Some(rm) = get_rainmeter() {
-            rm.log(rainmeter::RmLogLevel::LogNotice, "Set Volume");
-            rm.execute(&format!(
-                "[!CommandMeasure \"MadoWNPTitle\" \"SetVolume {volume}\"]"
-            ));
+            send_command(&*rm, "Set Volume", &format!("SetVolume {volume}"));
         }
     }
 
     fn seek_absolute(&self, position: f64) {
         if let Some(rm) = get_rainmeter() {
-            rm.log(rainmeter::RmLogLevel::LogNotice, "Set Position");
-            rm.execute(&format!(
-                "[!CommandMeasure \"MadoWNPTitle\" \"SetPosition {position}\"]"
-            ));
+            send_command(&*rm, "Set Position", &format!("SetPosition {position}"));
         }
     }
 
     fn get_data(&self) -> MusicPlayerState {
+        report_measures_error();
         tick_music_player();
-        return LAST_SONG_INFO
-            .lock()
-            .as_ref()
-            .cloned()
+        return instances::current()
+            .and_then(|instance| instance.music.lock().clone())
             .unwrap_or_else(get_current_song);
     }
 }
 
+/// How often the WebNowPlaying measures are read while a page listens to `MusicUpdate`.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Polls the player for the pages subscribed to `MusicUpdate`. Each skin
+/// reads its own measures, which may be mapped differently.
+pub fn register_provider(scheduler: &Scheduler) {
+    scheduler.register("MusicUpdate", POLL_INTERVAL, |subscribers| {
+        for instance in subscribers.iter().filter_map(|&id| INSTANCES.get(id)) {
+            instances::enter(&instance, || {
+                report_measures_error();
+                tick_music_player();
+            });
+        }
+    });
+}
+
+/// Raises `MusicUpdate` on the page of the current instance when the song
+/// info changed since it was last sent.
 pub fn tick_music_player() {
+    let Some(instance) = instances::current() else {
+        return;
+    };
     let current_song = get_current_song();
-    let mut last_song_info = LAST_SONG_INFO.lock();
+    let mut last_song_info = instance.music.lock();
 
     // If the song info has changed, update it and raise an event
     if *last_song_info != Some(current_song.clone()) {
@@ -82,30 +104,63 @@ pub fn tick_music_player() {
     }
 }
 
+/// Sends `command` to the WebNowPlaying command measure.
+fn send_command(rm: &dyn RmContext, log: &str, command: &str) {
+    rm.log(LogLevel::Notice, log);
+    let measure = WnpMeasures::from_options(rm).command;
+    if let Err(e) = bang::execute(rm, &[Bang::command_measure(&measure, command)]) {
+        rm.log(LogLevel::Error, &e.to_string());
+    }
+}
+
+/// `ErrorData::code` when measures of the WebNowPlaying mapping are missing.
+pub const MISSING_MEASURES: u32 = 1;
+
+/// Checks that the measures the music player reads exist. Missing ones are
+/// logged right away and kept on the current instance, as its page doesn't
+/// exist yet: [`report_measures_error`] raises them as `Event::ERROR` once
+/// the page listens.
+/// A skin without any of them simply doesn't use the music player, unless it
+/// configured the mapping.
+pub fn validate_measures(rm: &dyn RmContext) {
+    let error = missing_measures_error(rm);
+    if let Some(error) = &error {
+        rm.log(LogLevel::Error, &error.message);
+    }
+    if let Some(instance) = instances::current() {
+        *instance.music_error.lock() = error;
+    }
+}
+
+/// Raises the error found by [`validate_measures`] on the page of the
+/// current instance, the first time the page asks for the music player.
+fn report_measures_error() {
+    let error = instances::current().and_then(|instance| instance.music_error.lock().take());
+    if let Some(error) = error {
+        raise_event(Event::ERROR(error));
+    }
+}
+
+fn missing_measures_error(rm: &dyn RmContext) -> Option<ErrorData> {
+    let measures = WnpMeasures::from_options(rm);
+    let missing = measures.missing(rm);
+    let unused = missing.len() == measures.entries().len() && !WnpMeasures::is_customized(rm);
+    if missing.is_empty() || unused {
+        return None;
+    }
+    Some(ErrorData {
+        message: format!(
+            "MusicPlayerService: missing WebNowPlaying measures: {}",
+            missing.join(", ")
+        ),
+        code: MISSING_MEASURES,
+    })
+}
+
 fn get_current_song() -> MusicPlayerState {
-    if let Some(rm) = get_rainmeter() {
-        //rm.log(rainmeter::RmLogLevel::LogNotice, "Getting current song");
-        //rm.execute("[!CommandMeasure \"MadoWNPTitle\" \"GetCurrentSong\"]");
-        return MusicPlayerState {
-            is_connected: rm.read_int("MadoWNPStatus", 0) == 1,
-            player: rm.read_string("MadoWNPPlayer", "No Player"),
-            title: rm.read_string("MadoWNPTitle", ""),
-            artist: rm.read_string("MadoWNPArtist", ""),
-            album: rm.read_string("MadoWNPAlbum", ""),
-            cover: rm.read_string("MadoWNPAlbumCover", ""),
-            duration: rm.read_string("MadoWNPDuration", "00:00"),
-            position: rm.read_string("MadoWNPPosition", "00:00"),
-            progress: rm.read_double("MadoWNPProgress", 0f64),
-
-            volume: rm.read_double("MadoWNPVolume", 0f64),
-            status: match rm.read_int("MadoWNPState", 0) {
-                1 => MusicPlayerStatus::Playing,
-                2 => MusicPlayerStatus::Paused,
-                _ => MusicPlayerStatus::Stopped,
-            },
-        };
-    } else {
-        return MusicPlayerState {
+    match get_rainmeter() {
+        Some(rm) => read_state(&*rm),
+        None => MusicPlayerState {
             is_connected: false,
             player: "No Rainmeter".to_string(),
             title: "".to_string(),
@@ -117,6 +172,187 @@ fn get_current_song() -> MusicPlayerState {
             progress: 0.0,
             volume: 0.0,
             status: MusicPlayerStatus::Stopped,
+        },
+    }
+}
+
+/// Reads the player state from the WebNowPlaying measures.
+fn read_state(rm: &dyn RmContext) -> MusicPlayerState {
+    let measures = WnpMeasures::from_options(rm);
+    let string = |measure: &str, default: &str| {
+        read_measure(rm, measure).unwrap_or_else(|| default.to_string())
+    };
+    let number = |measure: &str| {
+        read_measure(rm, measure)
+            .and_then(|v| v.trim().parse::<f64>().ok())
+            .unwrap_or(0f64)
+    };
+    MusicPlayerState {
+        is_connected: number(&measures.status) == 1.0,
+        player: string(&measures.player, "No Player"),
+        title: string(&measures.title, ""),
+        artist: string(&measures.artist, ""),
+        album: string(&measures.album, ""),
+        cover: covers::cache().proxy(&string(&measures.cover, "")),
+        duration: string(&measures.duration, "00:00"),
+        position: string(&measures.position, "00:00"),
+        progress: number(&measures.progress),
+
+        volume: number(&measures.volume),
+        status: match number(&measures.state) as i32 {
+            1 => MusicPlayerStatus::Playing,
+            2 => MusicPlayerStatus::Paused,
+            _ => MusicPlayerStatus::Stopped,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Command, context::FakeRainmeter};
+    use std::sync::Arc;
+
+    #[test]
+    fn reads_state_from_measures() {
+        let rm = FakeRainmeter::from_ini(
+            "
+            [MadoWNPStatus]
+            Value=1
+            [MadoWNPPlayer]
+            Value=Spotify
+            [MadoWNPTitle]
+            Value=Song
+            [MadoWNPDuration]
+            Value=03:30
+            [MadoWNPProgress]
+            Value=42.5
+            [MadoWNPState]
+            Value=2
+            ",
+        );
+        let state = read_state(&rm);
+        assert!(state.is_connected);
+        assert_eq!(state.player, "Spotify");
+        assert_eq!(state.title, "Song");
+        assert_eq!(state.artist, "");
+        assert_eq!(state.duration, "03:30");
+        assert_eq!(state.position, "00:00");
+        assert_eq!(state.progress, 42.5);
+        assert_eq!(state.status, MusicPlayerStatus::Paused);
+
+        let state = read_state(&FakeRainmeter::new());
+        assert!(!state.is_connected);
+        assert_eq!(state.player, "No Player");
+        assert_eq!(state.status, MusicPlayerStatus::Stopped);
+    }
+
+    #[test]
+    fn reads_mapped_measures() {
+        let rm = FakeRainmeter::from_ini(
+            "
+            [Measure]
+            WNPTitleMeasure=MeasureTitle
+            WNPCommandMeasure=MeasurePlayer
+            [MeasureTitle]
+            Value=Mapped
+            ",
+        );
+        assert_eq!(read_state(&rm).title, "Mapped");
+        send_command(&rm, "Playing music", "Play");
+        assert_eq!(
+            rm.bangs(),
+            vec!["[!CommandMeasure \"MeasurePlayer\" \"Play\"]"]
+        );
+    }
+
+    #[test]
+    fn reports_missing_measures() {
+        // No WebNowPlaying measure at all: the skin doesn't use the music player
+        assert!(missing_measures_error(&FakeRainmeter::new()).is_none());
+
+        let rm = FakeRainmeter::from_ini(
+            "
+            [MadoWNPTitle]
+            Value=Song
+            ",
+        );
+        let error = missing_measures_error(&rm).unwrap();
+        assert_eq!(error.code, MISSING_MEASURES);
+        assert!(
+            error
+                .message
+                .contains("MadoWNPStatus, MadoWNPPlayer, MadoWNPArtist")
+        );
+        assert!(!error.message.contains("MadoWNPTitle"));
+
+        // A configured mapping pointing nowhere is always reported
+        let rm = FakeRainmeter::from_ini(
+            "
+            [Measure]
+            WNPTitleMeasure=MeasureTitle
+            ",
+        );
+        assert!(
+            missing_measures_error(&rm)
+                .unwrap()
+                .message
+                .contains("MeasureTitle")
+        );
+    }
+
+    #[test]
+    fn reports_missing_measures_once_the_page_listens() {
+        let instances = instances::Instances::default();
+        let (tx, rx) = std::sync::mpsc::channel();
+        let rm = Arc::new(FakeRainmeter::from_ini(
+            "
+            [MadoWNPTitle]
+            Value=Song
+            ",
+        ));
+        let instance = instances.register(rm.clone(), tx, 0);
+        let events = || -> Vec<String> {
+            rx.try_iter()
+                .filter_map(|command| match command {
+                    Command::Event(json) => Some(json),
+                    _ => None,
+                })
+                .collect()
         };
+
+        // Measures are checked while the skin loads, before the page exists
+        instances::enter(&instance, || validate_measures(&*rm));
+        assert!(events().is_empty());
+        assert_eq!(rm.logs()[0].0, LogLevel::Error);
+
+        instances::enter(&instance, || service().get_data());
+        let received = events();
+        let errors: Vec<_> = received.iter().filter(|e| e.contains("ERROR")).collect();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("missing WebNowPlaying measures"));
+        assert!(received.iter().any(|e| e.contains("MusicUpdate")));
+
+        // Only once
+        instances::enter(&instance, || service().get_data());
+        assert!(events().iter().all(|e| !e.contains("ERROR")));
+    }
+
+    #[test]
+    fn sends_commands_as_bangs() {
+        let rm = FakeRainmeter::new();
+        send_command(&rm, "Playing music", "Play");
+        send_command(&rm, "Set Volume", "SetVolume 0.5");
+        assert_eq!(
+            rm.bangs(),
+            vec![
+                "[!CommandMeasure \"MadoWNPTitle\" \"Play\"]",
+                "[!CommandMeasure \"MadoWNPTitle\" \"SetVolume 0.5\"]",
+            ]
+        );
+        assert_eq!(
+            rm.logs()[0],
+            (LogLevel::Notice, "Playing music".to_string())
+        );
     }
 }
@@ -0,0 +1,52 @@
+use std::sync::Arc;
+
+use mado::{events::Event, protocol::CommandSpec, services::variable_watch::VariableReader};
+
+use crate::{context::RmContext, instances::Instance};
+
+/// Specs of `host/watch_variable` and `host/unwatch_variable`, watching the
+/// variables of `instance`.
+pub fn command_specs(instance: &Arc<Instance>) -> Vec<CommandSpec> {
+    let rm = instance.rm();
+    let read: Arc<VariableReader> = Arc::new(move |name: &str| read_variable(&*rm, name));
+    instance.variables.command_specs(read)
+}
+
+/// Raises `VariableChanged` for every variable watched by the page of
+/// `instance` that changed since the last update.
+pub fn poll_variables(instance: &Instance) {
+    let rm = instance.rm();
+    for change in instance
+        .variables
+        .poll(|name: &str| read_variable(&*rm, name))
+    {
+        instance.raise_event(&Event::VariableChanged(change));
+    }
+}
+
+/// Value of the variable `name`, given with or without `#`s.
+/// `None` if the skin has no such variable.
+fn read_variable(rm: &dyn RmContext, name: &str) -> Option<String> {
+    let reference = format!("#{}#", name.trim_matches('#'));
+    let value = rm.replace_variables(&reference);
+    (value != reference).then_some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::FakeRainmeter;
+
+    #[test]
+    fn reads_variables_by_name() {
+        let rm = FakeRainmeter::from_ini(
+            "
+            [Variables]
+            Color=255,0,0
+            ",
+        );
+        assert_eq!(read_variable(&rm, "Color").as_deref(), Some("255,0,0"));
+        assert_eq!(read_variable(&rm, "#Color#").as_deref(), Some("255,0,0"));
+        assert_eq!(read_variable(&rm, "Missing"), None);
+    }
+}
@@ -0,0 +1,183 @@
+//! Rainmeter skin generator.
+//!
+//! Writes the `.ini` a Mado skin needs to run in Rainmeter: the `[Shigure]`
+//! plugin measure, the anchor meter it is positioned against and the measures
+//! of the services the skin uses. See `Rainmeter.md` for the layout.
+
+use std::{
+    fmt::Write as _,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::wnp::WnpMeasures;
+
+/// Services a skin can use that need measures in the skin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkinService {
+    /// `MusicPlayerService`, backed by the WebNowPlaying plugin
+    MusicPlayer,
+}
+
+impl SkinService {
+    pub const ALL: [SkinService; 1] = [SkinService::MusicPlayer];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::MusicPlayer => "music_player",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|s| s.name() == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SkinOptions {
+    /// Name of the skin folder and `.ini` file
+    pub name: String,
+    /// Page to load. `#@#` is the `@Resources` folder of the skin.
+    pub url: String,
+    pub width: u32,
+    pub height: u32,
+    /// Rainmeter update interval, in milliseconds
+    pub update: u32,
+    pub services: Vec<SkinService>,
+}
+
+impl Default for SkinOptions {
+    fn default() -> Self {
+        Self {
+            name: "Mado".to_string(),
+            url: "file:///#@#index.html".to_string(),
+            width: 400,
+            height: 300,
+            update: 100,
+            services: Vec::new(),
+        }
+    }
+}
+
+/// Renders the skin `.ini`.
+pub fn render_ini(options: &SkinOptions) -> String {
+    let mut ini = String::new();
+    // Writing to a String can't fail
+    let _ = write_ini(&mut ini, options);
+    ini
+}
+
+fn write_ini(ini: &mut String, options: &SkinOptions) -> std::fmt::Result {
+    let music_player = options.services.contains(&SkinService::MusicPlayer);
+
+    writeln!(ini, "[Rainmeter]")?;
+    writeln!(ini, "Update={}", options.update)?;
+    writeln!(ini)?;
+    writeln!(ini, "[Metadata]")?;
+    writeln!(ini, "Name={}", options.name)?;
+    writeln!(ini, "Information=Mado skin, generated by shigure_skin")?;
+
+    if music_player {
+        writeln!(ini)?;
+        writeln!(ini, ";; Mado: WebNowPlaying Integration")?;
+        for (player_type, measure) in WnpMeasures::default().entries() {
+            writeln!(ini)?;
+            writeln!(ini, "[{measure}]")?;
+            writeln!(ini, "Measure=Plugin")?;
+            writeln!(ini, "Plugin=WebNowPlaying")?;
+            writeln!(ini, "PlayerType={player_type}")?;
+        }
+    }
+
+    writeln!(ini)?;
+    writeln!(ini, ";; Mado: Core")?;
+    writeln!(ini)?;
+    writeln!(ini, "[Shigure]")?;
+    writeln!(ini, "Measure=Plugin")?;
+    writeln!(ini, "Plugin=shigure")?;
+    writeln!(ini, "Url={}", options.url)?;
+    writeln!(ini, "Width={}", options.width)?;
+    writeln!(ini, "Height={}", options.height)?;
+    writeln!(ini, "X=[&Anch:MeterX]")?;
+    writeln!(ini, "Y=[&Anch:MeterY]")?;
+    writeln!(ini, "DynamicVariables=1")?;
+
+    writeln!(ini)?;
+    writeln!(ini, "[Anch]")?;
+    writeln!(ini, "Meter=String")?;
+    writeln!(ini, "W={}", options.width)?;
+    writeln!(ini, "H={}", options.height)?;
+    writeln!(ini, "SolidColor=0,0,0,1")?;
+    Ok(())
+}
+
+const PLACEHOLDER_PAGE: &str = "<!DOCTYPE html>
+<html>
+  <body>
+    <p>Replace @Resources/index.html with your Mado page.</p>
+  </body>
+</html>
+";
+
+/// Writes the skin to `skins_dir/<name>`: the `.ini` and an `@Resources` folder.
+/// A placeholder `index.html` is added if the page is missing and the skin loads it.
+/// Returns the path of the `.ini`.
+pub fn write_skin(options: &SkinOptions, skins_dir: &Path) -> io::Result<PathBuf> {
+    let skin_dir = skins_dir.join(&options.name);
+    let resources = skin_dir.join("@Resources");
+    fs::create_dir_all(&resources)?;
+
+    let index = resources.join("index.html");
+    if options.url == SkinOptions::default().url && !index.exists() {
+        fs::write(&index, PLACEHOLDER_PAGE)?;
+    }
+
+    let ini = skin_dir.join(format!("{}.ini", options.name));
+    fs::write(&ini, render_ini(options))?;
+    Ok(ini)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Compares `actual` with `tests/fixtures/skins/<name>`.
+    /// Set `UPDATE_GOLDEN=1` to rewrite the fixture instead.
+    fn assert_golden(name: &str, actual: &str) {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/skins")
+            .join(name);
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            fs::write(&path, actual).unwrap();
+        }
+        let expected = fs::read_to_string(&path).unwrap().replace("\r\n", "\n");
+        assert_eq!(actual, expected, "{} is out of date", path.display());
+    }
+
+    #[test]
+    fn renders_core_skin() {
+        assert_golden("core.ini", &render_ini(&SkinOptions::default()));
+    }
+
+    #[test]
+    fn renders_music_player_measures() {
+        let options = SkinOptions {
+            name: "Player".to_string(),
+            url: "https://www.example.com".to_string(),
+            width: 640,
+            height: 200,
+            update: 250,
+            services: vec![SkinService::MusicPlayer],
+        };
+        assert_golden("music_player.ini", &render_ini(&options));
+    }
+
+    #[test]
+    fn writes_skin_layout() {
+        let dir = std::env::temp_dir().join(format!("shigure-skin-{}", std::process::id()));
+        let ini = write_skin(&SkinOptions::default(), &dir).unwrap();
+        assert_eq!(ini, dir.join("Mado").join("Mado.ini"));
+        assert!(dir.join("Mado/@Resources/index.html").is_file());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,176 @@
+//! WebNowPlaying measures backing the music player.
+//!
+//! By default the music player reads the `MadoWNP*` measures written by
+//! `shigure_skin`. Skins that already have WebNowPlaying measures point the
+//! music player at them with options of the Shigure measure:
+//! ```ini
+//! [Shigure]
+//! Measure=Plugin
+//! Plugin=shigure
+//! WNPTitleMeasure=MeasureTitle
+//! WNPArtistMeasure=MeasureArtist
+//! ; Measure receiving the player commands, the title measure by default
+//! WNPCommandMeasure=MeasureTitle
+//! ```
+
+use crate::context::RmContext;
+
+/// Names of the measures the music player reads, one per WebNowPlaying `PlayerType`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WnpMeasures {
+    pub status: String,
+    pub player: String,
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub cover: String,
+    pub duration: String,
+    pub position: String,
+    pub progress: String,
+    pub volume: String,
+    pub state: String,
+    /// Measure the player commands are sent to, with `!CommandMeasure`
+    pub command: String,
+}
+
+impl Default for WnpMeasures {
+    fn default() -> Self {
+        Self {
+            status: "MadoWNPStatus".to_string(),
+            player: "MadoWNPPlayer".to_string(),
+            title: "MadoWNPTitle".to_string(),
+            artist: "MadoWNPArtist".to_string(),
+            album: "MadoWNPAlbum".to_string(),
+            cover: "MadoWNPAlbumCover".to_string(),
+            duration: "MadoWNPDuration".to_string(),
+            position: "MadoWNPPosition".to_string(),
+            progress: "MadoWNPProgress".to_string(),
+            volume: "MadoWNPVolume".to_string(),
+            state: "MadoWNPState".to_string(),
+            command: "MadoWNPTitle".to_string(),
+        }
+    }
+}
+
+impl WnpMeasures {
+    /// Reads the mapping from the `WNP<PlayerType>Measure` options of the measure.
+    pub fn from_options(rm: &dyn RmContext) -> Self {
+        let mut measures = Self::default();
+        for (player_type, name) in measures.entries_mut() {
+            *name = rm.read_string(&option_key(player_type), name);
+        }
+        measures.command = rm.read_string(&option_key("Command"), &measures.title);
+        measures
+    }
+
+    /// Whether any of the `WNP<PlayerType>Measure` options is set.
+    pub fn is_customized(rm: &dyn RmContext) -> bool {
+        Self::default()
+            .entries()
+            .into_iter()
+            .map(|(player_type, _)| player_type)
+            .chain(["Command"])
+            .any(|player_type| !rm.read_string(&option_key(player_type), "").is_empty())
+    }
+
+    /// `PlayerType` and name of every value measure.
+    pub fn entries(&self) -> [(&'static str, &str); 11] {
+        [
+            ("Status", &self.status),
+            ("Player", &self.player),
+            ("Title", &self.title),
+            ("Artist", &self.artist),
+            ("Album", &self.album),
+            ("Cover", &self.cover),
+            ("Duration", &self.duration),
+            ("Position", &self.position),
+            ("Progress", &self.progress),
+            ("Volume", &self.volume),
+            ("State", &self.state),
+        ]
+    }
+
+    fn entries_mut(&mut self) -> [(&'static str, &mut String); 11] {
+        [
+            ("Status", &mut self.status),
+            ("Player", &mut self.player),
+            ("Title", &mut self.title),
+            ("Artist", &mut self.artist),
+            ("Album", &mut self.album),
+            ("Cover", &mut self.cover),
+            ("Duration", &mut self.duration),
+            ("Position", &mut self.position),
+            ("Progress", &mut self.progress),
+            ("Volume", &mut self.volume),
+            ("State", &mut self.state),
+        ]
+    }
+
+    /// Measures of the mapping that don't exist in the skin, without duplicates.
+    pub fn missing(&self, rm: &dyn RmContext) -> Vec<String> {
+        let mut missing: Vec<String> = Vec::new();
+        let names = self.entries().map(|(_, name)| name);
+        for name in names.into_iter().chain([self.command.as_str()]) {
+            if read_measure(rm, name).is_none() && !missing.iter().any(|m| m == name) {
+                missing.push(name.to_string());
+            }
+        }
+        missing
+    }
+}
+
+fn option_key(player_type: &str) -> String {
+    format!("WNP{player_type}Measure")
+}
+
+/// String value of the measure `name`, or `None` if there is no such measure.
+pub fn read_measure(rm: &dyn RmContext, name: &str) -> Option<String> {
+    let reference = format!("[&{name}]");
+    let value = rm.replace_variables(&reference);
+    (value != reference).then_some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::FakeRainmeter;
+
+    #[test]
+    fn reads_mapping_from_options() {
+        let rm = FakeRainmeter::from_ini(
+            "
+            [Measure]
+            WNPTitleMeasure=MeasureTitle
+            WNPCoverMeasure=MeasureCover
+            ",
+        );
+        assert!(WnpMeasures::is_customized(&rm));
+        let measures = WnpMeasures::from_options(&rm);
+        assert_eq!(measures.title, "MeasureTitle");
+        assert_eq!(measures.cover, "MeasureCover");
+        assert_eq!(measures.artist, "MadoWNPArtist");
+        // Commands follow the title measure unless mapped on their own
+        assert_eq!(measures.command, "MeasureTitle");
+
+        assert!(!WnpMeasures::is_customized(&FakeRainmeter::new()));
+        let defaults = WnpMeasures::from_options(&FakeRainmeter::new());
+        assert_eq!(defaults, WnpMeasures::default());
+    }
+
+    #[test]
+    fn lists_missing_measures() {
+        let mut ini = String::new();
+        for (_, name) in WnpMeasures::default().entries() {
+            if name != "MadoWNPVolume" {
+                ini.push_str(&format!("[{name}]\nValue=0\n"));
+            }
+        }
+        let rm = FakeRainmeter::from_ini(&ini);
+        assert_eq!(
+            WnpMeasures::default().missing(&rm),
+            vec!["MadoWNPVolume".to_string()]
+        );
+        assert_eq!(read_measure(&rm, "MadoWNPTitle").as_deref(), Some("0"));
+        assert_eq!(read_measure(&rm, "MadoWNPVolume"), None);
+    }
+}
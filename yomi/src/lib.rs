@@ -1,14 +1,8 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
-}
+// Yomi — Mado services for Linux.
+// Services read the kernel interfaces (procfs, sysfs) from a configurable root,
+// so they can be tested against the fixtures in `tests/fixtures`.
+// Yomi is a library only: it has no WebView of its own, so nothing builds these
+// services yet. A Linux host registers them with the `command_specs` and
+// `register_provider` of the matching `mado::services` modules.
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
-    }
-}
+pub mod services;
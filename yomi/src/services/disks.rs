@@ -0,0 +1,305 @@
+//! `DiskService` backed by `/proc/self/mountinfo`.
+//!
+//! Only filesystems mounted from a `/dev` device are listed, once for each
+//! device and btrfs subvolume, so bind mounts of their subdirectories are left
+//! out. Removable media are recognized from the `removable` flag sysfs keeps
+//! for each block device.
+
+use std::{
+    ffi::CString,
+    fs, io,
+    mem::MaybeUninit,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use mado::services::disks::{DiskService, Mount};
+
+/// Sizes in bytes.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct DiskSpace {
+    pub total: u64,
+    pub used: u64,
+    pub available: u64,
+}
+
+/// Reads the space of the filesystem mounted at a path.
+pub type SpaceReader = dyn Fn(&Path) -> io::Result<DiskSpace> + Send + Sync;
+
+pub struct ProcDisks {
+    mountinfo: PathBuf,
+    sys: PathBuf,
+    space: Box<SpaceReader>,
+}
+
+impl Default for ProcDisks {
+    fn default() -> Self {
+        Self::with_paths("/proc/self/mountinfo", "/sys")
+    }
+}
+
+impl ProcDisks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the mount table from `mountinfo` and sysfs from `sys`.
+    pub fn with_paths(mountinfo: impl Into<PathBuf>, sys: impl Into<PathBuf>) -> Self {
+        Self {
+            mountinfo: mountinfo.into(),
+            sys: sys.into(),
+            space: Box::new(statvfs),
+        }
+    }
+
+    /// Reads the space of mounts with `space` instead of `statvfs`.
+    pub fn with_space_reader(
+        mut self,
+        space: impl Fn(&Path) -> io::Result<DiskSpace> + Send + Sync + 'static,
+    ) -> Self {
+        self.space = Box::new(space);
+        self
+    }
+
+    /// Whether the block device `major:minor` is removable. Partitions
+    /// inherit the flag of their disk.
+    fn is_removable(&self, device: &str) -> bool {
+        let flag = |dir: &Path| {
+            fs::read_to_string(dir.join("removable")).is_ok_and(|value| value.trim() == "1")
+        };
+        let dir = self.sys.join("dev/block").join(device);
+        if !dir.join("partition").exists() {
+            return flag(&dir);
+        }
+        fs::canonicalize(&dir)
+            .ok()
+            .and_then(|dir| dir.parent().map(flag))
+            .unwrap_or(false)
+    }
+}
+
+impl DiskService for ProcDisks {
+    fn list_mounts(&self) -> Result<Vec<Mount>, String> {
+        let mountinfo = fs::read_to_string(&self.mountinfo)
+            .map_err(|e| format!("{}: {e}", self.mountinfo.display()))?;
+        let mounts = without_bind_mounts(parse_mountinfo(&mountinfo)?)
+            .into_iter()
+            .filter(|entry| entry.source.starts_with("/dev/"))
+            // Mounts the user can't stat (e.g. other users' FUSE mounts) are left out
+            .filter_map(|entry| {
+                let space = (self.space)(Path::new(&entry.mount_point)).ok()?;
+                Some(Mount {
+                    removable: self.is_removable(&entry.device),
+                    device: entry.source,
+                    mount_point: entry.mount_point,
+                    fs_type: entry.fs_type,
+                    total: space.total,
+                    used: space.used,
+                    available: space.available,
+                })
+            })
+            .collect();
+        Ok(mounts)
+    }
+}
+
+/// Line of `mountinfo`:
+/// `id parent major:minor root mount_point options [optional...] - fs_type source super_options`.
+#[derive(Debug, Clone, PartialEq)]
+struct MountEntry {
+    /// `major:minor` of the device
+    device: String,
+    /// Directory of the filesystem mounted: `/`, the subvolume for btrfs, or
+    /// a directory inside them for bind mounts
+    root: String,
+    mount_point: String,
+    fs_type: String,
+    source: String,
+    /// btrfs subvolume, from the `subvol` super option
+    subvolume: Option<String>,
+}
+
+impl MountEntry {
+    /// Whether the whole filesystem or subvolume is mounted, not one of
+    /// its directories.
+    fn is_whole(&self) -> bool {
+        self.root == self.subvolume.as_deref().unwrap_or("/")
+    }
+}
+
+/// Keeps a single mount of each device and subvolume, the one mounting all of
+/// it if any, else the first one.
+fn without_bind_mounts(entries: Vec<MountEntry>) -> Vec<MountEntry> {
+    let mut kept: Vec<MountEntry> = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let same = kept
+            .iter_mut()
+            .find(|k| k.device == entry.device && k.subvolume == entry.subvolume);
+        match same {
+            Some(same) if !same.is_whole() && entry.is_whole() => *same = entry,
+            Some(_) => {}
+            None => kept.push(entry),
+        }
+    }
+    kept
+}
+
+fn parse_mountinfo(mountinfo: &str) -> Result<Vec<MountEntry>, String> {
+    mountinfo
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let invalid = || format!("mountinfo: invalid line {line:?}");
+            let (mount, filesystem) = line.split_once(" - ").ok_or_else(invalid)?;
+            let mount: Vec<&str> = mount.split_whitespace().collect();
+            let filesystem: Vec<&str> = filesystem.split_whitespace().collect();
+            if mount.len() < 6 || filesystem.len() < 2 {
+                return Err(invalid());
+            }
+            Ok(MountEntry {
+                device: mount[2].to_string(),
+                root: unescape(mount[3]),
+                mount_point: unescape(mount[4]),
+                fs_type: filesystem[0].to_string(),
+                source: unescape(filesystem[1]),
+                subvolume: filesystem.get(2).and_then(|options| {
+                    options
+                        .split(',')
+                        .find_map(|option| option.strip_prefix("subvol="))
+                        .map(unescape)
+                }),
+            })
+        })
+        .collect()
+}
+
+/// Undoes the octal escapes of spaces, tabs, newlines and backslashes (`\040`).
+fn unescape(field: &str) -> String {
+    let mut bytes = Vec::with_capacity(field.len());
+    let mut rest = field.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let code = tail.get(..3).and_then(|digits| {
+            let digits = std::str::from_utf8(digits).ok()?;
+            u8::from_str_radix(digits, 8).ok()
+        });
+        match code {
+            Some(code) if byte == b'\\' => {
+                bytes.push(code);
+                rest = &tail[3..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+// The width of the statvfs fields depends on the platform
+#[allow(clippy::unnecessary_cast)]
+fn statvfs(path: &Path) -> io::Result<DiskSpace> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is a valid C string and `stat` is only read once filled
+    let stat = unsafe {
+        if libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        stat.assume_init()
+    };
+    let block = stat.f_frsize as u64;
+    let total = stat.f_blocks as u64 * block;
+    Ok(DiskSpace {
+        total,
+        used: total.saturating_sub(stat.f_bfree as u64 * block),
+        available: stat.f_bavail as u64 * block,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(path: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(path)
+    }
+
+    fn fixture_disks() -> ProcDisks {
+        ProcDisks::with_paths(fixture("proc/self/mountinfo"), fixture("sys")).with_space_reader(
+            |path| match path.to_str() {
+                Some("/media/sd") => Err(io::Error::from(io::ErrorKind::PermissionDenied)),
+                _ => Ok(DiskSpace {
+                    total: 100,
+                    used: 40,
+                    available: 55,
+                }),
+            },
+        )
+    }
+
+    #[test]
+    fn lists_device_mounts() {
+        let mounts = fixture_disks().list_mounts().unwrap();
+        let points: Vec<&str> = mounts.iter().map(|m| m.mount_point.as_str()).collect();
+        // No pseudo filesystems, no bind mounts of /srv/data and /@home/alice/music,
+        // no unreadable /media/sd
+        assert_eq!(points, vec!["/", "/home", "/var/log", "/media/usb stick"]);
+        assert_eq!(mounts[1].device, "/dev/nvme0n1p3");
+        assert_eq!(mounts[1].fs_type, "btrfs");
+        assert_eq!(mounts[0].device, "/dev/sda1");
+        assert_eq!(mounts[0].fs_type, "ext4");
+        assert!(!mounts[0].removable);
+        assert!(mounts[3].removable);
+        assert_eq!(mounts[3].used, 40);
+    }
+
+    #[test]
+    fn finds_removable_partitions_through_their_disk() {
+        let disks = fixture_disks();
+        assert!(disks.is_removable("179:1"));
+        assert!(!disks.is_removable("8:1"));
+        assert!(!disks.is_removable("9:9"));
+    }
+
+    #[test]
+    fn parses_mountinfo_lines() {
+        let entries = parse_mountinfo(
+            "36 35 98:0 /mnt1 /mnt\\0402 rw,noatime master:1 - ext3 /dev/root rw,errors=continue\n",
+        )
+        .unwrap();
+        assert_eq!(
+            entries,
+            vec![MountEntry {
+                device: "98:0".into(),
+                root: "/mnt1".into(),
+                mount_point: "/mnt 2".into(),
+                fs_type: "ext3".into(),
+                source: "/dev/root".into(),
+                subvolume: None,
+            }]
+        );
+        let entries = parse_mountinfo(
+            "61 28 0:33 /@home /home rw shared:31 - btrfs /dev/sda2 rw,subvolid=257,subvol=/@home",
+        )
+        .unwrap();
+        assert_eq!(entries[0].subvolume.as_deref(), Some("/@home"));
+        assert!(entries[0].is_whole());
+        assert_eq!(unescape("a\\134b\\011c\\x"), "a\\b\tc\\x");
+        assert!(parse_mountinfo("36 35 98:0 / /mnt rw").is_err());
+        assert!(
+            ProcDisks::with_paths("/nonexistent", "/sys")
+                .list_mounts()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn reads_the_space_of_the_root_filesystem() {
+        let space = statvfs(Path::new("/")).unwrap();
+        assert!(space.total > 0);
+        assert!(space.used <= space.total);
+    }
+}
@@ -0,0 +1,37 @@
+pub mod disks;
+pub mod mpd;
+pub mod mpris;
+pub mod network;
+pub mod power;
+pub mod processes;
+pub mod sensors;
+pub mod system_metrics;
+
+use mado::services::music_player::MusicPlayerState;
+
+/// Called by the music players with the new state whenever the selected player changes.
+pub type Listener = dyn Fn(MusicPlayerState) + Send + Sync;
+
+/// `MM:SS`, or `H:MM:SS` from an hour, like WebNowPlaying.
+fn format_time(microseconds: i64) -> String {
+    let seconds = microseconds.max(0) / 1_000_000;
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{hours}:{minutes:02}:{seconds:02}")
+    } else {
+        format!("{minutes:02}:{seconds:02}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_times_like_web_now_playing() {
+        assert_eq!(format_time(0), "00:00");
+        assert_eq!(format_time(205_900_000), "03:25");
+        assert_eq!(format_time(3_723_000_000), "1:02:03");
+        assert_eq!(format_time(-5), "00:00");
+    }
+}
@@ -0,0 +1,899 @@
+//! `MusicPlayerService` for MPD, over its text protocol.
+//!
+//! Commands are sent on one connection, opened again when MPD closed it (it
+//! does after a minute without commands). Changes are waited for with `idle`
+//! on a second connection, only opened once a listener is set with
+//! `on_change`. Covers are read with `albumart` (an image next to the song),
+//! else `readpicture` (an image embedded in it), and given to pages as data
+//! URIs, so nothing is left behind on disk.
+
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Shutdown, TcpStream},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    sync::{
+        Arc, Mutex, Once,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use mado::services::music_player::{MusicPlayerService, MusicPlayerState, MusicPlayerStatus};
+
+use super::{Listener, format_time};
+
+/// Wait before connecting again after MPD went away.
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+/// Largest cover read from MPD, in bytes.
+const MAX_COVER: usize = 16 * 1024 * 1024;
+
+/// Where MPD listens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MpdAddress {
+    /// `host:port`
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+impl MpdAddress {
+    /// Address and password from `MPD_HOST` and `MPD_PORT`, read like `mpc`
+    /// does: `MPD_HOST` is `[password@]host` or a socket path, and MPD is
+    /// looked for at `localhost:6600` unless they are set.
+    pub fn from_env() -> (Self, Option<String>) {
+        let host = std::env::var("MPD_HOST").ok();
+        let port = std::env::var("MPD_PORT").ok();
+        parse_host(host.as_deref(), port.as_deref())
+    }
+}
+
+fn parse_host(host: Option<&str>, port: Option<&str>) -> (MpdAddress, Option<String>) {
+    let host = host.filter(|host| !host.is_empty()).unwrap_or("localhost");
+    // Socket paths may contain '@' themselves
+    let (password, host) = match host.split_once('@') {
+        Some((password, host)) if !password.starts_with('/') => (Some(password.to_string()), host),
+        _ => (None, host),
+    };
+    let address = if host.starts_with('/') {
+        MpdAddress::Unix(host.into())
+    } else {
+        MpdAddress::Tcp(format!("{host}:{}", port.unwrap_or("6600")))
+    };
+    (address, password)
+}
+
+pub struct MpdMusicPlayer {
+    server: Server,
+    /// Connection of the commands, `None` until the first one
+    connection: Mutex<Option<Connection>>,
+    idle: Arc<Idle>,
+}
+
+/// How to reach MPD, shared by both connections.
+#[derive(Clone)]
+struct Server {
+    address: MpdAddress,
+    password: Option<String>,
+    /// Song file of the last cover looked up, and its URL (empty if none)
+    cover: Arc<Mutex<Option<(String, String)>>>,
+}
+
+/// State of the connection waiting for changes.
+struct Idle {
+    started: Once,
+    listener: Mutex<Option<Box<Listener>>>,
+    /// Its stream, shut down to stop waiting when the player is dropped
+    stream: Mutex<Option<Stream>>,
+    closed: AtomicBool,
+}
+
+impl Default for Idle {
+    fn default() -> Self {
+        Self {
+            started: Once::new(),
+            listener: Mutex::default(),
+            stream: Mutex::default(),
+            closed: AtomicBool::default(),
+        }
+    }
+}
+
+impl Default for MpdMusicPlayer {
+    fn default() -> Self {
+        let (address, password) = MpdAddress::from_env();
+        let player = Self::with_address(address);
+        match password {
+            Some(password) => player.with_password(password),
+            None => player,
+        }
+    }
+}
+
+impl MpdMusicPlayer {
+    /// Talks to the MPD of `MPD_HOST` and `MPD_PORT`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_address(address: MpdAddress) -> Self {
+        Self {
+            server: Server {
+                address,
+                password: None,
+                cover: Arc::default(),
+            },
+            connection: Mutex::default(),
+            idle: Arc::default(),
+        }
+    }
+
+    /// Sends `password` on connecting, for servers that restrict commands.
+    pub fn with_password(mut self, password: impl Into<String>) -> Self {
+        self.server.password = Some(password.into());
+        self
+    }
+
+    /// Calls `listener` with the state each time MPD reports a change of the
+    /// player or the volume, from a thread waiting for them. Seeking aside,
+    /// the position moving isn't a change.
+    pub fn on_change(&self, listener: impl Fn(MusicPlayerState) + Send + Sync + 'static) {
+        *self.idle.listener.lock().unwrap() = Some(Box::new(listener));
+        self.idle.started.call_once(|| {
+            let (server, idle) = (self.server.clone(), self.idle.clone());
+            thread::spawn(move || watch(server, idle));
+        });
+    }
+
+    /// Runs `f` on the command connection, connecting again once if MPD
+    /// closed it. `None` if MPD can't be reached or refused.
+    fn run<T>(&self, f: impl Fn(&mut Connection) -> Result<T, Error>) -> Option<T> {
+        let mut connection = self.connection.lock().unwrap();
+        for _ in 0..2 {
+            if connection.is_none() {
+                *connection = Some(self.server.connect().ok()?);
+            }
+            match f(connection.as_mut()?) {
+                Ok(value) => return Some(value),
+                Err(Error::Ack) => return None,
+                Err(Error::Io) => *connection = None,
+            }
+        }
+        None
+    }
+
+    fn command(&self, command: &str) {
+        self.run(|connection| connection.command(command));
+    }
+}
+
+impl Drop for MpdMusicPlayer {
+    fn drop(&mut self) {
+        self.idle.closed.store(true, Ordering::SeqCst);
+        if let Some(stream) = self.idle.stream.lock().unwrap().take() {
+            let _ = stream.shutdown();
+        }
+    }
+}
+
+impl MusicPlayerService for MpdMusicPlayer {
+    fn play(&self) {
+        // `play` would restart a paused song on older servers
+        self.run(|connection| {
+            let status = connection.command("status")?;
+            match field(&status, "state") {
+                Some("pause") => connection.command("pause 0"),
+                _ => connection.command("play"),
+            }
+        });
+    }
+
+    fn pause(&self) {
+        self.command("pause 1");
+    }
+
+    fn next(&self) {
+        self.command("next");
+    }
+
+    fn previous(&self) {
+        self.command("previous");
+    }
+
+    fn set_volume(&self, volume: f64) {
+        let volume = (volume.clamp(0.0, 1.0) * 100.0).round();
+        self.command(&format!("setvol {volume}"));
+    }
+
+    fn seek_absolute(&self, position: f64) {
+        self.run(|connection| {
+            let status = connection.command("status")?;
+            let duration = duration(&status, &[]);
+            let target = position.clamp(0.0, 1.0) * duration;
+            connection.command(&format!("seekcur {target:.3}"))
+        });
+    }
+
+    fn get_data(&self) -> MusicPlayerState {
+        self.run(|connection| self.server.read_state(connection))
+            .unwrap_or_else(MusicPlayerState::disconnected)
+    }
+}
+
+/// Notifies `idle.listener` of the state after each change, until the
+/// player is dropped.
+fn watch(server: Server, idle: Arc<Idle>) {
+    let mut last = None;
+    let mut notify = |state: MusicPlayerState| {
+        if last.as_ref() != Some(&state) && !idle.closed.load(Ordering::SeqCst) {
+            last = Some(state.clone());
+            if let Some(listener) = &*idle.listener.lock().unwrap() {
+                listener(state);
+            }
+        }
+    };
+    while !idle.closed.load(Ordering::SeqCst) {
+        if let Ok(mut connection) = server.connect() {
+            {
+                let mut stream = idle.stream.lock().unwrap();
+                if idle.closed.load(Ordering::SeqCst) {
+                    return;
+                }
+                *stream = connection.writer.try_clone().ok();
+            }
+            while let Ok(state) = server.read_state(&mut connection) {
+                notify(state);
+                if connection.command("idle player mixer").is_err() {
+                    break;
+                }
+            }
+        }
+        notify(MusicPlayerState::disconnected());
+        thread::sleep(RECONNECT_DELAY);
+    }
+}
+
+impl Server {
+    fn connect(&self) -> Result<Connection, Error> {
+        Connection::open(&self.address, self.password.as_deref())
+    }
+
+    fn read_state(&self, connection: &mut Connection) -> Result<MusicPlayerState, Error> {
+        let status = connection.command("status")?;
+        let song = connection.command("currentsong")?;
+        let cover = match field(&song, "file") {
+            Some(file) => self.cover(connection, file)?,
+            None => String::new(),
+        };
+        Ok(state(&status, &song, cover))
+    }
+
+    /// URL of the cover of song `file`, empty if it has none.
+    fn cover(&self, connection: &mut Connection, file: &str) -> Result<String, Error> {
+        if let Some((cached, url)) = &*self.cover.lock().unwrap()
+            && cached == file
+        {
+            return Ok(url.clone());
+        }
+        let mut url = String::new();
+        for command in ["albumart", "readpicture"] {
+            let image = match connection.binary(command, file) {
+                Ok(image) => image,
+                // No image next to the song, or a server before 0.22
+                Err(Error::Ack) => continue,
+                Err(error) => return Err(error),
+            };
+            if !image.is_empty() {
+                url = format!(
+                    "data:{};base64,{}",
+                    mime_type(&image),
+                    STANDARD.encode(&image)
+                );
+                break;
+            }
+        }
+        *self.cover.lock().unwrap() = Some((file.to_string(), url.clone()));
+        Ok(url)
+    }
+}
+
+/// Maps the replies of `status` and `currentsong`.
+fn state(
+    status: &[(String, String)],
+    song: &[(String, String)],
+    cover: String,
+) -> MusicPlayerState {
+    let number = |key| field(status, key).and_then(|value| value.parse::<f64>().ok());
+    // `time` is `elapsed:duration` in whole seconds, all servers before 0.20 send
+    let elapsed = number("elapsed")
+        .or_else(|| field(status, "time")?.split_once(':')?.0.parse().ok())
+        .unwrap_or(0.0);
+    let duration = duration(status, song);
+    let artists: Vec<&str> = song
+        .iter()
+        .filter(|(key, _)| key == "Artist")
+        .map(|(_, value)| value.as_str())
+        .collect();
+    // Radio streams have a name instead of a title
+    let title = field(song, "Title")
+        .or_else(|| field(song, "Name"))
+        .or_else(|| field(song, "file")?.rsplit('/').next())
+        .unwrap_or_default();
+    let microseconds = |seconds: f64| (seconds * 1e6) as i64;
+    MusicPlayerState {
+        is_connected: true,
+        player: "MPD".to_string(),
+        title: title.to_string(),
+        artist: artists.join(", "),
+        album: field(song, "Album").unwrap_or_default().to_string(),
+        cover,
+        duration: format_time(microseconds(duration)),
+        position: format_time(microseconds(elapsed)),
+        progress: if duration > 0.0 {
+            (elapsed / duration).clamp(0.0, 1.0)
+        } else {
+            0.0
+        },
+        // -1 without a mixer
+        volume: (number("volume").unwrap_or(0.0) / 100.0).clamp(0.0, 1.0),
+        status: match field(status, "state") {
+            Some("play") => MusicPlayerStatus::Playing,
+            Some("pause") => MusicPlayerStatus::Paused,
+            _ => MusicPlayerStatus::Stopped,
+        },
+    }
+}
+
+/// Duration of the current song in seconds, 0 for streams.
+fn duration(status: &[(String, String)], song: &[(String, String)]) -> f64 {
+    field(status, "duration")
+        .or_else(|| field(status, "time")?.split_once(':').map(|(_, d)| d))
+        .or_else(|| field(song, "duration"))
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0.0)
+}
+
+fn field<'a>(pairs: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    pairs
+        .iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, value)| value.as_str())
+}
+
+/// MIME type of an image, from its first bytes.
+fn mime_type(image: &[u8]) -> &'static str {
+    match image {
+        [0x89, b'P', b'N', b'G', ..] => "image/png",
+        [0xFF, 0xD8, 0xFF, ..] => "image/jpeg",
+        [b'G', b'I', b'F', b'8', ..] => "image/gif",
+        [
+            b'R',
+            b'I',
+            b'F',
+            b'F',
+            _,
+            _,
+            _,
+            _,
+            b'W',
+            b'E',
+            b'B',
+            b'P',
+            ..,
+        ] => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Quotes a command argument.
+fn quote(argument: &str) -> String {
+    format!(
+        "\"{}\"",
+        argument.replace('\\', "\\\\").replace('"', "\\\"")
+    )
+}
+
+enum Error {
+    /// MPD can't be reached, connecting again may help
+    Io,
+    /// MPD refused the command
+    Ack,
+}
+
+impl From<io::Error> for Error {
+    fn from(_: io::Error) -> Self {
+        Self::Io
+    }
+}
+
+enum Stream {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+impl Stream {
+    fn connect(address: &MpdAddress) -> io::Result<Self> {
+        Ok(match address {
+            MpdAddress::Tcp(address) => Self::Tcp(TcpStream::connect(address)?),
+            MpdAddress::Unix(path) => Self::Unix(UnixStream::connect(path)?),
+        })
+    }
+
+    fn try_clone(&self) -> io::Result<Self> {
+        Ok(match self {
+            Self::Tcp(stream) => Self::Tcp(stream.try_clone()?),
+            Self::Unix(stream) => Self::Unix(stream.try_clone()?),
+        })
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.shutdown(Shutdown::Both),
+            Self::Unix(stream) => stream.shutdown(Shutdown::Both),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Tcp(stream) => stream.read(buf),
+            Self::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Tcp(stream) => stream.write(buf),
+            Self::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.flush(),
+            Self::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+struct Connection {
+    reader: BufReader<Stream>,
+    writer: Stream,
+}
+
+impl Connection {
+    fn open(address: &MpdAddress, password: Option<&str>) -> Result<Self, Error> {
+        let stream = Stream::connect(address)?;
+        let mut connection = Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        };
+        let greeting = connection.read_line()?;
+        if !greeting.starts_with("OK MPD ") {
+            return Err(Error::Io);
+        }
+        if let Some(password) = password {
+            connection.command(&format!("password {}", quote(password)))?;
+        }
+        Ok(connection)
+    }
+
+    fn read_line(&mut self) -> io::Result<String> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(line.trim_end_matches('\n').to_string())
+    }
+
+    fn send(&mut self, command: &str) -> io::Result<()> {
+        self.writer.write_all(format!("{command}\n").as_bytes())
+    }
+
+    /// Next `key: value` line of the reply, `None` at its end.
+    fn reply_line(&mut self) -> Result<Option<(String, String)>, Error> {
+        let line = self.read_line()?;
+        if line == "OK" {
+            return Ok(None);
+        }
+        if line.starts_with("ACK ") {
+            return Err(Error::Ack);
+        }
+        match line.split_once(": ") {
+            Some((key, value)) => Ok(Some((key.to_string(), value.to_string()))),
+            None => Err(Error::Io),
+        }
+    }
+
+    /// Sends `command` and reads its `key: value` reply.
+    fn command(&mut self, command: &str) -> Result<Vec<(String, String)>, Error> {
+        self.send(command)?;
+        let mut pairs = Vec::new();
+        while let Some(pair) = self.reply_line()? {
+            pairs.push(pair);
+        }
+        Ok(pairs)
+    }
+
+    /// Reads the image `command` (`albumart` or `readpicture`) finds for song
+    /// `file`, which MPD sends in chunks. Empty if there is none. Images past
+    /// [`MAX_COVER`] are given up on before being read, leaving the rest of the
+    /// reply unread, so the connection has to be opened again.
+    fn binary(&mut self, command: &str, file: &str) -> Result<Vec<u8>, Error> {
+        let mut data = Vec::new();
+        loop {
+            self.send(&format!("{command} {} {}", quote(file), data.len()))?;
+            let (mut size, mut chunk) = (0, 0);
+            while let Some((key, value)) = self.reply_line()? {
+                match key.as_str() {
+                    "size" => size = value.parse().unwrap_or(0),
+                    "binary" => {
+                        chunk = value.parse().unwrap_or(0);
+                        let start = data.len();
+                        if size > MAX_COVER || chunk > MAX_COVER - start {
+                            return Err(Error::Io);
+                        }
+                        data.resize(start + chunk, 0);
+                        self.reader.read_exact(&mut data[start..])?;
+                        // The chunk ends with a newline
+                        self.read_line()?;
+                    }
+                    _ => {}
+                }
+            }
+            if chunk == 0 || data.len() >= size {
+                return Ok(data);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        fs,
+        net::TcpListener,
+        os::unix::net::UnixListener,
+        sync::{Condvar, mpsc},
+    };
+
+    const COVER: &[u8] = b"\x89PNG\r\n\x1a\nfake album art";
+    const PICTURE: &[u8] = b"\xFF\xD8\xFFfake embedded picture";
+
+    struct Song {
+        tags: &'static str,
+        albumart: Option<&'static [u8]>,
+        picture: Option<&'static [u8]>,
+    }
+
+    static SONGS: [Song; 2] = [
+        Song {
+            tags: "file: music/First.flac\nTitle: First song\nArtist: Artist A\nArtist: Artist B\nAlbum: Album\nduration: 200.000\n",
+            albumart: Some(COVER),
+            picture: None,
+        },
+        Song {
+            tags: "file: music/Second.mp3\nTitle: Second song\nArtist: Artist C\nduration: 125.500\n",
+            albumart: None,
+            picture: Some(PICTURE),
+        },
+    ];
+
+    struct FakeState {
+        playing: bool,
+        song: usize,
+        volume: u32,
+        elapsed: f64,
+        /// Commands changing the state, in order
+        commands: Vec<String>,
+        /// Bumped on each change, for `idle`
+        version: u64,
+    }
+
+    /// MPD stand-in answering the commands the player sends.
+    struct FakeMpd {
+        state: Mutex<FakeState>,
+        changed: Condvar,
+        password: Option<&'static str>,
+        /// Closes connections after this many commands, like MPD does idle ones
+        close_after: Option<usize>,
+    }
+
+    impl FakeMpd {
+        fn new(password: Option<&'static str>, close_after: Option<usize>) -> Arc<Self> {
+            Arc::new(Self {
+                state: Mutex::new(FakeState {
+                    playing: false,
+                    song: 0,
+                    volume: 50,
+                    elapsed: 50.0,
+                    commands: Vec::new(),
+                    version: 0,
+                }),
+                changed: Condvar::new(),
+                password,
+                close_after,
+            })
+        }
+
+        fn commands(&self) -> Vec<String> {
+            self.state.lock().unwrap().commands.clone()
+        }
+
+        fn serve(self: &Arc<Self>, stream: impl Read + Write + Send + 'static) {
+            let mpd = self.clone();
+            thread::spawn(move || {
+                let _ = mpd.talk(stream);
+            });
+        }
+
+        fn talk(&self, stream: impl Read + Write) -> io::Result<()> {
+            let mut stream = BufReader::new(stream);
+            stream.get_mut().write_all(b"OK MPD 0.23.5\n")?;
+            let mut authorized = self.password.is_none();
+            // Version of the last status sent, changes since are reported by `idle`
+            let mut seen = 0;
+            for served in 0.. {
+                if self.close_after == Some(served) {
+                    return Ok(());
+                }
+                let mut line = String::new();
+                if stream.read_line(&mut line)? == 0 {
+                    return Ok(());
+                }
+                let line = line.trim_end();
+                let reply = if let Some(password) = line.strip_prefix("password ") {
+                    authorized = self.password.map(quote).as_deref() == Some(password);
+                    Ok(Vec::new())
+                } else if authorized {
+                    self.reply(line, &mut seen)
+                } else {
+                    Err("[4@0] {} you don't have permission".to_string())
+                };
+                let reply = match reply {
+                    Ok(reply) => [reply, b"OK\n".to_vec()].concat(),
+                    Err(error) => format!("ACK {error}\n").into_bytes(),
+                };
+                stream.get_mut().write_all(&reply)?;
+            }
+            Ok(())
+        }
+
+        fn reply(&self, line: &str, seen: &mut u64) -> Result<Vec<u8>, String> {
+            let (command, args) = line.split_once(' ').unwrap_or((line, ""));
+            let mut state = self.state.lock().unwrap();
+            let song = &SONGS[state.song];
+            let reply = match command {
+                "status" => {
+                    *seen = state.version;
+                    format!(
+                        "volume: {}\nstate: {}\nsong: {}\nelapsed: {:.3}\nduration: {}\n",
+                        state.volume,
+                        if state.playing { "play" } else { "pause" },
+                        state.song,
+                        state.elapsed,
+                        field(&parse_tags(song.tags), "duration").unwrap(),
+                    )
+                    .into_bytes()
+                }
+                "currentsong" => song.tags.as_bytes().to_vec(),
+                "idle" => {
+                    let _state = self
+                        .changed
+                        .wait_while(state, |state| state.version == *seen)
+                        .unwrap();
+                    b"changed: player\n".to_vec()
+                }
+                "albumart" | "readpicture" => {
+                    let (file, offset) = args.rsplit_once(' ').unwrap();
+                    assert_eq!(file, quote(field(&parse_tags(song.tags), "file").unwrap()));
+                    let image = if command == "albumart" {
+                        song.albumart.ok_or("[50@0] {albumart} No file exists")?
+                    } else {
+                        match song.picture {
+                            Some(picture) => picture,
+                            None => return Ok(Vec::new()),
+                        }
+                    };
+                    // Small chunks, so the player has to ask for the rest
+                    let offset: usize = offset.parse().unwrap();
+                    let chunk = &image[offset..image.len().min(offset + 8)];
+                    let header = format!("size: {}\nbinary: {}\n", image.len(), chunk.len());
+                    [header.as_bytes(), chunk, b"\n"].concat()
+                }
+                _ => {
+                    match (command, args) {
+                        ("play", _) | ("pause", "0") => state.playing = true,
+                        ("pause", "1") => state.playing = false,
+                        ("next", _) => (state.song, state.elapsed) = (1, 0.0),
+                        ("previous", _) => (state.song, state.elapsed) = (0, 0.0),
+                        ("setvol", volume) => state.volume = volume.parse().unwrap(),
+                        ("seekcur", position) => state.elapsed = position.parse().unwrap(),
+                        _ => return Err(format!("[5@0] {{}} unknown command \"{command}\"")),
+                    }
+                    state.commands.push(line.to_string());
+                    state.version += 1;
+                    self.changed.notify_all();
+                    Vec::new()
+                }
+            };
+            Ok(reply)
+        }
+    }
+
+    fn parse_tags(tags: &str) -> Vec<(String, String)> {
+        tags.lines()
+            .filter_map(|line| line.split_once(": "))
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("yomi-mpd-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn cover_bytes(state: &MusicPlayerState, mime_type: &str) -> Vec<u8> {
+        let prefix = format!("data:{mime_type};base64,");
+        STANDARD
+            .decode(state.cover.strip_prefix(&prefix).unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn follows_fake_server_over_tcp() {
+        let mpd = FakeMpd::new(None, None);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = mpd.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                server.serve(stream.unwrap());
+            }
+        });
+
+        let player = MpdMusicPlayer::with_address(MpdAddress::Tcp(address));
+        let state = player.get_data();
+        assert!(state.is_connected);
+        assert_eq!(state.player, "MPD");
+        assert_eq!(state.title, "First song");
+        assert_eq!(state.artist, "Artist A, Artist B");
+        assert_eq!(state.album, "Album");
+        assert_eq!(
+            (state.duration.as_str(), state.position.as_str()),
+            ("03:20", "00:50")
+        );
+        assert_eq!(state.progress, 0.25);
+        assert_eq!(state.volume, 0.5);
+        assert_eq!(state.status, MusicPlayerStatus::Paused);
+        assert_eq!(cover_bytes(&state, "image/png"), COVER);
+
+        let (sender, changes) = mpsc::channel();
+        player.on_change(move |state| sender.send(state).unwrap());
+        let next_change = || changes.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(next_change(), state);
+
+        player.play();
+        assert_eq!(next_change().status, MusicPlayerStatus::Playing);
+        player.next();
+        let state = next_change();
+        assert_eq!(state.title, "Second song");
+        // Embedded in the song
+        assert_eq!(cover_bytes(&state, "image/jpeg"), PICTURE);
+
+        player.set_volume(0.8);
+        assert_eq!(next_change().volume, 0.8);
+        player.seek_absolute(0.5);
+        assert_eq!(next_change().position, "01:02");
+        player.pause();
+        assert_eq!(next_change().status, MusicPlayerStatus::Paused);
+        assert_eq!(
+            mpd.commands(),
+            vec!["pause 0", "next", "setvol 80", "seekcur 62.750", "pause 1"]
+        );
+    }
+
+    #[test]
+    fn reconnects_over_unix_socket_with_password() {
+        let dir = temp_dir("unix");
+        fs::create_dir_all(&dir).unwrap();
+        let socket = dir.join("socket");
+        let listener = UnixListener::bind(&socket).unwrap();
+        // Connections closed after seven commands, the password included, so
+        // reading the state has to connect again
+        let mpd = FakeMpd::new(Some("secret"), Some(7));
+        let server = mpd.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                server.serve(stream.unwrap());
+            }
+        });
+
+        let address = MpdAddress::Unix(socket.clone());
+        let refused = MpdMusicPlayer::with_address(address.clone());
+        assert!(!refused.get_data().is_connected);
+
+        let player = MpdMusicPlayer::with_address(address).with_password("secret");
+        player.next();
+        player.play();
+        let state = player.get_data();
+        assert!(state.is_connected);
+        assert_eq!(state.title, "Second song");
+        assert_eq!(state.status, MusicPlayerStatus::Playing);
+        assert_eq!(mpd.commands(), vec!["next", "pause 0"]);
+
+        let gone = MpdMusicPlayer::with_address(MpdAddress::Unix(dir.join("nothing")));
+        assert_eq!(gone.get_data(), MusicPlayerState::disconnected());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn gives_up_on_covers_too_large() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = MpdAddress::Tcp(listener.local_addr().unwrap().to_string());
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut stream = BufReader::new(stream);
+            stream.get_mut().write_all(b"OK MPD 0.23.5\n").unwrap();
+            let mut line = String::new();
+            stream.read_line(&mut line).unwrap();
+            // Claims more than any cover, then sends little of it
+            let header = format!("size: {0}\nbinary: {0}\n", u64::MAX / 2);
+            stream.get_mut().write_all(header.as_bytes()).unwrap();
+            stream.get_mut().write_all(&[0; 64]).unwrap();
+            // Keeps the connection open, for the client to leave first
+            let _ = stream.read_line(&mut line);
+        });
+
+        let mut connection = Connection::open(&address, None).ok().unwrap();
+        let image = connection.binary("albumart", "music/First.flac");
+        assert!(matches!(image, Err(Error::Io)));
+    }
+
+    #[test]
+    fn maps_replies_of_older_servers() {
+        let status = parse_tags("volume: -1\nstate: stop\ntime: 75:3725\n");
+        let song = parse_tags("file: http://radio.example/stream\nName: Radio\n");
+        let radio = state(&status, &song, String::new());
+        assert_eq!(radio.title, "Radio");
+        assert_eq!(radio.artist, "");
+        assert_eq!(
+            (radio.duration.as_str(), radio.position.as_str()),
+            ("1:02:05", "01:15")
+        );
+        assert_eq!(radio.volume, 0.0);
+        assert_eq!(radio.status, MusicPlayerStatus::Stopped);
+
+        let song = parse_tags("file: music/Untagged.ogg\n");
+        assert_eq!(state(&status, &song, String::new()).title, "Untagged.ogg");
+        assert_eq!(quote(r#"a "b" \c"#), r#""a \"b\" \\c""#);
+    }
+
+    #[test]
+    fn reads_hosts_like_mpc() {
+        let tcp = |address: &str| MpdAddress::Tcp(address.to_string());
+        assert_eq!(parse_host(None, None), (tcp("localhost:6600"), None));
+        assert_eq!(
+            parse_host(Some("secret@music.lan"), Some("6601")),
+            (tcp("music.lan:6601"), Some("secret".to_string()))
+        );
+        assert_eq!(
+            parse_host(Some("/run/mpd/socket"), None),
+            (MpdAddress::Unix("/run/mpd/socket".into()), None)
+        );
+        assert_eq!(
+            parse_host(Some("secret@/run/mpd/socket"), None),
+            (
+                MpdAddress::Unix("/run/mpd/socket".into()),
+                Some("secret".to_string())
+            )
+        );
+    }
+}
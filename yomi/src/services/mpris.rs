@@ -0,0 +1,736 @@
+//! `MusicPlayerService` for the MPRIS players of the session bus.
+//!
+//! Players own an `org.mpris.MediaPlayer2.<name>` bus name and export their
+//! state as properties of `/org/mpris/MediaPlayer2`. The state is kept up to
+//! date from their `PropertiesChanged` signals and players coming and going
+//! are noticed from `NameOwnerChanged`, so only the position, which players
+//! don't signal, is read when asked for.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, Weak},
+    thread::{self, JoinHandle},
+};
+
+use mado::services::music_player::{MusicPlayerService, MusicPlayerState, MusicPlayerStatus};
+use zbus::{
+    MatchRule,
+    blocking::{Connection, MessageIterator, fdo::DBusProxy, fdo::PropertiesProxy},
+    export::serde::Serialize,
+    message::Type,
+    names::InterfaceName,
+    zvariant::{DynamicType, ObjectPath, OwnedObjectPath, OwnedValue, Value},
+};
+
+use super::{Listener, format_time};
+
+const NAME_PREFIX: &str = "org.mpris.MediaPlayer2.";
+const PATH: &str = "/org/mpris/MediaPlayer2";
+const ROOT_INTERFACE: &str = "org.mpris.MediaPlayer2";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+
+pub struct MprisMusicPlayer {
+    shared: Arc<Shared>,
+    /// Threads handling the signals, ended by closing the connection
+    listeners: Vec<JoinHandle<()>>,
+}
+
+struct Shared {
+    connection: Connection,
+    /// Players in the order they appeared
+    players: Mutex<Vec<Player>>,
+    listener: Mutex<Option<Box<Listener>>>,
+    /// State last given to the listener
+    notified: Mutex<Option<MusicPlayerState>>,
+}
+
+impl MprisMusicPlayer {
+    /// Follows the players of the session bus.
+    pub fn new() -> zbus::Result<Self> {
+        Self::with_connection(Connection::session()?)
+    }
+
+    /// Follows the players of the bus `connection` is on, closing it when
+    /// dropped.
+    pub fn with_connection(connection: Connection) -> zbus::Result<Self> {
+        let shared = Arc::new(Shared {
+            connection,
+            players: Mutex::default(),
+            listener: Mutex::default(),
+            notified: Mutex::default(),
+        });
+        // Listen before listing, so no player falls between the two
+        let owners = MessageIterator::for_match_rule(
+            MatchRule::builder()
+                .msg_type(Type::Signal)
+                .interface("org.freedesktop.DBus")?
+                .member("NameOwnerChanged")?
+                .arg0ns("org.mpris.MediaPlayer2")?
+                .build(),
+            &shared.connection,
+            None,
+        )?;
+        let properties = MessageIterator::for_match_rule(
+            MatchRule::builder()
+                .msg_type(Type::Signal)
+                .interface("org.freedesktop.DBus.Properties")?
+                .member("PropertiesChanged")?
+                .path(PATH)?
+                .build(),
+            &shared.connection,
+            None,
+        )?;
+        let listeners = vec![
+            spawn_listener(owners, Arc::downgrade(&shared), Shared::on_owner_changed),
+            spawn_listener(
+                properties,
+                Arc::downgrade(&shared),
+                Shared::on_properties_changed,
+            ),
+        ];
+
+        let dbus = DBusProxy::new(&shared.connection)?;
+        for name in dbus.list_names()? {
+            if name.starts_with(NAME_PREFIX) {
+                let owner = dbus.get_name_owner(name.as_ref())?;
+                shared.add_player(name.to_string(), owner.to_string());
+            }
+        }
+        Ok(Self { shared, listeners })
+    }
+
+    /// Calls `listener` with the state of the selected player each time it
+    /// changes, from the thread receiving the D-Bus signals.
+    pub fn on_change(&self, listener: impl Fn(MusicPlayerState) + Send + Sync + 'static) {
+        *self.shared.listener.lock().unwrap() = Some(Box::new(listener));
+    }
+
+    /// Bus names of the players, in the order they appeared.
+    pub fn players(&self) -> Vec<String> {
+        let players = self.shared.players.lock().unwrap();
+        players.iter().map(|p| p.name.clone()).collect()
+    }
+
+    /// Calls `method` of the player interface of the selected player.
+    fn call(&self, method: &str, body: &(impl Serialize + DynamicType)) {
+        let Some(player) = self.shared.selected() else {
+            return;
+        };
+        // Players may refuse, e.g. seeking when `CanSeek` is false
+        let _ = self.shared.connection.call_method(
+            Some(player.name.as_str()),
+            PATH,
+            Some(PLAYER_INTERFACE),
+            method,
+            body,
+        );
+    }
+}
+
+impl MusicPlayerService for MprisMusicPlayer {
+    fn play(&self) {
+        self.call("Play", &());
+    }
+
+    fn pause(&self) {
+        self.call("Pause", &());
+    }
+
+    fn next(&self) {
+        self.call("Next", &());
+    }
+
+    fn previous(&self) {
+        self.call("Previous", &());
+    }
+
+    fn set_volume(&self, volume: f64) {
+        if let Some(player) = self.shared.selected() {
+            let _ = self.shared.properties(&player.name).and_then(|properties| {
+                let interface = InterfaceName::from_static_str_unchecked(PLAYER_INTERFACE);
+                Ok(properties.set(interface, "Volume", Value::from(volume))?)
+            });
+        }
+    }
+
+    fn seek_absolute(&self, position: f64) {
+        let Some(player) = self.shared.selected() else {
+            return;
+        };
+        let target = (position.clamp(0.0, 1.0) * player.length as f64) as i64;
+        match &player.track_id {
+            Some(track_id) => self.call("SetPosition", &(track_id, target)),
+            // Without a track ID, only relative seeking is possible
+            None => self.call("Seek", &(target - self.shared.position(&player.name))),
+        }
+    }
+
+    fn get_data(&self) -> MusicPlayerState {
+        let Some(player) = self.shared.selected() else {
+            return MusicPlayerState::disconnected();
+        };
+        let position = self.shared.position(&player.name);
+        let mut players = self.shared.players.lock().unwrap();
+        match players.iter_mut().find(|p| p.name == player.name) {
+            Some(player) => {
+                player.position = position;
+                player.state()
+            }
+            None => MusicPlayerState::disconnected(),
+        }
+    }
+}
+
+impl Drop for MprisMusicPlayer {
+    fn drop(&mut self) {
+        // The message iterators keep the connection open, and their threads
+        // waiting for a signal, until it is closed
+        let _ = self.shared.connection.clone().close();
+        for listener in self.listeners.drain(..) {
+            let _ = listener.join();
+        }
+    }
+}
+
+/// Handles the messages of `messages` on a thread of its own, until the
+/// connection is closed.
+fn spawn_listener(
+    messages: MessageIterator,
+    shared: Weak<Shared>,
+    handle: fn(&Shared, &zbus::Message),
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        for message in messages {
+            let Some(shared) = shared.upgrade() else {
+                return;
+            };
+            if let Ok(message) = message {
+                handle(&shared, &message);
+                shared.notify();
+            }
+        }
+    })
+}
+
+impl Shared {
+    fn properties(&self, name: &str) -> zbus::Result<PropertiesProxy<'static>> {
+        PropertiesProxy::builder(&self.connection)
+            .destination(name.to_string())?
+            .path(PATH)?
+            .build()
+    }
+
+    /// Reads the state of a player that appeared.
+    fn add_player(&self, name: String, owner: String) {
+        let mut player = Player::new(name, owner);
+        if let Ok(properties) = self.properties(&player.name) {
+            let root = InterfaceName::from_static_str_unchecked(ROOT_INTERFACE);
+            if let Ok(identity) = properties.get(root, "Identity") {
+                player.identity = string(&identity).unwrap_or_default();
+            }
+            let interface = InterfaceName::from_static_str_unchecked(PLAYER_INTERFACE);
+            if let Ok(values) = properties.get_all(interface) {
+                player.apply(&values);
+            }
+        }
+        let mut players = self.players.lock().unwrap();
+        players.retain(|p| p.name != player.name);
+        players.push(player);
+    }
+
+    fn on_owner_changed(&self, message: &zbus::Message) {
+        let Ok((name, _, owner)) = message.body().deserialize::<(String, String, String)>() else {
+            return;
+        };
+        if !name.starts_with(NAME_PREFIX) {
+            return;
+        }
+        if owner.is_empty() {
+            self.players.lock().unwrap().retain(|p| p.name != name);
+        } else {
+            self.add_player(name, owner);
+        }
+    }
+
+    fn on_properties_changed(&self, message: &zbus::Message) {
+        let header = message.header();
+        let Some(sender) = header.sender() else {
+            return;
+        };
+        let Ok((interface, changed, invalidated)) =
+            message
+                .body()
+                .deserialize::<(String, HashMap<String, OwnedValue>, Vec<String>)>()
+        else {
+            return;
+        };
+        if interface != PLAYER_INTERFACE {
+            return;
+        }
+        let name = {
+            let mut players = self.players.lock().unwrap();
+            let Some(player) = players.iter_mut().find(|p| p.owner == sender.as_str()) else {
+                return;
+            };
+            player.apply(&changed);
+            player.name.clone()
+        };
+        // Some players only say a property changed, without its value
+        if !invalidated.is_empty() {
+            let interface = InterfaceName::from_static_str_unchecked(PLAYER_INTERFACE);
+            let values = self
+                .properties(&name)
+                .and_then(|properties| Ok(properties.get_all(interface)?));
+            if let Ok(values) = values {
+                let mut players = self.players.lock().unwrap();
+                if let Some(player) = players.iter_mut().find(|p| p.name == name) {
+                    player.apply(&values);
+                }
+            }
+        }
+    }
+
+    /// The player pages see: the first one playing, else the first one paused,
+    /// else the first one.
+    fn selected(&self) -> Option<Player> {
+        let players = self.players.lock().unwrap();
+        let with_status = |status| players.iter().find(|p| p.status == status);
+        with_status(MusicPlayerStatus::Playing)
+            .or_else(|| with_status(MusicPlayerStatus::Paused))
+            .or_else(|| players.first())
+            .cloned()
+    }
+
+    /// Position of the player in microseconds, which players don't signal.
+    fn position(&self, name: &str) -> i64 {
+        let interface = InterfaceName::from_static_str_unchecked(PLAYER_INTERFACE);
+        self.properties(name)
+            .ok()
+            .and_then(|properties| properties.get(interface, "Position").ok())
+            .and_then(|position| integer(&position))
+            .unwrap_or(0)
+    }
+
+    /// Gives the state of the selected player to the listener if it changed.
+    fn notify(&self) {
+        let state = self
+            .selected()
+            .map_or_else(MusicPlayerState::disconnected, |player| player.state());
+        let mut notified = self.notified.lock().unwrap();
+        if notified.as_ref() == Some(&state) {
+            return;
+        }
+        *notified = Some(state.clone());
+        if let Some(listener) = &*self.listener.lock().unwrap() {
+            listener(state);
+        }
+    }
+}
+
+/// What is known of a player.
+#[derive(Debug, Clone)]
+struct Player {
+    /// Well-known bus name, like `org.mpris.MediaPlayer2.vlc`
+    name: String,
+    /// Unique name of its connection, which sends its signals
+    owner: String,
+    identity: String,
+    status: MusicPlayerStatus,
+    track_id: Option<OwnedObjectPath>,
+    title: String,
+    artist: String,
+    album: String,
+    art_url: String,
+    /// Length of the track and position in it, in microseconds
+    length: i64,
+    position: i64,
+    volume: f64,
+}
+
+impl Player {
+    fn new(name: String, owner: String) -> Self {
+        Self {
+            identity: name.trim_start_matches(NAME_PREFIX).to_string(),
+            name,
+            owner,
+            status: MusicPlayerStatus::Stopped,
+            track_id: None,
+            title: String::new(),
+            artist: String::new(),
+            album: String::new(),
+            art_url: String::new(),
+            length: 0,
+            position: 0,
+            volume: 0.0,
+        }
+    }
+
+    /// Applies the values of player interface properties.
+    fn apply(&mut self, properties: &HashMap<String, OwnedValue>) {
+        for (property, value) in properties {
+            match property.as_str() {
+                "PlaybackStatus" => {
+                    self.status = match string(value).as_deref() {
+                        Some("Playing") => MusicPlayerStatus::Playing,
+                        Some("Paused") => MusicPlayerStatus::Paused,
+                        _ => MusicPlayerStatus::Stopped,
+                    }
+                }
+                "Metadata" => self.apply_metadata(value),
+                "Volume" => self.volume = float(value).unwrap_or(0.0).clamp(0.0, 1.0),
+                "Position" => self.position = integer(value).unwrap_or(0),
+                _ => {}
+            }
+        }
+    }
+
+    /// Applies the `Metadata` property, which replaces the previous one.
+    fn apply_metadata(&mut self, metadata: &Value) {
+        let mut entries = HashMap::new();
+        if let Value::Dict(dict) = unwrap(metadata) {
+            for (key, value) in dict.iter() {
+                if let Some(key) = string(key) {
+                    entries.insert(key, value);
+                }
+            }
+        }
+        let text = |key: &str| entries.get(key).and_then(|v| string(v)).unwrap_or_default();
+        self.track_id = entries
+            .get("mpris:trackid")
+            .and_then(|v| string(v))
+            .and_then(|id| ObjectPath::try_from(id).ok())
+            .map(OwnedObjectPath::from);
+        self.title = text("xesam:title");
+        self.artist = entries
+            .get("xesam:artist")
+            .map(|v| strings(v).join(", "))
+            .unwrap_or_default();
+        self.album = text("xesam:album");
+        self.art_url = text("mpris:artUrl");
+        self.length = entries
+            .get("mpris:length")
+            .and_then(|v| integer(v))
+            .unwrap_or(0);
+    }
+
+    fn state(&self) -> MusicPlayerState {
+        let progress = if self.length > 0 {
+            (self.position as f64 / self.length as f64).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        MusicPlayerState {
+            is_connected: true,
+            player: self.identity.clone(),
+            title: self.title.clone(),
+            artist: self.artist.clone(),
+            album: self.album.clone(),
+            cover: self.art_url.clone(),
+            duration: format_time(self.length),
+            position: format_time(self.position),
+            progress,
+            volume: self.volume,
+            status: self.status.clone(),
+        }
+    }
+}
+
+/// The value inside variants.
+fn unwrap<'a>(value: &'a Value<'a>) -> &'a Value<'a> {
+    match value {
+        Value::Value(inner) => unwrap(inner),
+        value => value,
+    }
+}
+
+fn string(value: &Value) -> Option<String> {
+    match unwrap(value) {
+        Value::Str(s) => Some(s.to_string()),
+        Value::ObjectPath(path) => Some(path.to_string()),
+        _ => None,
+    }
+}
+
+/// A list of strings, which some players send as a single string.
+fn strings(value: &Value) -> Vec<String> {
+    match unwrap(value) {
+        Value::Array(array) => array.inner().iter().filter_map(string).collect(),
+        value => string(value).into_iter().collect(),
+    }
+}
+
+/// Lengths and positions are `x` in the specification, but some players
+/// send other integer types.
+fn integer(value: &Value) -> Option<i64> {
+    match *unwrap(value) {
+        Value::I64(v) => Some(v),
+        Value::U64(v) => i64::try_from(v).ok(),
+        Value::I32(v) => Some(v.into()),
+        Value::U32(v) => Some(v.into()),
+        Value::F64(v) => Some(v as i64),
+        _ => None,
+    }
+}
+
+fn float(value: &Value) -> Option<f64> {
+    match *unwrap(value) {
+        Value::F64(v) => Some(v),
+        _ => integer(value).map(|v| v as f64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader},
+        path::Path,
+        process::{Child, Command, Stdio},
+        sync::mpsc,
+        time::Duration,
+    };
+    use zbus::{interface, object_server::SignalEmitter};
+
+    /// Private session bus, killed when dropped.
+    struct TestBus {
+        daemon: Child,
+        address: String,
+    }
+
+    impl TestBus {
+        /// Private bus, `None` when `dbus-daemon` can't be started.
+        fn start() -> Option<Self> {
+            let config =
+                Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/dbus/session.conf");
+            let mut daemon = Command::new("dbus-daemon")
+                .arg(format!("--config-file={}", config.display()))
+                .args(["--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .ok()?;
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.take()?)
+                .read_line(&mut address)
+                .ok()?;
+            Some(Self {
+                daemon,
+                address: address.trim().to_string(),
+            })
+        }
+
+        fn connect(&self) -> Connection {
+            zbus::blocking::connection::Builder::address(self.address.as_str())
+                .unwrap()
+                .build()
+                .unwrap()
+        }
+
+        /// Connects a stub player owning `org.mpris.MediaPlayer2.<name>`.
+        fn start_player(&self, name: &str, player: StubPlayer) -> Connection {
+            zbus::blocking::connection::Builder::address(self.address.as_str())
+                .unwrap()
+                .name(format!("{NAME_PREFIX}{name}"))
+                .unwrap()
+                .serve_at(PATH, StubRoot(name.to_string()))
+                .unwrap()
+                .serve_at(PATH, player)
+                .unwrap()
+                .build()
+                .unwrap()
+        }
+    }
+
+    impl Drop for TestBus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+        }
+    }
+
+    struct StubRoot(String);
+
+    #[interface(name = "org.mpris.MediaPlayer2")]
+    impl StubRoot {
+        #[zbus(property)]
+        fn identity(&self) -> String {
+            format!("Stub {}", self.0)
+        }
+    }
+
+    struct StubPlayer {
+        status: String,
+        metadata: HashMap<String, OwnedValue>,
+        volume: f64,
+        position: i64,
+        calls: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl StubPlayer {
+        fn new(title: &str, calls: Arc<Mutex<Vec<String>>>) -> Self {
+            let value = |v: Value<'static>| OwnedValue::try_from(v).unwrap();
+            let track_id = ObjectPath::try_from("/org/mpris/MediaPlayer2/Track/1").unwrap();
+            Self {
+                status: "Paused".into(),
+                metadata: HashMap::from([
+                    ("mpris:trackid".into(), value(track_id.into())),
+                    ("mpris:length".into(), value(200_000_000i64.into())),
+                    ("xesam:title".into(), value(title.to_string().into())),
+                    (
+                        "xesam:artist".into(),
+                        value(vec!["Artist A", "Artist B"].into()),
+                    ),
+                    ("xesam:album".into(), value("Album".into())),
+                    ("mpris:artUrl".into(), value("file:///tmp/cover.png".into())),
+                ]),
+                volume: 0.5,
+                position: 50_000_000,
+                calls,
+            }
+        }
+    }
+
+    #[interface(name = "org.mpris.MediaPlayer2.Player")]
+    impl StubPlayer {
+        async fn play(&mut self, #[zbus(signal_emitter)] emitter: SignalEmitter<'_>) {
+            self.calls.lock().unwrap().push("Play".into());
+            self.status = "Playing".into();
+            self.playback_status_changed(&emitter).await.unwrap();
+        }
+
+        fn next(&self) {
+            self.calls.lock().unwrap().push("Next".into());
+        }
+
+        fn set_position(&mut self, track_id: ObjectPath<'_>, position: i64) {
+            let call = format!("SetPosition {track_id} {position}");
+            self.calls.lock().unwrap().push(call);
+            self.position = position;
+        }
+
+        #[zbus(property)]
+        fn playback_status(&self) -> String {
+            self.status.clone()
+        }
+
+        #[zbus(property)]
+        fn metadata(&self) -> HashMap<String, OwnedValue> {
+            self.metadata.clone()
+        }
+
+        #[zbus(property)]
+        fn volume(&self) -> f64 {
+            self.volume
+        }
+
+        #[zbus(property)]
+        fn set_volume(&mut self, volume: f64) {
+            self.volume = volume;
+        }
+
+        #[zbus(property(emits_changed_signal = "false"))]
+        fn position(&self) -> i64 {
+            self.position
+        }
+    }
+
+    #[test]
+    fn follows_stub_players() {
+        let bus = TestBus::start().expect("dbus-daemon is needed to test the MPRIS backend");
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let _first = bus.start_player("first", StubPlayer::new("First song", calls.clone()));
+
+        let mpris = MprisMusicPlayer::with_connection(bus.connect()).unwrap();
+        let (sender, changes) = mpsc::channel();
+        mpris.on_change(move |state| sender.send(state).unwrap());
+        let state = mpris.get_data();
+        assert!(state.is_connected);
+        assert_eq!(state.player, "Stub first");
+        assert_eq!(state.title, "First song");
+        assert_eq!(state.artist, "Artist A, Artist B");
+        assert_eq!(state.cover, "file:///tmp/cover.png");
+        assert_eq!(
+            (state.duration.as_str(), state.position.as_str()),
+            ("03:20", "00:50")
+        );
+        assert_eq!(state.progress, 0.25);
+        assert_eq!(state.status, MusicPlayerStatus::Paused);
+
+        // A second player starting to play is selected
+        let second = bus.start_player("second", StubPlayer::new("Second song", calls.clone()));
+        let state = changes.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(state.title, "First song");
+        assert_eq!(mpris.players().len(), 2);
+        let iface = second
+            .object_server()
+            .interface::<_, StubPlayer>(PATH)
+            .unwrap();
+        zbus::block_on(iface.get_mut().play(iface.signal_emitter().clone()));
+        let state = changes.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(state.title, "Second song");
+        assert_eq!(state.status, MusicPlayerStatus::Playing);
+
+        // Commands go to the selected player
+        mpris.next();
+        mpris.set_volume(0.8);
+        mpris.seek_absolute(0.5);
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                "Play",
+                "Next",
+                "SetPosition /org/mpris/MediaPlayer2/Track/1 100000000"
+            ]
+        );
+        let state = changes.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(state.volume, 0.8);
+
+        // Back to the first player once the second one quits
+        drop(iface);
+        second.close().unwrap();
+        let state = changes.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(state.title, "First song");
+        assert_eq!(mpris.players(), vec!["org.mpris.MediaPlayer2.first"]);
+
+        // Dropping the player ends its threads, without waiting for a signal
+        let (sender, dropped) = mpsc::channel();
+        thread::spawn(move || {
+            drop(mpris);
+            sender.send(()).unwrap();
+        });
+        dropped.recv_timeout(Duration::from_secs(5)).unwrap();
+    }
+
+    #[test]
+    fn reads_metadata_variants() {
+        let mut player = Player::new("org.mpris.MediaPlayer2.vlc".into(), ":1.1".into());
+        assert_eq!(player.identity, "vlc");
+        let metadata = StubPlayer::new("Song", Arc::default()).metadata;
+        let mut metadata = metadata;
+        // Single artist as a string, length as unsigned
+        metadata.insert(
+            "xesam:artist".into(),
+            OwnedValue::try_from(Value::from("Solo")).unwrap(),
+        );
+        metadata.insert(
+            "mpris:length".into(),
+            OwnedValue::try_from(Value::from(60_000_000u64)).unwrap(),
+        );
+        let properties = HashMap::from([
+            (
+                "Metadata".to_string(),
+                OwnedValue::try_from(Value::from(metadata)).unwrap(),
+            ),
+            ("Volume".to_string(), OwnedValue::from(1.5f64)),
+        ]);
+        player.apply(&properties);
+        let state = player.state();
+        assert_eq!(state.artist, "Solo");
+        assert_eq!(state.duration, "01:00");
+        assert_eq!(state.volume, 1.0);
+        assert_eq!(
+            player.track_id.as_ref().map(|id| id.as_str()),
+            Some("/org/mpris/MediaPlayer2/Track/1")
+        );
+    }
+}
@@ -0,0 +1,287 @@
+//! `NetworkService` backed by procfs and sysfs.
+//!
+//! Byte counters come from `net/dev` in procfs, link state and hardware
+//! addresses from `class/net` in sysfs, IP addresses from `getifaddrs`.
+
+use std::{
+    collections::HashMap,
+    ffi::CStr,
+    fs, io,
+    net::{Ipv4Addr, Ipv6Addr},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use mado::{
+    scheduler::{Clock, SystemClock},
+    services::network::{NetworkInterface, NetworkService, NetworkStatus, RateTracker},
+};
+
+/// Lists the IP addresses of each interface.
+pub type AddressReader = dyn Fn() -> io::Result<HashMap<String, Vec<String>>> + Send + Sync;
+
+/// `IFF_UP` and `IFF_LOOPBACK` in the `flags` of an interface.
+const IFF_UP: u32 = 0x1;
+const IFF_LOOPBACK: u32 = 0x8;
+
+pub struct ProcNetwork {
+    proc: PathBuf,
+    sys: PathBuf,
+    clock: Arc<dyn Clock>,
+    addresses: Box<AddressReader>,
+    rates: Mutex<RateTracker>,
+}
+
+impl Default for ProcNetwork {
+    fn default() -> Self {
+        Self::with_roots("/proc", "/sys")
+    }
+}
+
+impl ProcNetwork {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads procfs from `proc` and sysfs from `sys`.
+    pub fn with_roots(proc: impl Into<PathBuf>, sys: impl Into<PathBuf>) -> Self {
+        Self {
+            proc: proc.into(),
+            sys: sys.into(),
+            clock: Arc::new(SystemClock),
+            addresses: Box::new(getifaddrs),
+            rates: Mutex::default(),
+        }
+    }
+
+    /// Times samples with `clock`, for tests.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Lists IP addresses with `addresses` instead of `getifaddrs`.
+    pub fn with_address_reader(
+        mut self,
+        addresses: impl Fn() -> io::Result<HashMap<String, Vec<String>>> + Send + Sync + 'static,
+    ) -> Self {
+        self.addresses = Box::new(addresses);
+        self
+    }
+
+    fn read_sys(&self, interface: &str, file: &str) -> Option<String> {
+        let path = self.sys.join("class/net").join(interface).join(file);
+        fs::read_to_string(path).ok().map(|v| v.trim().to_string())
+    }
+}
+
+impl NetworkService for ProcNetwork {
+    fn get_network(&self) -> Result<NetworkStatus, String> {
+        let path = self.proc.join("net/dev");
+        let dev = fs::read_to_string(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let counters = parse_net_dev(&dev)?;
+        // Addresses are a bonus: interfaces are still listed without them
+        let mut addresses = (self.addresses)().unwrap_or_default();
+
+        let now = self.clock.now();
+        let mut rates = self.rates.lock().unwrap();
+        let names: Vec<&str> = counters.iter().map(|c| c.name.as_str()).collect();
+        rates.retain(&names);
+
+        let interfaces = counters
+            .iter()
+            .map(|c| {
+                let (rx_rate, tx_rate) = rates.update(&c.name, now, c.rx_bytes, c.tx_bytes);
+                let flags = self
+                    .read_sys(&c.name, "flags")
+                    .and_then(|f| u32::from_str_radix(f.trim_start_matches("0x"), 16).ok())
+                    .unwrap_or(0);
+                // Loopback and some virtual interfaces don't report their state
+                let is_up = match self.read_sys(&c.name, "operstate").as_deref() {
+                    Some("up") => true,
+                    Some("unknown") => flags & IFF_UP != 0,
+                    _ => false,
+                };
+                NetworkInterface {
+                    name: c.name.clone(),
+                    is_up,
+                    is_loopback: flags & IFF_LOOPBACK != 0,
+                    mac: self
+                        .read_sys(&c.name, "address")
+                        .filter(|mac| !mac.is_empty() && mac != "00:00:00:00:00:00"),
+                    addresses: addresses.remove(&c.name).unwrap_or_default(),
+                    rx_bytes: c.rx_bytes,
+                    tx_bytes: c.tx_bytes,
+                    rx_rate,
+                    tx_rate,
+                }
+            })
+            .collect();
+        Ok(NetworkStatus::new(interfaces))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Counters {
+    name: String,
+    rx_bytes: u64,
+    tx_bytes: u64,
+}
+
+/// Parses `net/dev`: two header lines, then
+/// `name: rx_bytes rx_packets ... (8 receive fields) tx_bytes tx_packets ...`.
+fn parse_net_dev(dev: &str) -> Result<Vec<Counters>, String> {
+    dev.lines()
+        .skip(2)
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let invalid = || format!("net/dev: invalid line {line:?}");
+            let (name, fields) = line.split_once(':').ok_or_else(invalid)?;
+            let fields: Vec<u64> = fields
+                .split_whitespace()
+                .map(|v| v.parse().map_err(|_| invalid()))
+                .collect::<Result<_, _>>()?;
+            if fields.len() < 9 {
+                return Err(invalid());
+            }
+            Ok(Counters {
+                name: name.trim().to_string(),
+                rx_bytes: fields[0],
+                tx_bytes: fields[8],
+            })
+        })
+        .collect()
+}
+
+fn getifaddrs() -> io::Result<HashMap<String, Vec<String>>> {
+    let mut list = std::ptr::null_mut();
+    // SAFETY: on success `list` points to a linked list freed below
+    if unsafe { libc::getifaddrs(&mut list) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut addresses: HashMap<String, Vec<String>> = HashMap::new();
+    let mut entry = list;
+    while !entry.is_null() {
+        // SAFETY: entries, their names and addresses stay valid until `freeifaddrs`;
+        // the address is read as the type its family announces
+        unsafe {
+            let ifaddr = &*entry;
+            entry = ifaddr.ifa_next;
+            let address = ifaddr.ifa_addr;
+            if address.is_null() {
+                continue;
+            }
+            let ip = match i32::from((*address).sa_family) {
+                libc::AF_INET => {
+                    let address = &*(address as *const libc::sockaddr_in);
+                    Ipv4Addr::from(u32::from_be(address.sin_addr.s_addr)).to_string()
+                }
+                libc::AF_INET6 => {
+                    let address = &*(address as *const libc::sockaddr_in6);
+                    Ipv6Addr::from(address.sin6_addr.s6_addr).to_string()
+                }
+                _ => continue,
+            };
+            let name = CStr::from_ptr(ifaddr.ifa_name)
+                .to_string_lossy()
+                .into_owned();
+            addresses.entry(name).or_default().push(ip);
+        }
+    }
+    // SAFETY: `list` came from `getifaddrs` and isn't used afterwards
+    unsafe { libc::freeifaddrs(list) };
+    Ok(addresses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mado::scheduler::FakeClock;
+    use std::{path::Path, time::Duration};
+
+    fn fixture(path: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(path)
+    }
+
+    fn fake_addresses() -> io::Result<HashMap<String, Vec<String>>> {
+        Ok(HashMap::from([(
+            "wlan0".to_string(),
+            vec!["192.168.1.20".to_string()],
+        )]))
+    }
+
+    #[test]
+    fn reads_fixture_interfaces() {
+        let network = ProcNetwork::with_roots(fixture("proc"), fixture("sys"))
+            .with_address_reader(fake_addresses);
+        let status = network.get_network().unwrap();
+        let names: Vec<&str> = status.interfaces.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(names, vec!["lo", "eth0", "wlan0"]);
+
+        let [lo, eth0, wlan0] = &status.interfaces[..] else {
+            unreachable!()
+        };
+        assert!(lo.is_loopback && lo.is_up);
+        assert_eq!(lo.mac, None);
+        assert!(!eth0.is_up && !eth0.is_loopback);
+        assert_eq!(eth0.tx_bytes, 1782404);
+        assert!(wlan0.is_up);
+        assert_eq!(wlan0.mac.as_deref(), Some("3c:22:fb:12:34:56"));
+        assert_eq!(wlan0.addresses, vec!["192.168.1.20"]);
+        assert_eq!(wlan0.rx_bytes, 987654321);
+        // First sample: no rate yet
+        assert_eq!((status.download, status.upload), (0.0, 0.0));
+    }
+
+    #[test]
+    fn measures_rates_between_samples() {
+        let dir = std::env::temp_dir().join(format!("yomi-network-{}", std::process::id()));
+        fs::create_dir_all(dir.join("net")).unwrap();
+        let clock = Arc::new(FakeClock::new());
+        let network = ProcNetwork::with_roots(&dir, fixture("sys"))
+            .with_clock(clock.clone())
+            .with_address_reader(fake_addresses);
+        let write_dev = |lo: u64, wlan0_rx: u64, wlan0_tx: u64| {
+            let dev = format!(
+                "header\nheader\n    lo: {lo} 0 0 0 0 0 0 0 {lo} 0 0 0 0 0 0 0\n \
+                 wlan0: {wlan0_rx} 0 0 0 0 0 0 0 {wlan0_tx} 0 0 0 0 0 0 0\n"
+            );
+            fs::write(dir.join("net/dev"), dev).unwrap();
+        };
+
+        // A 32 bit counter wrapping between the second and third samples
+        let wrap = u64::from(u32::MAX) - 999;
+        let samples = [(0, wrap - 4000, 0), (500, wrap, 1000), (900, 1000, 3000)];
+        let mut rates = Vec::new();
+        for (lo, rx, tx) in samples {
+            write_dev(lo, rx, tx);
+            let status = network.get_network().unwrap();
+            rates.push((status.download, status.upload));
+            clock.advance(Duration::from_secs(2));
+        }
+        // Loopback traffic isn't counted in the totals
+        assert_eq!(rates, vec![(0.0, 0.0), (2000.0, 500.0), (1000.0, 1000.0)]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_malformed_counters() {
+        assert!(parse_net_dev("h\nh\n  eth0: 1 2 3\n").is_err());
+        assert!(parse_net_dev("h\nh\n  eth0 1 2 3 4 5 6 7 8 9\n").is_err());
+        assert_eq!(parse_net_dev("h\nh\n").unwrap(), vec![]);
+    }
+
+    #[test]
+    fn lists_addresses_of_this_machine() {
+        // Every Linux machine has a loopback interface with an address
+        let addresses = getifaddrs().unwrap();
+        assert!(
+            addresses
+                .values()
+                .flatten()
+                .any(|a| a == "127.0.0.1" || a == "::1")
+        );
+    }
+}
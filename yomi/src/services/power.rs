@@ -0,0 +1,199 @@
+//! `PowerService` backed by the power_supply class of sysfs.
+//!
+//! Batteries report either energy (`energy_*` in µWh, `power_now` in µW) or
+//! charge (`charge_*` in µAh, `current_now` in µA), depending on the driver.
+//! Batteries of peripherals, like wireless mice, are left out.
+
+use std::{fs, path::PathBuf};
+
+use mado::services::power::{Battery, ChargeState, PowerService, PowerStatus};
+
+pub struct SysfsPower {
+    sys: PathBuf,
+}
+
+impl Default for SysfsPower {
+    fn default() -> Self {
+        Self::with_root("/sys")
+    }
+}
+
+impl SysfsPower {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads sysfs from `sys` instead of `/sys`.
+    pub fn with_root(sys: impl Into<PathBuf>) -> Self {
+        Self { sys: sys.into() }
+    }
+}
+
+impl PowerService for SysfsPower {
+    fn get_power(&self) -> Result<PowerStatus, String> {
+        let class = self.sys.join("class/power_supply");
+        let entries = fs::read_dir(&class).map_err(|e| format!("{}: {e}", class.display()))?;
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .collect();
+        names.sort();
+
+        let mut external_online = false;
+        let mut batteries = Vec::new();
+        for name in names {
+            let dir = class.join(&name);
+            let read = |file: &str| {
+                fs::read_to_string(dir.join(file))
+                    .ok()
+                    .map(|value| value.trim().to_string())
+            };
+            if read("scope").as_deref() == Some("Device") {
+                continue;
+            }
+            match read("type").as_deref() {
+                Some("Battery") if read("present").as_deref() != Some("0") => {
+                    let number = |file: &str| read(file)?.parse::<f64>().ok();
+                    batteries.extend(read_battery(name, read("status"), number));
+                }
+                Some("Battery") | None => {}
+                // Mains, USB and wireless chargers
+                Some(_) => external_online |= read("online").as_deref() == Some("1"),
+            }
+        }
+        Ok(PowerStatus {
+            on_ac: external_online || batteries.is_empty(),
+            batteries,
+        })
+    }
+}
+
+/// Builds a battery from its `status` and numeric attributes, `None` if its
+/// charge can't be known.
+fn read_battery(
+    name: String,
+    status: Option<String>,
+    number: impl Fn(&str) -> Option<f64>,
+) -> Option<Battery> {
+    let state = match status.as_deref() {
+        Some("Charging") => ChargeState::Charging,
+        Some("Discharging") => ChargeState::Discharging,
+        Some("Full") => ChargeState::Full,
+        Some("Not charging") => ChargeState::NotCharging,
+        _ => ChargeState::Unknown,
+    };
+    // Energy in µWh with a rate in µW, or charge in µAh with a rate in µA.
+    // Some drivers report a negative rate while discharging, others nothing
+    // but `capacity`, leaving the rate and times unknown.
+    let amounts = match (number("energy_now"), number("energy_full")) {
+        (Some(now), Some(full)) => {
+            let rate = number("power_now").map(f64::abs);
+            Some((now, full, rate, rate.map(|rate| rate / 1e6)))
+        }
+        _ => number("charge_now")
+            .zip(number("charge_full"))
+            .map(|(now, full)| {
+                let rate = number("current_now").map(f64::abs);
+                let volts = number("voltage_now");
+                let watts = rate.zip(volts).map(|(rate, volts)| rate * volts / 1e12);
+                (now, full, rate, watts)
+            }),
+    };
+    let percentage = number("capacity")
+        .or_else(|| {
+            let (now, full, ..) = amounts?;
+            (full > 0.0).then(|| now / full * 100.0)
+        })?
+        .clamp(0.0, 100.0);
+    let (rate, watts) = amounts.map_or((None, None), |(_, _, rate, watts)| (rate, watts));
+    let rate = rate.filter(|&rate| rate > 0.0);
+    let hours_until = |amount: f64| rate.map(|rate| amount / rate * 3600.0);
+    Some(Battery {
+        name,
+        percentage,
+        state,
+        energy_rate: watts.filter(|&watts| watts > 0.0),
+        time_to_empty: match (state, amounts) {
+            (ChargeState::Discharging, Some((now, ..))) => hours_until(now),
+            _ => None,
+        },
+        time_to_full: match (state, amounts) {
+            (ChargeState::Charging, Some((now, full, ..))) => hours_until((full - now).max(0.0)),
+            _ => None,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn reads_fixture_batteries() {
+        let power =
+            SysfsPower::with_root(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/sys"));
+        let status = power.get_power().unwrap();
+        assert!(!status.on_ac);
+        // The mouse battery (hidpp_battery_0) isn't listed
+        let [bat0, bat1] = &status.batteries[..] else {
+            panic!("unexpected batteries {:?}", status.batteries);
+        };
+
+        assert_eq!(bat0.name, "BAT0");
+        assert_eq!(bat0.percentage, 42.0);
+        assert_eq!(bat0.state, ChargeState::Discharging);
+        assert_eq!(bat0.energy_rate, Some(10.5));
+        assert_eq!(bat0.time_to_empty, Some(7200.0));
+        assert_eq!(bat0.time_to_full, None);
+
+        // Charge based, without capacity
+        assert_eq!(bat1.percentage, 50.0);
+        assert_eq!(bat1.state, ChargeState::Charging);
+        assert_eq!(bat1.energy_rate, Some(12.0));
+        assert_eq!(bat1.time_to_full, Some(7200.0));
+        assert!(SysfsPower::with_root("/nonexistent").get_power().is_err());
+    }
+
+    #[test]
+    fn treats_machines_without_batteries_as_plugged() {
+        let sys = std::env::temp_dir().join(format!("yomi-power-{}", std::process::id()));
+        let ac = sys.join("class/power_supply/ACAD");
+        fs::create_dir_all(&ac).unwrap();
+        fs::write(ac.join("type"), "Mains\n").unwrap();
+        fs::write(ac.join("online"), "0\n").unwrap();
+        let status = SysfsPower::with_root(&sys).get_power().unwrap();
+        assert!(status.on_ac);
+        assert!(status.batteries.is_empty());
+        fs::remove_dir_all(&sys).unwrap();
+    }
+
+    #[test]
+    fn reads_whatever_charge_drivers_report() {
+        let number = |file: &str| match file {
+            "power_now" => Some(-5e6),
+            _ => None,
+        };
+        assert_eq!(read_battery("BAT0".into(), None, number), None);
+
+        // ACPI and EC drivers exposing nothing but the capacity
+        let number = |file: &str| match file {
+            "capacity" => Some(64.0),
+            _ => None,
+        };
+        let battery = read_battery("BAT0".into(), Some("Discharging".into()), number).unwrap();
+        assert_eq!(battery.percentage, 64.0);
+        assert_eq!(battery.state, ChargeState::Discharging);
+        assert_eq!(battery.energy_rate, None);
+        assert_eq!(battery.time_to_empty, None);
+
+        let number = |file: &str| match file {
+            "energy_now" => Some(10e6),
+            "energy_full" => Some(40e6),
+            "power_now" => Some(-5e6),
+            _ => None,
+        };
+        let battery = read_battery("BAT0".into(), Some("Discharging".into()), number).unwrap();
+        assert_eq!(battery.percentage, 25.0);
+        assert_eq!(battery.time_to_empty, Some(7200.0));
+    }
+}
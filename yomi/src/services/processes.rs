@@ -0,0 +1,264 @@
+//! `ProcessService` backed by procfs.
+//!
+//! Each `<pid>` directory has the CPU time of the process in `stat`, its
+//! name and resident memory in `status` and its arguments in `cmdline`. CPU
+//! usage compares the ticks of each process to the ticks of all CPUs in
+//! `/proc/stat` since the previous read.
+
+use std::{collections::HashMap, fs, path::PathBuf, sync::Mutex};
+
+use mado::services::processes::{Process, ProcessService};
+
+use super::system_metrics::parse_stat;
+
+/// CPU ticks of the previous read.
+#[derive(Debug, Default)]
+struct Sample {
+    /// Ticks of all CPUs since boot
+    total: u64,
+    /// Start time and ticks of each process by PID, the start time telling
+    /// a reused PID apart
+    processes: HashMap<u32, (u64, u64)>,
+}
+
+pub struct ProcProcesses {
+    root: PathBuf,
+    last: Mutex<Option<Sample>>,
+}
+
+impl Default for ProcProcesses {
+    fn default() -> Self {
+        Self::with_root("/proc")
+    }
+}
+
+impl ProcProcesses {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads procfs from `root` instead of `/proc`.
+    pub fn with_root(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            last: Mutex::default(),
+        }
+    }
+
+    /// Reads a process, `None` if it exited meanwhile.
+    fn read_process(&self, pid: u32) -> Option<(ProcessStat, Process)> {
+        let dir = self.root.join(pid.to_string());
+        let stat = parse_process_stat(&fs::read_to_string(dir.join("stat")).ok()?)?;
+        let status = fs::read_to_string(dir.join("status")).ok()?;
+        let (name, memory) = parse_status(&status);
+        // Kernel threads have no arguments
+        let command_line = fs::read(dir.join("cmdline"))
+            .map(|cmdline| parse_cmdline(&cmdline))
+            .unwrap_or_default();
+        let process = Process {
+            pid,
+            name: name.unwrap_or_else(|| stat.name.clone()),
+            command_line,
+            cpu: 0.0,
+            memory,
+        };
+        Some((stat, process))
+    }
+}
+
+impl ProcessService for ProcProcesses {
+    fn list_processes(&self) -> Result<Vec<Process>, String> {
+        let stat_path = self.root.join("stat");
+        let stat =
+            fs::read_to_string(&stat_path).map_err(|e| format!("{}: {e}", stat_path.display()))?;
+        let total = parse_stat(&stat)?.total.total;
+        let entries =
+            fs::read_dir(&self.root).map_err(|e| format!("{}: {e}", self.root.display()))?;
+        let mut pids: Vec<u32> = entries
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+            .collect();
+        pids.sort();
+
+        let mut last = self.last.lock().unwrap();
+        let mut sample = Sample {
+            total,
+            processes: HashMap::new(),
+        };
+        let processes = pids
+            .into_iter()
+            .filter_map(|pid| self.read_process(pid))
+            .map(|(stat, mut process)| {
+                sample
+                    .processes
+                    .insert(process.pid, (stat.start_time, stat.ticks));
+                if let Some(previous) = last.as_ref() {
+                    // Processes started since the previous read used all their ticks since
+                    let before = match previous.processes.get(&process.pid) {
+                        Some(&(start_time, ticks)) if start_time == stat.start_time => ticks,
+                        _ => 0,
+                    };
+                    let elapsed = total.saturating_sub(previous.total);
+                    if elapsed > 0 {
+                        process.cpu = stat.ticks.saturating_sub(before) as f64 / elapsed as f64;
+                    }
+                }
+                process
+            })
+            .collect();
+        *last = Some(sample);
+        Ok(processes)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ProcessStat {
+    /// Name the kernel knows the process by, cut to 15 bytes
+    name: String,
+    /// User and system time in clock ticks
+    ticks: u64,
+    /// Clock ticks since boot when the process started
+    start_time: u64,
+}
+
+/// Parses `<pid>/stat`: `pid (comm) state ppid ...`, where `comm` can itself
+/// contain spaces and parentheses.
+fn parse_process_stat(stat: &str) -> Option<ProcessStat> {
+    let (head, rest) = stat.rsplit_once(')')?;
+    let (_, name) = head.split_once(" (")?;
+    // Fields after the name, starting at field 3 (state)
+    let fields: Vec<&str> = rest.split_whitespace().collect();
+    let field = |n: usize| fields.get(n - 3)?.parse::<u64>().ok();
+    Some(ProcessStat {
+        name: name.to_string(),
+        ticks: field(14)? + field(15)?,
+        start_time: field(22)?,
+    })
+}
+
+/// Name and resident memory in bytes from `<pid>/status`.
+fn parse_status(status: &str) -> (Option<String>, u64) {
+    let field = |name: &str| {
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+            .map(str::trim)
+    };
+    let memory = field("VmRSS")
+        .and_then(|value| value.trim_end_matches("kB").trim().parse::<u64>().ok())
+        .map_or(0, |kb| kb * 1024);
+    (field("Name").map(str::to_string), memory)
+}
+
+/// Joins the NUL separated arguments of `<pid>/cmdline` with spaces.
+fn parse_cmdline(cmdline: &[u8]) -> String {
+    cmdline
+        .split(|&byte| byte == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(String::from_utf8_lossy)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mado::services::processes::{ProcessSort, TopQuery};
+    use std::path::Path;
+
+    fn fixture_root() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/proc")
+    }
+
+    #[test]
+    fn lists_fixture_processes() {
+        let processes = ProcProcesses::with_root(fixture_root())
+            .list_processes()
+            .unwrap();
+        let pids: Vec<u32> = processes.iter().map(|p| p.pid).collect();
+        assert_eq!(pids, vec![1, 2, 4242]);
+
+        let [systemd, kthreadd, web] = &processes[..] else {
+            unreachable!()
+        };
+        assert_eq!(systemd.command_line, "/sbin/init splash");
+        assert_eq!(systemd.memory, 12952 * 1024);
+        assert_eq!(kthreadd.command_line, "");
+        assert_eq!(kthreadd.memory, 0);
+        assert_eq!(web.name, "Web Content");
+        assert_eq!(
+            web.command_line,
+            "/usr/lib/firefox/firefox -contentproc -childID 1 tab"
+        );
+        // First read: no usage measured yet
+        assert!(processes.iter().all(|p| p.cpu == 0.0));
+    }
+
+    #[test]
+    fn measures_cpu_usage_between_reads() {
+        let root = std::env::temp_dir().join(format!("yomi-processes-{}", std::process::id()));
+        let write = |total: u64, processes: &[(u32, u64, u64)]| {
+            let _ = fs::remove_dir_all(&root);
+            fs::create_dir_all(&root).unwrap();
+            fs::write(
+                root.join("stat"),
+                format!("cpu  {total} 0 0 0 0 0 0 0 0 0\n"),
+            )
+            .unwrap();
+            for &(pid, ticks, start_time) in processes {
+                let dir = root.join(pid.to_string());
+                fs::create_dir(&dir).unwrap();
+                let stat = format!(
+                    "{pid} (worker) R 1 1 1 0 -1 0 0 0 0 0 {ticks} 0 0 0 20 0 1 0 {start_time} 0 0"
+                );
+                fs::write(dir.join("stat"), stat).unwrap();
+                fs::write(
+                    dir.join("status"),
+                    format!("Name:\tworker\nVmRSS:\t{pid} kB\n"),
+                )
+                .unwrap();
+            }
+        };
+        let service = ProcProcesses::with_root(&root);
+
+        write(1000, &[(10, 100, 5), (11, 50, 5), (12, 0, 5)]);
+        service.list_processes().unwrap();
+        // 12 exited and its PID got reused, 13 started
+        write(
+            1400,
+            &[(10, 200, 5), (11, 50, 5), (12, 40, 900), (13, 20, 950)],
+        );
+        let cpu: Vec<f64> = service
+            .list_processes()
+            .unwrap()
+            .iter()
+            .map(|p| p.cpu)
+            .collect();
+        assert_eq!(cpu, vec![0.25, 0.0, 0.1, 0.05]);
+
+        let top = service
+            .top_n(TopQuery {
+                count: 2,
+                sort_by: ProcessSort::Memory,
+            })
+            .unwrap();
+        assert_eq!(top.iter().map(|p| p.pid).collect::<Vec<_>>(), vec![13, 12]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn parses_names_with_parentheses() {
+        let stat =
+            parse_process_stat("77 (a) (b)) S 1 77 77 0 -1 0 0 0 0 0 30 12 0 0 20 0 1 0 4000 0 0")
+                .unwrap();
+        assert_eq!(
+            stat,
+            ProcessStat {
+                name: "a) (b)".into(),
+                ticks: 42,
+                start_time: 4000
+            }
+        );
+        assert_eq!(parse_process_stat("77 (short) S 1"), None);
+        assert_eq!(parse_status("Name:\tbash\n"), (Some("bash".into()), 0));
+    }
+}
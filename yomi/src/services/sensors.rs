@@ -0,0 +1,184 @@
+//! `SensorsService` backed by the hwmon class of sysfs.
+//!
+//! Each `class/hwmon/hwmonN` directory is a chip exposing `<kind><index>_input`
+//! files along with optional `_label`, `_min`, `_max` and `_crit` ones.
+//! Temperatures are in millidegrees and voltages in millivolts.
+
+use std::{fs, path::PathBuf};
+
+use mado::services::sensors::{Sensor, SensorChip, SensorKind, SensorsService};
+
+pub struct SysfsSensors {
+    sys: PathBuf,
+}
+
+impl Default for SysfsSensors {
+    fn default() -> Self {
+        Self::with_root("/sys")
+    }
+}
+
+impl SysfsSensors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads sysfs from `sys` instead of `/sys`.
+    pub fn with_root(sys: impl Into<PathBuf>) -> Self {
+        Self { sys: sys.into() }
+    }
+
+    fn read_chip(&self, id: &str) -> Option<SensorChip> {
+        let mut dir = self.sys.join("class/hwmon").join(id);
+        // Older drivers keep their attributes on the parent device
+        if !dir.join("name").exists() {
+            dir = dir.join("device");
+        }
+        let read = |file: &str| {
+            fs::read_to_string(dir.join(file))
+                .ok()
+                .map(|value| value.trim().to_string())
+        };
+        let name = read("name")?;
+        let mut sensors: Vec<(SensorKind, u32, Sensor)> = fs::read_dir(&dir)
+            .ok()?
+            .filter_map(|entry| {
+                let file = entry.ok()?.file_name().into_string().ok()?;
+                let (kind, index) = parse_input_name(&file)?;
+                let id = file.strip_suffix("_input")?;
+                let value = |suffix: &str| {
+                    let raw: f64 = read(&format!("{id}_{suffix}"))?.parse().ok()?;
+                    Some(scale(kind, raw))
+                };
+                // Some drivers list inputs they fail to read, e.g. for unplugged fans
+                let input = value("input")?;
+                let sensor = Sensor {
+                    id: id.to_string(),
+                    label: read(&format!("{id}_label")).unwrap_or_else(|| id.to_string()),
+                    kind,
+                    input,
+                    min: value("min"),
+                    max: value("max"),
+                    critical: value("crit"),
+                };
+                Some((kind, index, sensor))
+            })
+            .collect();
+        sensors.sort_by_key(|(kind, index, _)| (*kind, *index));
+        Some(SensorChip {
+            id: id.to_string(),
+            name,
+            sensors: sensors.into_iter().map(|(_, _, sensor)| sensor).collect(),
+        })
+    }
+}
+
+impl SensorsService for SysfsSensors {
+    fn list_chips(&self) -> Result<Vec<SensorChip>, String> {
+        let class = self.sys.join("class/hwmon");
+        let entries = fs::read_dir(&class).map_err(|e| format!("{}: {e}", class.display()))?;
+        let mut ids: Vec<(u32, String)> = entries
+            .filter_map(|entry| {
+                let id = entry.ok()?.file_name().into_string().ok()?;
+                Some((id.strip_prefix("hwmon")?.parse().ok()?, id))
+            })
+            .collect();
+        // hwmon10 comes after hwmon2
+        ids.sort();
+        Ok(ids
+            .iter()
+            .filter_map(|(_, id)| self.read_chip(id))
+            .collect())
+    }
+}
+
+/// Kind and index of an input file, like `temp1_input` or `in0_input`.
+fn parse_input_name(file: &str) -> Option<(SensorKind, u32)> {
+    let name = file.strip_suffix("_input")?;
+    let digits = name.find(|c: char| c.is_ascii_digit())?;
+    let kind = match &name[..digits] {
+        "temp" => SensorKind::Temperature,
+        "fan" => SensorKind::Fan,
+        "in" => SensorKind::Voltage,
+        _ => return None,
+    };
+    Some((kind, name[digits..].parse().ok()?))
+}
+
+/// Converts a raw hwmon value to the unit of its kind.
+fn scale(kind: SensorKind, raw: f64) -> f64 {
+    match kind {
+        SensorKind::Temperature | SensorKind::Voltage => raw / 1000.0,
+        SensorKind::Fan => raw,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn fixture_sensors() -> SysfsSensors {
+        SysfsSensors::with_root(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/sys"))
+    }
+
+    #[test]
+    fn lists_fixture_chips() {
+        let chips = fixture_sensors().list_chips().unwrap();
+        let ids: Vec<(&str, &str)> = chips
+            .iter()
+            .map(|c| (c.id.as_str(), c.name.as_str()))
+            .collect();
+        assert_eq!(
+            ids,
+            vec![
+                ("hwmon0", "acpitz"),
+                ("hwmon1", "coretemp"),
+                ("hwmon2", "nct6775"),
+                ("hwmon10", "nvme")
+            ]
+        );
+
+        let acpitz = &chips[0].sensors[0];
+        assert_eq!(acpitz.label, "temp1");
+        assert_eq!(acpitz.input, 27.8);
+        assert_eq!(acpitz.critical, Some(105.0));
+        assert!(chips[1].sensors[1].is_critical());
+
+        // The unreadable temp7 is left out, pwm1 isn't a sensor
+        let nct6775: Vec<(&str, SensorKind, f64)> = chips[2]
+            .sensors
+            .iter()
+            .map(|s| (s.label.as_str(), s.kind, s.input))
+            .collect();
+        assert_eq!(
+            nct6775,
+            vec![
+                ("fan1", SensorKind::Fan, 1185.0),
+                ("SYSFAN", SensorKind::Fan, 0.0),
+                ("Vcore", SensorKind::Voltage, 1.04),
+            ]
+        );
+        assert_eq!(chips[2].sensors[2].max, Some(1.744));
+        assert_eq!(chips[3].sensors[0].min, Some(-273.15));
+    }
+
+    #[test]
+    fn parses_input_names() {
+        assert_eq!(
+            parse_input_name("temp12_input"),
+            Some((SensorKind::Temperature, 12))
+        );
+        assert_eq!(
+            parse_input_name("in0_input"),
+            Some((SensorKind::Voltage, 0))
+        );
+        assert_eq!(parse_input_name("fan1_min"), None);
+        assert_eq!(parse_input_name("curr1_input"), None);
+        assert!(
+            SysfsSensors::with_root("/nonexistent")
+                .list_chips()
+                .is_err()
+        );
+    }
+}
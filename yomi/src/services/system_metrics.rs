@@ -0,0 +1,241 @@
+//! `SystemMetricsService` backed by procfs.
+//!
+//! Reads `stat`, `meminfo`, `loadavg` and `uptime` from the procfs root,
+//! `/proc` unless told otherwise.
+
+use std::{fs, path::PathBuf, sync::Mutex};
+
+use mado::services::system_metrics::{
+    CpuUsage, LoadAverage, MemoryUsage, SystemMetrics, SystemMetricsService,
+};
+
+/// Time a CPU spent since boot, in clock ticks.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(super) struct CpuTimes {
+    idle: u64,
+    pub(super) total: u64,
+}
+
+impl CpuTimes {
+    /// Share of the time between `previous` and `self` the CPU was busy.
+    fn usage_since(self, previous: CpuTimes) -> f64 {
+        let total = self.total.saturating_sub(previous.total);
+        let idle = self.idle.saturating_sub(previous.idle);
+        if total == 0 {
+            return 0.0;
+        }
+        total.saturating_sub(idle) as f64 / total as f64
+    }
+}
+
+/// Aggregate and per-core times of `/proc/stat`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(super) struct CpuStat {
+    pub(super) total: CpuTimes,
+    cores: Vec<CpuTimes>,
+}
+
+pub struct ProcSystemMetrics {
+    root: PathBuf,
+    /// CPU times of the previous read, usage is measured against them
+    last_cpu: Mutex<CpuStat>,
+}
+
+impl Default for ProcSystemMetrics {
+    fn default() -> Self {
+        Self::with_root("/proc")
+    }
+}
+
+impl ProcSystemMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads procfs from `root` instead of `/proc`.
+    pub fn with_root(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            last_cpu: Mutex::default(),
+        }
+    }
+
+    fn read(&self, file: &str) -> Result<String, String> {
+        let path = self.root.join(file);
+        fs::read_to_string(&path).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    fn cpu_usage(&self) -> Result<CpuUsage, String> {
+        let stat = parse_stat(&self.read("stat")?)?;
+        let previous = std::mem::replace(&mut *self.last_cpu.lock().unwrap(), stat.clone());
+        Ok(CpuUsage {
+            total: stat.total.usage_since(previous.total),
+            cores: stat
+                .cores
+                .iter()
+                .enumerate()
+                .map(|(i, core)| {
+                    core.usage_since(previous.cores.get(i).copied().unwrap_or_default())
+                })
+                .collect(),
+        })
+    }
+}
+
+impl SystemMetricsService for ProcSystemMetrics {
+    fn get_metrics(&self) -> Result<SystemMetrics, String> {
+        Ok(SystemMetrics {
+            cpu: self.cpu_usage()?,
+            memory: parse_meminfo(&self.read("meminfo")?)?,
+            load: parse_loadavg(&self.read("loadavg")?)?,
+            uptime: parse_uptime(&self.read("uptime")?)?,
+        })
+    }
+}
+
+/// Parses the `cpu` lines of `/proc/stat`:
+/// `cpu<N> user nice system idle iowait irq softirq steal guest guest_nice`.
+pub(super) fn parse_stat(stat: &str) -> Result<CpuStat, String> {
+    let mut cpu = CpuStat::default();
+    let mut found = false;
+    for line in stat.lines() {
+        let mut fields = line.split_whitespace();
+        let Some(name) = fields.next().and_then(|name| name.strip_prefix("cpu")) else {
+            continue;
+        };
+        let values: Vec<u64> = fields
+            .map(|v| {
+                v.parse()
+                    .map_err(|_| format!("stat: invalid line {line:?}"))
+            })
+            .collect::<Result<_, _>>()?;
+        if values.len() < 4 {
+            return Err(format!("stat: invalid line {line:?}"));
+        }
+        // Guest time is already counted in user time
+        let times = CpuTimes {
+            idle: values[3] + values.get(4).copied().unwrap_or(0),
+            total: values.iter().take(8).sum(),
+        };
+        if name.is_empty() {
+            cpu.total = times;
+            found = true;
+        } else {
+            cpu.cores.push(times);
+        }
+    }
+    if !found {
+        return Err("stat: no cpu line".to_string());
+    }
+    Ok(cpu)
+}
+
+/// Parses `/proc/meminfo`, where sizes are in kB.
+fn parse_meminfo(meminfo: &str) -> Result<MemoryUsage, String> {
+    let field = |name: &str| {
+        meminfo.lines().find_map(|line| {
+            let value = line.strip_prefix(name)?.strip_prefix(':')?;
+            let kb: u64 = value.trim().trim_end_matches("kB").trim().parse().ok()?;
+            Some(kb * 1024)
+        })
+    };
+    let total = field("MemTotal").ok_or("meminfo: no MemTotal")?;
+    // Kernels before 3.14 don't estimate the available memory
+    let available = field("MemAvailable")
+        .or_else(|| Some(field("MemFree")? + field("Buffers")? + field("Cached")?))
+        .ok_or("meminfo: no MemAvailable")?;
+    let swap_total = field("SwapTotal").unwrap_or(0);
+    let swap_free = field("SwapFree").unwrap_or(0);
+    Ok(MemoryUsage {
+        total,
+        used: total.saturating_sub(available),
+        available,
+        swap_total,
+        swap_used: swap_total.saturating_sub(swap_free),
+    })
+}
+
+/// Parses `/proc/loadavg`: `0.52 0.58 0.59 1/467 12345`.
+fn parse_loadavg(loadavg: &str) -> Result<LoadAverage, String> {
+    let values: Vec<f64> = loadavg
+        .split_whitespace()
+        .take(3)
+        .filter_map(|v| v.parse().ok())
+        .collect();
+    match values[..] {
+        [one, five, fifteen] => Ok(LoadAverage { one, five, fifteen }),
+        _ => Err(format!("loadavg: invalid content {loadavg:?}")),
+    }
+}
+
+/// Parses `/proc/uptime`: seconds since boot, then idle seconds.
+fn parse_uptime(uptime: &str) -> Result<f64, String> {
+    uptime
+        .split_whitespace()
+        .next()
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| format!("uptime: invalid content {uptime:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    /// `tests/fixtures/proc`, a snapshot of a two core machine.
+    fn fixture_root() -> &'static Path {
+        Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/proc"))
+    }
+
+    #[test]
+    fn reads_fixture_metrics() {
+        let metrics = ProcSystemMetrics::with_root(fixture_root())
+            .get_metrics()
+            .unwrap();
+        // First read: usage since boot
+        assert_eq!(metrics.cpu.cores.len(), 2);
+        assert!((metrics.cpu.total - 5662.0 / 9384.0).abs() < 1e-9);
+        assert_eq!(metrics.memory.total, 16314248 * 1024);
+        assert_eq!(metrics.memory.available, 10468580 * 1024);
+        assert_eq!(metrics.memory.used, (16314248 - 10468580) * 1024);
+        assert_eq!(metrics.memory.swap_used, (2097148 - 1572860) * 1024);
+        assert_eq!(
+            metrics.load,
+            LoadAverage {
+                one: 0.52,
+                five: 0.58,
+                fifteen: 0.59
+            }
+        );
+        assert_eq!(metrics.uptime, 86400.52);
+        assert!(
+            ProcSystemMetrics::with_root("/nonexistent")
+                .get_metrics()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn measures_cpu_usage_between_reads() {
+        let before =
+            parse_stat("cpu 100 0 100 800 0 0 0 0 0 0\ncpu0 100 0 100 800 0 0 0 0 0 0").unwrap();
+        // 100 more busy ticks, 300 more idle ones
+        let after =
+            parse_stat("cpu 150 0 150 1000 100 0 0 0 0 0\ncpu0 150 0 150 1000 100 0 0 0 0 0")
+                .unwrap();
+        assert_eq!(after.total.usage_since(before.total), 0.25);
+        assert_eq!(after.cores[0].usage_since(after.cores[0]), 0.0);
+        assert!(parse_stat("intr 1 2 3").is_err());
+        assert!(parse_stat("cpu 1 x 3 4").is_err());
+    }
+
+    #[test]
+    fn falls_back_without_mem_available() {
+        let memory =
+            parse_meminfo("MemTotal: 1000 kB\nMemFree: 100 kB\nBuffers: 50 kB\nCached: 250 kB\n")
+                .unwrap();
+        assert_eq!(memory.available, 400 * 1024);
+        assert_eq!(memory.swap_total, 0);
+        assert!(parse_meminfo("MemFree: 100 kB").is_err());
+    }
+}